@@ -1,52 +1,35 @@
+use intcode_computer::geometry::{Grid, Pos};
 use std::fs::read;
 use std::str;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-struct Pos {
-    x: i32,
-    y: i32,
-}
-
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 struct AngleDistance {
     angle: f64,
     distance: f64,
 }
 
-impl Pos {
-    pub fn new(x: i32, y: i32) -> Self {
-        Pos { x, y }
-    }
+// if the normalized difference is the same, the points lay on a line
+fn norm_diff(pos: Pos, other: Pos) -> (i32, i32) {
+    let diff = pos - other;
 
-    // if the normalized difference is the same, the points lay on a line
-    pub fn norm_diff(&self, other: &Self) -> (i32, i32) {
-        let diff = Pos {
-            x: self.x - other.x,
-            y: self.y - other.y,
-        };
+    // we round to 3 decimal places to avoid precision issues
+    let len = ((diff.x * diff.x + diff.y * diff.y) as f64).sqrt();
+    (
+        (diff.x as f64 / len * 1000.).round() as i32,
+        (diff.y as f64 / len * 1000.).round() as i32,
+    )
+}
 
-        // we round to 3 decimal places to avoid precision issues
-        let len = ((diff.x * diff.x + diff.y * diff.y) as f64).sqrt();
-        (
-            (diff.x as f64 / len * 1000.).round() as i32,
-            (diff.y as f64 / len * 1000.).round() as i32,
-        )
-    }
+fn get_angle_distance(pos: Pos, other: Pos) -> AngleDistance {
+    let diff = (pos.x as f64 - other.x as f64, pos.y as f64 - other.y as f64);
 
-    pub fn get_angle_distance(&self, other: Self) -> AngleDistance {
-        let diff = (
-            self.x as f64 - other.x as f64,
-            self.y as f64 - other.y as f64,
-        );
-
-        let mut angle = diff.1.atan2(diff.0);
-        // move last quadrant (-pi3/4..-pi/2) at the end
-        if angle < -std::f64::consts::PI / 2.0 {
-            angle += 2.0 * std::f64::consts::PI;
-        }
-        let distance = (diff.0 * diff.0 + diff.1 * diff.1).sqrt();
-        AngleDistance { angle, distance }
+    let mut angle = diff.1.atan2(diff.0);
+    // move last quadrant (-pi3/4..-pi/2) at the end
+    if angle < -std::f64::consts::PI / 2.0 {
+        angle += 2.0 * std::f64::consts::PI;
     }
+    let distance = (diff.0 * diff.0 + diff.1 * diff.1).sqrt();
+    AngleDistance { angle, distance }
 }
 
 fn get_visible_asteroids(asteroids: &Vec<Pos>) -> Vec<usize> {
@@ -58,7 +41,7 @@ fn get_visible_asteroids(asteroids: &Vec<Pos>) -> Vec<usize> {
                 continue;
             }
 
-            let norm_diff = asteroids[i].norm_diff(&asteroids[j]);
+            let norm_diff = norm_diff(asteroids[i], asteroids[j]);
             if !norms.contains(&norm_diff) {
                 norms.push(norm_diff);
             }
@@ -70,21 +53,16 @@ fn get_visible_asteroids(asteroids: &Vec<Pos>) -> Vec<usize> {
 }
 
 fn get_asteroids(input: &str) -> Vec<Pos> {
-    let mut asteroids = vec![];
-    let mut row = 0;
-    for line in input.lines() {
-        let mut col = 0;
-        for b in line.bytes() {
-            match b {
-                b'#' => asteroids.push(Pos::new(col, row)),
-                b'.' => {}
-                _ => panic!("illegal character"),
-            }
-            col += 1;
-        }
-        row += 1;
-    }
-    asteroids
+    let map = Grid::parse(input, |c| match c {
+        '#' => true,
+        '.' => false,
+        _ => panic!("illegal character"),
+    })
+    .expect("lines differ in length");
+    map.iter()
+        .filter(|(_, asteroid)| **asteroid)
+        .map(|(pos, _)| pos)
+        .collect()
 }
 
 fn get_200th_vaporized(asteroids: Vec<Pos>, battle_station: Pos) -> Pos {
@@ -97,7 +75,7 @@ fn get_200th_vaporized(asteroids: Vec<Pos>, battle_station: Pos) -> Pos {
         .iter()
         .map(|a| Asteroid {
             pos: *a,
-            angle_distance: a.get_angle_distance(battle_station),
+            angle_distance: get_angle_distance(*a, battle_station),
         })
         .collect();
     // sort by distance and angle so that we can just loop in circles afterwards
@@ -1,18 +1,14 @@
-use intcode_computer::{Machine, Pos};
+use intcode_computer::{Machine, Program};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let code = Machine::read_code("input.txt")?;
+    let program = Program::read("input.txt")?;
+    let mut machine = Machine::new(&program);
 
     let mut count = 0;
     for y in 0..50 {
         for x in 0..50 {
-            let mut machine = Machine::new(code.clone());
-            machine.add_input(y);
-            machine.add_input(x);
-            machine.run_until_block();
-            let output = machine.drain_output();
-            assert_eq!(output.len(), 1);
-            count += output[0];
+            machine.reset();
+            count += machine.outputs(vec![y, x]).next().expect("No output");
         }
     }
 
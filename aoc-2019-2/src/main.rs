@@ -1,22 +1,25 @@
-use intcode_computer::Machine;
+use intcode_computer::symbolic::{Executor, Target};
+use intcode_computer::{Machine, RunOutcome};
 
 fn part_1(code: Vec<i64>) {
     // from the puzzle description
     let mut machine = Machine::new(code);
-    println!("result part1: {}", machine.run(12, 2));
+    match machine.run(12, 2) {
+        RunOutcome::Halted(_) => println!("result part1: {}", machine.get_state(0)),
+        RunOutcome::NeedsInput => panic!("Needs input"),
+    }
 }
 
 fn part_2(code: Vec<i64>) {
-    for noun in 0..100 {
-        for verb in 0..100 {
-            let mut machine = Machine::new(code.clone());
-            let result = machine.run(noun, verb);
-            if result == 19690720 {
-                println!("result part2: {}", 100 * noun + verb);
-                return;
-            }
-        }
-    }
+    let mut executor = Executor::new(code);
+    let noun = executor.symbolize_memory(1, 0..100);
+    let verb = executor.symbolize_memory(2, 0..100);
+    let target = Target::Memory {
+        address: 0,
+        value: 19690720,
+    };
+    let solution = executor.solve(target).expect("No solution");
+    println!("result part2: {}", 100 * solution[noun] + solution[verb]);
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -1,95 +1,46 @@
 use geo::Line;
+use intcode_computer::geometry::{Direction, Pos};
 use line_intersection::LineInterval;
 
 use std::fs::File;
 use std::io::prelude::*;
 
-#[derive(Debug, Clone, Copy)]
-pub struct Point {
-    x: i32,
-    y: i32,
-}
-
-impl Point {
-    pub fn distance(&self, other: &Self) -> i32 {
-        (self.x - other.x).abs() + (self.y - other.y).abs()
-    }
-
-    pub fn distance_to_central_port(&self) -> i32 {
-        self.x.abs() + self.y.abs()
-    }
-
-    pub fn add_segment(&self, segment: &Segment) -> Self {
-        match segment {
-            Segment::Up(value) => Point {
-                x: self.x,
-                y: self.y + value,
-            },
-            Segment::Down(value) => Point {
-                x: self.x,
-                y: self.y - value,
-            },
-            Segment::Left(value) => Point {
-                x: self.x - value,
-                y: self.y,
-            },
-            Segment::Right(value) => Point {
-                x: self.x + value,
-                y: self.y,
-            },
-        }
-    }
-}
-
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum Segment {
-    Up(i32),
-    Down(i32),
-    Left(i32),
-    Right(i32),
+pub struct Segment {
+    direction: Direction,
+    length: i64,
 }
 
 impl Segment {
-    pub fn value(&self) -> i32 {
-        match self {
-            Segment::Up(value) => *value,
-            Segment::Down(value) => *value,
-            Segment::Left(value) => *value,
-            Segment::Right(value) => *value,
-        }
+    pub fn end(&self, start: Pos) -> Pos {
+        start + self.direction.delta() * self.length
     }
 }
 
 impl From<&str> for Segment {
     fn from(s: &str) -> Self {
-        let value = i32::from_str_radix(&s[1..], 10).expect("parse error");
-        match s.as_bytes()[0] {
-            b'U' => Segment::Up(value),
-            b'D' => Segment::Down(value),
-            b'L' => Segment::Left(value),
-            b'R' => Segment::Right(value),
-            _ => {
-                panic!("Unexpected token");
-            }
-        }
+        let length = s[1..].parse().expect("parse error");
+        let first = s.chars().next().expect("empty segment");
+        let direction = Direction::from_char(first).expect("Unexpected token");
+        Segment { direction, length }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Path {
-    pub points: Vec<Point>,
-    pub distances: Vec<i32>,
+    pub points: Vec<Pos>,
+    pub distances: Vec<i64>,
 }
 
 impl Path {
     fn new() -> Self {
         Path {
-            points: vec![Point { x: 0, y: 0 }],
+            points: vec![Pos::ORIGIN],
             distances: vec![0],
         }
     }
 
-    fn line_intersection(line1: (&Point, &Point), line2: (&Point, &Point)) -> Option<Point> {
+    fn line_intersection(line1: (&Pos, &Pos), line2: (&Pos, &Pos)) -> Option<Pos> {
         let segment1 = LineInterval::line_segment(Line {
             start: (line1.0.x as f64, line1.0.y as f64).into(),
             end: (line1.1.x as f64, line1.1.y as f64).into(),
@@ -101,39 +52,36 @@ impl Path {
         });
 
         let intersection = segment1.relate(&segment2).unique_intersection();
-        intersection.map(|point| Point {
-            x: point.x() as i32,
-            y: point.y() as i32,
-        })
+        intersection.map(|point| Pos::new(point.x() as i64, point.y() as i64))
     }
 
-    pub fn find_closest_intersection_distance(&self, other: &Self) -> i32 {
-        let mut closest_distance = std::i32::MAX;
+    pub fn find_closest_intersection_distance(&self, other: &Self) -> i64 {
+        let mut closest_distance = i64::MAX;
         for line1 in self.points[1..].iter().zip(self.points[2..].iter()) {
             for line2 in other.points[1..].iter().zip(other.points[2..].iter()) {
                 if let Some(point) = Self::line_intersection(line1, line2) {
-                    let distance = point.distance_to_central_port();
+                    let distance = point.manhattan(Pos::ORIGIN);
                     println!("Found intersection: {:?}: {}", point, distance);
-                    closest_distance = i32::min(closest_distance, distance);
+                    closest_distance = i64::min(closest_distance, distance);
                 }
             }
         }
         closest_distance
     }
 
-    pub fn find_fewest_combined_steps(&self, other: &Self) -> i32 {
-        let mut fewest_combined_steps = std::i32::MAX;
+    pub fn find_fewest_combined_steps(&self, other: &Self) -> i64 {
+        let mut fewest_combined_steps = i64::MAX;
         for i_self in 1..self.points.len() - 1 {
             for i_other in 1..other.points.len() - 1 {
                 let line1 = (&self.points[i_self], &self.points[i_self + 1]);
                 let line2 = (&other.points[i_other], &other.points[i_other + 1]);
                 if let Some(point) = Self::line_intersection(line1, line2) {
                     println!("Found intersection: {:?}", point);
-                    let steps1 = self.distances[i_self] + point.distance(line1.0);
-                    let steps2 = other.distances[i_other] + point.distance(line2.0);
+                    let steps1 = self.distances[i_self] + point.manhattan(*line1.0);
+                    let steps2 = other.distances[i_other] + point.manhattan(*line2.0);
                     let combined_steps = steps1 + steps2;
 
-                    fewest_combined_steps = i32::min(fewest_combined_steps, combined_steps);
+                    fewest_combined_steps = i64::min(fewest_combined_steps, combined_steps);
                 }
             }
         }
@@ -146,8 +94,8 @@ impl From<&str> for Path {
         let mut path = Path::new();
         for s in s.trim().split(',') {
             let segment = Segment::from(s);
-            let distance = path.distances.last().unwrap() + segment.value();
-            let point = path.points.last().unwrap().add_segment(&segment);
+            let distance = path.distances.last().unwrap() + segment.length;
+            let point = segment.end(*path.points.last().unwrap());
             path.points.push(point);
             path.distances.push(distance);
         }
@@ -160,10 +108,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
 
-    let paths = contents
-        .lines()
-        .map(|line| Path::from(line))
-        .collect::<Vec<_>>();
+    let paths = contents.lines().map(Path::from).collect::<Vec<_>>();
 
     // part 1
     let result = paths[0].find_closest_intersection_distance(&paths[1]);
@@ -183,7 +128,13 @@ mod tests {
     #[test]
     fn test_segment_from_slice() {
         let segment = Segment::from("U123");
-        assert_eq!(segment, Segment::Up(123));
+        assert_eq!(
+            segment,
+            Segment {
+                direction: Direction::Up,
+                length: 123
+            }
+        );
     }
 
     #[test]
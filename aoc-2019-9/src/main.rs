@@ -4,16 +4,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let code = Machine::read_code("input.txt")?;
 
     let mut machine = Machine::new(code.clone());
-    let _ = machine.run_with_input(1);
-    let result = machine.drain_output();
+    let result: Vec<i64> = machine.outputs(vec![1]).collect();
 
     println!("result: {:?}", result);
 
     let mut machine = Machine::new(code);
-    let _ = machine.run_with_input(2);
-    let result = machine.drain_output();
+    let result = machine.outputs(vec![2]).next().expect("No output");
 
-    println!("result: {:?}", result);
+    println!("result: {}", result);
 
     Ok(())
 }
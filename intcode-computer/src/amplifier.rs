@@ -0,0 +1,283 @@
+//! Phase settings for chains of amplifiers running the same program, as in day 7.
+//!
+//! Every amplifier first reads its phase and then signals, the first one starts with
+//! [`Options::input`]. In a serial chain each amplifier passes its first output on and the last
+//! one produces the result. With feedback the last amplifier also feeds the first, the result is
+//! the last value it outputs before all amplifiers halted.
+//!
+//! Serial chains are searched amplifier by amplifier. The output of an amplifier only depends on
+//! its phase and input signal, so runs are cached, and prefixes which used the same phases and
+//! end in the same signal have the same continuations, so only one of them is extended. Chains
+//! with feedback depend on all phases at once and every arrangement is run.
+
+use crate::{Machine, Program, RunOutcome};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::rc::Rc;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wiring {
+    Serial,
+    Feedback,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub amplifiers: usize,
+    /// Phase settings to choose from, at most 64.
+    pub phases: Vec<i64>,
+    pub wiring: Wiring,
+    /// Whether amplifiers may share a phase setting.
+    pub repetition: bool,
+    /// Signal for the first amplifier.
+    pub input: i64,
+}
+
+impl Options {
+    /// One amplifier per phase, each phase used once, like the puzzle.
+    pub fn new(phases: Vec<i64>, wiring: Wiring) -> Options {
+        Options {
+            amplifiers: phases.len(),
+            phases,
+            wiring,
+            repetition: false,
+            input: 0,
+        }
+    }
+}
+
+/// The best phase setting of each amplifier with the resulting signal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub phases: Vec<i64>,
+    pub signal: i64,
+    /// Instructions executed by all runs of the search.
+    pub instructions: u64,
+}
+
+/// Returns the setting with the highest signal, the first in the order of
+/// [`Options::phases`] among equal signals. Settings for which an amplifier halts without
+/// output, or a chain with feedback which blocks on input, are skipped. `None` if no setting
+/// produces a signal.
+pub fn best_setting(program: &Program, options: &Options) -> Option<Setting> {
+    assert!(options.phases.len() <= 64, "At most 64 phases");
+    match options.wiring {
+        Wiring::Serial => best_serial(program, options),
+        Wiring::Feedback => {
+            let mut best: Option<(Vec<i64>, i64)> = None;
+            let mut instructions = 0;
+            for indices in arrangements(options) {
+                let phases: Vec<i64> = indices.iter().map(|i| options.phases[*i]).collect();
+                let (signal, executed) = run_feedback(program, &phases, options.input);
+                instructions += executed;
+                match (signal, &best) {
+                    // the first of equal maxima
+                    (Some(signal), Some((_, best))) if *best >= signal => {}
+                    (Some(signal), _) => best = Some((phases, signal)),
+                    (None, _) => {}
+                }
+            }
+            best.map(|(phases, signal)| Setting {
+                phases,
+                signal,
+                instructions,
+            })
+        }
+    }
+}
+
+fn best_serial(program: &Program, options: &Options) -> Option<Setting> {
+    let mut machine = Machine::new(program);
+    let mut cache = HashMap::new();
+    let mut instructions = 0;
+    let mut run = |phase: i64, signal: i64| {
+        *cache.entry((phase, signal)).or_insert_with(|| {
+            machine.reset();
+            let output = machine.outputs(vec![phase, signal]).next();
+            instructions += machine.instructions();
+            output
+        })
+    };
+
+    // the earliest prefix of phase indices for each set of used phases and signal
+    let mut layer: BTreeMap<(u64, i64), Vec<usize>> = BTreeMap::new();
+    layer.insert((0, options.input), vec![]);
+    for _ in 0..options.amplifiers {
+        let mut next: BTreeMap<(u64, i64), Vec<usize>> = BTreeMap::new();
+        for ((used, signal), prefix) in &layer {
+            for (index, phase) in options.phases.iter().enumerate() {
+                let bit = 1 << index;
+                if !options.repetition && used & bit != 0 {
+                    continue;
+                }
+                let output = match run(*phase, *signal) {
+                    Some(output) => output,
+                    None => continue,
+                };
+                let used = if options.repetition { 0 } else { used | bit };
+                let mut extended = prefix.clone();
+                extended.push(index);
+                let entry = next
+                    .entry((used, output))
+                    .or_insert_with(|| extended.clone());
+                if extended < *entry {
+                    *entry = extended;
+                }
+            }
+        }
+        layer = next;
+    }
+
+    let ((_, signal), indices) =
+        layer
+            .into_iter()
+            .max_by(|((_, a), a_indices), ((_, b), b_indices)| {
+                a.cmp(b).then_with(|| b_indices.cmp(a_indices))
+            })?;
+    Some(Setting {
+        phases: indices.iter().map(|i| options.phases[*i]).collect(),
+        signal,
+        instructions,
+    })
+}
+
+/// All assignments of phase indices to amplifiers in lexicographic order.
+fn arrangements(options: &Options) -> Vec<Vec<usize>> {
+    fn extend(options: &Options, prefix: &mut Vec<usize>, all: &mut Vec<Vec<usize>>) {
+        if prefix.len() == options.amplifiers {
+            all.push(prefix.clone());
+            return;
+        }
+        for index in 0..options.phases.len() {
+            if options.repetition || !prefix.contains(&index) {
+                prefix.push(index);
+                extend(options, prefix, all);
+                prefix.pop();
+            }
+        }
+    }
+    let mut all = vec![];
+    extend(options, &mut vec![], &mut all);
+    all
+}
+
+/// The signal of a chain with feedback, `None` if it blocks, with the executed instructions.
+fn run_feedback(program: &Program, phases: &[i64], input: i64) -> (Option<i64>, u64) {
+    let channels: Vec<_> = phases
+        .iter()
+        .map(|phase| Rc::new(RefCell::new(VecDeque::from(vec![*phase]))))
+        .collect();
+    match channels.first() {
+        Some(channel) => channel.borrow_mut().push_back(input),
+        None => return (None, 0),
+    }
+    // the last amplifier writes to its own channel, its outputs are the results and move on to
+    // the first one between runs, also when that is the same amplifier
+    let results = Rc::new(RefCell::new(VecDeque::new()));
+    let mut amplifiers: Vec<Machine> = (0..phases.len())
+        .map(|i| {
+            let output = match channels.get(i + 1) {
+                Some(channel) => channel.clone(),
+                None => results.clone(),
+            };
+            Machine::new_with_in_out(program, channels[i].clone(), output)
+        })
+        .collect();
+    let mut halted = vec![false; phases.len()];
+    let mut last = None;
+    while halted.iter().any(|halted| !halted) {
+        let mut progress = false;
+        for (i, amplifier) in amplifiers.iter_mut().enumerate() {
+            if halted[i] {
+                continue;
+            }
+            let before = amplifier.instructions();
+            if let RunOutcome::Halted(_) = amplifier.run_until_block() {
+                halted[i] = true;
+            }
+            progress |= amplifier.instructions() > before;
+            for value in results.borrow_mut().drain(..) {
+                channels[0].borrow_mut().push_back(value);
+                last = Some(value);
+            }
+        }
+        if !progress {
+            last = None;
+            break;
+        }
+    }
+    (last, amplifiers.iter().map(Machine::instructions).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial() {
+        // signal * 10 + phase
+        let program = Program::new(Machine::parse_code(
+            "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0",
+        ));
+        let options = Options::new(vec![0, 1, 2, 3, 4], Wiring::Serial);
+        let best = best_setting(&program, &options).unwrap();
+        assert_eq!(best.phases, vec![4, 3, 2, 1, 0]);
+        assert_eq!(best.signal, 43210);
+
+        let options = Options {
+            amplifiers: 3,
+            repetition: true,
+            input: 7,
+            ..options
+        };
+        assert_eq!(best_setting(&program, &options).unwrap().signal, 7444);
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // signal + phase, 10^12 settings of which only the sums matter
+        let program = Program::new(Machine::parse_code("3,11,3,12,1,11,12,12,4,12,99,0,0"));
+        let options = Options {
+            amplifiers: 12,
+            repetition: true,
+            ..Options::new((0..10).collect(), Wiring::Serial)
+        };
+        let best = best_setting(&program, &options).unwrap();
+        assert_eq!(best.phases, vec![9; 12]);
+        assert_eq!(best.signal, 108);
+    }
+
+    #[test]
+    fn test_feedback() {
+        let program = Program::new(Machine::parse_code(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,\
+             27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        ));
+        let options = Options::new(vec![5, 6, 7, 8, 9], Wiring::Feedback);
+        let best = best_setting(&program, &options).unwrap();
+        assert_eq!(best.phases, vec![9, 8, 7, 6, 5]);
+        assert_eq!(best.signal, 139629729);
+
+        // reads its phase and signal and halts without output
+        let program = Program::new(Machine::parse_code("3,0,3,0,99"));
+        assert_eq!(best_setting(&program, &options), None);
+        let options = Options::new(vec![0, 1], Wiring::Serial);
+        assert_eq!(best_setting(&program, &options), None);
+    }
+
+    #[test]
+    fn test_single_feedback_amplifier() {
+        // reads its phase as a count, then doubles the signal that many times feeding itself
+        let program = Program::new(Machine::parse_code(
+            "3,20,3,21,1002,21,2,21,4,21,1001,20,-1,20,1005,20,2,99,0,0,0,0",
+        ));
+        let options = Options {
+            input: 1,
+            ..Options::new(vec![3], Wiring::Feedback)
+        };
+        let best = best_setting(&program, &options).unwrap();
+        assert_eq!(best.phases, vec![3]);
+        assert_eq!(best.signal, 8);
+        assert_eq!(run_feedback(&program, &[3], 5).0, Some(40));
+    }
+}
@@ -0,0 +1,161 @@
+//! Static control flow analysis of program images.
+//!
+//! Instructions are decoded by following every path from the entry point. Jumps through memory
+//! are resolved with the initial content of the image, which is only a guess for programs that
+//! modify themselves.
+
+use crate::decode::{decode, DecodeError, Instruction, Opcode, Param};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Jump {
+    None,
+    Static(usize),
+    /// The target comes from the relative base or is negative.
+    Dynamic,
+}
+
+/// Where execution continues after an instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Successors {
+    pub falls_through: bool,
+    pub jump: Jump,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    /// Instructions reachable from address 0.
+    pub instructions: BTreeMap<usize, Instruction>,
+    /// Reachable addresses which do not hold a valid instruction.
+    pub errors: BTreeMap<usize, DecodeError>,
+    /// Addresses of jumps with dynamic targets.
+    pub dynamic_jumps: BTreeSet<usize>,
+    /// Static targets of all reachable jumps.
+    pub jump_targets: BTreeSet<usize>,
+}
+
+/// The value of a parameter if it is known before running, reading the initial image for
+/// position mode.
+pub fn static_value(code: &[i64], param: Param) -> Option<i64> {
+    match param {
+        Param::Immediate(value) => Some(value),
+        Param::Position(address) if address >= 0 => {
+            Some(code.get(address as usize).cloned().unwrap_or(0))
+        }
+        _ => None,
+    }
+}
+
+pub fn successors(code: &[i64], instruction: &Instruction) -> Successors {
+    let jump_if_nonzero = match instruction.opcode {
+        Opcode::Halt => {
+            return Successors {
+                falls_through: false,
+                jump: Jump::None,
+            }
+        }
+        Opcode::JumpIfTrue => true,
+        Opcode::JumpIfFalse => false,
+        _ => {
+            return Successors {
+                falls_through: true,
+                jump: Jump::None,
+            }
+        }
+    };
+    let target = match static_value(code, instruction.params[1]) {
+        Some(target) if target >= 0 => Jump::Static(target as usize),
+        _ => Jump::Dynamic,
+    };
+    // only immediate conditions are certain, position mode cells may change
+    match instruction.params[0] {
+        Param::Immediate(value) if (value != 0) == jump_if_nonzero => Successors {
+            falls_through: false,
+            jump: target,
+        },
+        Param::Immediate(_) => Successors {
+            falls_through: true,
+            jump: Jump::None,
+        },
+        _ => Successors {
+            falls_through: true,
+            jump: target,
+        },
+    }
+}
+
+impl Analysis {
+    pub fn new(code: &[i64]) -> Analysis {
+        Self::from_entries(code, &[0])
+    }
+
+    /// Follows the control flow from every entry point.
+    pub fn from_entries(code: &[i64], entries: &[usize]) -> Analysis {
+        let mut analysis = Analysis::default();
+        let mut pending: Vec<usize> = entries.to_vec();
+        while let Some(address) = pending.pop() {
+            if analysis.instructions.contains_key(&address)
+                || analysis.errors.contains_key(&address)
+            {
+                continue;
+            }
+            let instruction = match decode(code, address) {
+                Ok(instruction) => instruction,
+                Err(error) => {
+                    analysis.errors.insert(address, error);
+                    continue;
+                }
+            };
+            let successors = successors(code, &instruction);
+            if successors.falls_through {
+                pending.push(instruction.next());
+            }
+            match successors.jump {
+                Jump::Static(target) => {
+                    analysis.jump_targets.insert(target);
+                    pending.push(target);
+                }
+                Jump::Dynamic => {
+                    analysis.dynamic_jumps.insert(address);
+                }
+                Jump::None => {}
+            }
+            analysis.instructions.insert(address, instruction);
+        }
+        analysis
+    }
+
+    /// Whether any reachable instruction uses relative mode.
+    pub fn uses_relative_mode(&self) -> bool {
+        self.instructions
+            .values()
+            .flat_map(|i| i.params.iter())
+            .any(|p| matches!(p, Param::Relative(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn test_reachability() {
+        // the conditional jump at 2 also falls through into the data cell at 5
+        let code = Machine::parse_code("3,9,1005,9,6,42,1106,0,10,0,99");
+        let analysis = Analysis::new(&code);
+        assert_eq!(
+            analysis.instructions.keys().cloned().collect::<Vec<_>>(),
+            vec![0, 2, 6, 10]
+        );
+        assert_eq!(
+            analysis.errors.get(&5),
+            Some(&DecodeError::UnknownOpcode(42))
+        );
+        assert!(analysis.dynamic_jumps.is_empty());
+        assert_eq!(
+            analysis.jump_targets.iter().cloned().collect::<Vec<_>>(),
+            vec![6, 10]
+        );
+    }
+}
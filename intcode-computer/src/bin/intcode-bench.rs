@@ -0,0 +1,358 @@
+//! Benchmark of the Intcode interpreter on the puzzle programs.
+//!
+//! ```text
+//! intcode-bench <repo-dir> [--runs <n>] [--save <file>] [--baseline <file>] [--threshold <percent>]
+//! ```
+//!
+//! Reads the puzzle inputs from `<repo-dir>/aoc-2019-<day>/input.txt` and runs each workload
+//! `--runs` times, 5 by default, keeping the fastest run. Allocations are counted by a wrapper of
+//! the system allocator. `--save` writes the results as JSON, `--baseline` compares them with
+//! saved results and exits with 1 if a workload got slower by more than `--threshold` percent,
+//! 10 by default, or allocates more. Build with `--release` for meaningful numbers.
+
+use intcode_computer::amplifier::{self, best_setting, Wiring};
+use intcode_computer::device::Arcade;
+use intcode_computer::json::{self, Json};
+use intcode_computer::{Machine, Program, RunOutcome};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// The system allocator, counting allocations and their bytes.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+fn count(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    BYTES.fetch_add(size as u64, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+struct Workload {
+    name: &'static str,
+    day: u32,
+    /// Runs the workload, returning the executed instructions and the puzzle answer.
+    run: fn(&Program) -> (u64, i64),
+}
+
+const WORKLOADS: &[Workload] = &[
+    Workload {
+        name: "day9-boost-test",
+        day: 9,
+        run: |program| boost(program, 1),
+    },
+    Workload {
+        name: "day9-boost-sensor",
+        day: 9,
+        run: |program| boost(program, 2),
+    },
+    Workload {
+        name: "day19-sweep",
+        day: 19,
+        run: sweep,
+    },
+    Workload {
+        name: "day13-game",
+        day: 13,
+        run: game,
+    },
+    Workload {
+        name: "day7-amplifiers",
+        day: 7,
+        run: amplifiers,
+    },
+];
+
+fn boost(program: &Program, mode: i64) -> (u64, i64) {
+    let mut machine = Machine::new(program);
+    let answer = machine.outputs(vec![mode]).last().expect("No output");
+    (machine.instructions(), answer)
+}
+
+/// Part 1 of day 19, scanning the beam like its `main`.
+fn sweep(program: &Program) -> (u64, i64) {
+    let mut machine = Machine::new(program);
+    let (mut instructions, mut count) = (0, 0);
+    for y in 0..50 {
+        for x in 0..50 {
+            machine.reset();
+            count += machine.outputs(vec![y, x]).next().expect("No output");
+            instructions += machine.instructions();
+        }
+    }
+    (instructions, count)
+}
+
+fn game(program: &Program) -> (u64, i64) {
+    let mut machine = Machine::new(program);
+    machine.set_state(0, 2);
+    let mut arcade = Arcade::new();
+    match machine.run_device(&mut arcade) {
+        RunOutcome::Halted(status) => (status.instructions, arcade.screen.score().unwrap_or(0)),
+        RunOutcome::NeedsInput => panic!("Needs input"),
+    }
+}
+
+/// Both parts of the puzzle, the answer is the sum of the highest signals.
+fn amplifiers(program: &Program) -> (u64, i64) {
+    let mut instructions = 0;
+    let mut answer = 0;
+    for (phases, wiring) in [
+        ((0..5).collect(), Wiring::Serial),
+        ((5..10).collect(), Wiring::Feedback),
+    ] {
+        let setting =
+            best_setting(program, &amplifier::Options::new(phases, wiring)).expect("No signal");
+        instructions += setting.instructions;
+        answer += setting.signal;
+    }
+    (instructions, answer)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Measurement {
+    name: String,
+    instructions: u64,
+    allocations: u64,
+    bytes: u64,
+    seconds: f64,
+}
+
+impl Measurement {
+    fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 / self.seconds
+    }
+}
+
+fn measure(workload: &Workload, program: &Program, runs: usize) -> (Measurement, i64) {
+    let mut best: Option<(Measurement, i64)> = None;
+    for _ in 0..runs {
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let bytes = BYTES.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let (instructions, answer) = (workload.run)(program);
+        let seconds = start.elapsed().as_secs_f64();
+        let measurement = Measurement {
+            name: workload.name.to_string(),
+            instructions,
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+            bytes: BYTES.load(Ordering::Relaxed) - bytes,
+            seconds,
+        };
+        if best.as_ref().is_none_or(|(b, _)| seconds < b.seconds) {
+            best = Some((measurement, answer));
+        }
+    }
+    best.expect("at least one run")
+}
+
+fn to_json(measurements: &[Measurement]) -> String {
+    let mut json = String::from("{\n  \"workloads\": [\n");
+    for (i, m) in measurements.iter().enumerate() {
+        let _ = write!(
+            json,
+            "    {{\"name\": \"{}\", \"instructions\": {}, \"allocations\": {}, \"bytes\": {}, \
+             \"seconds\": {:.6}, \"instructions_per_second\": {:.0}}}",
+            m.name,
+            m.instructions,
+            m.allocations,
+            m.bytes,
+            m.seconds,
+            m.instructions_per_second()
+        );
+        json.push_str(if i + 1 < measurements.len() {
+            ",\n"
+        } else {
+            "\n"
+        });
+    }
+    json.push_str("  ]\n}\n");
+    json
+}
+
+fn from_json(text: &str) -> Result<Vec<Measurement>, Box<dyn Error>> {
+    let json = json::parse(text)?;
+    let workloads = match json.get("workloads") {
+        Some(Json::Array(workloads)) => workloads,
+        _ => return Err("missing workloads".into()),
+    };
+    workloads
+        .iter()
+        .map(|workload| {
+            let name = match workload.get("name") {
+                Some(Json::String(name)) => name.clone(),
+                _ => return Err("workload without name".into()),
+            };
+            let number = |key| {
+                workload
+                    .get(key)
+                    .and_then(Json::as_f64)
+                    .ok_or_else(|| format!("{} has no {}", name, key))
+            };
+            Ok(Measurement {
+                instructions: number("instructions")? as u64,
+                allocations: number("allocations")? as u64,
+                bytes: number("bytes")? as u64,
+                seconds: number("seconds")?,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Prints the changes against the baseline and returns the number of regressions.
+fn compare(measurements: &[Measurement], baseline: &[Measurement], threshold: f64) -> usize {
+    let mut regressions = 0;
+    println!();
+    println!("{:<20} {:>10} {:>12}", "baseline", "time", "allocations");
+    for m in measurements {
+        let base = match baseline.iter().find(|b| b.name == m.name) {
+            Some(base) => base,
+            None => {
+                println!("{:<20} not in baseline", m.name);
+                continue;
+            }
+        };
+        let change = (m.seconds / base.seconds - 1.0) * 100.0;
+        let slower = change > threshold;
+        let more_allocations = m.allocations > base.allocations;
+        let mut flags = vec![];
+        if slower {
+            flags.push("SLOWER");
+        }
+        if more_allocations {
+            flags.push("MORE ALLOCATIONS");
+        }
+        if base.instructions != m.instructions {
+            flags.push("instruction count changed");
+        }
+        println!(
+            "{:<20} {:>+9.1}% {:>+12} {}",
+            m.name,
+            change,
+            m.allocations as i64 - base.allocations as i64,
+            flags.join(", ")
+        );
+        if slower || more_allocations {
+            regressions += 1;
+        }
+    }
+    regressions
+}
+
+struct Options {
+    repo: String,
+    runs: usize,
+    save: Option<String>,
+    baseline: Option<String>,
+    threshold: f64,
+}
+
+const USAGE: &str = "usage: intcode-bench <repo-dir> [--runs <n>] [--save <file>] \
+                     [--baseline <file>] [--threshold <percent>]";
+
+fn parse_options(args: &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut args = args.iter();
+    let mut options = Options {
+        repo: args.next().ok_or(USAGE)?.clone(),
+        runs: 5,
+        save: None,
+        baseline: None,
+        threshold: 10.0,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--runs" => options.runs = value()?.parse::<usize>()?.max(1),
+            "--save" => options.save = Some(value()?.clone()),
+            "--baseline" => options.baseline = Some(value()?.clone()),
+            "--threshold" => options.threshold = value()?.parse()?,
+            _ => return Err(format!("unknown argument {}\n{}", arg, USAGE).into()),
+        }
+    }
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<usize, Box<dyn Error>> {
+    let baseline = match &options.baseline {
+        Some(path) => Some(from_json(&fs::read_to_string(path)?)?),
+        None => None,
+    };
+    println!(
+        "{:<20} {:>12} {:>10} {:>12} {:>10} {:>12}",
+        "workload", "instructions", "Minstr/s", "allocations", "time", "answer"
+    );
+    let mut measurements = vec![];
+    for workload in WORKLOADS {
+        let path = Path::new(&options.repo)
+            .join(format!("aoc-2019-{}", workload.day))
+            .join("input.txt");
+        let program = Program::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (measurement, answer) = measure(workload, &program, options.runs);
+        println!(
+            "{:<20} {:>12} {:>10.2} {:>12} {:>8.1}ms {:>12}",
+            measurement.name,
+            measurement.instructions,
+            measurement.instructions_per_second() / 1e6,
+            measurement.allocations,
+            measurement.seconds * 1e3,
+            answer
+        );
+        measurements.push(measurement);
+    }
+    if let Some(path) = &options.save {
+        fs::write(path, to_json(&measurements))?;
+    }
+    Ok(match baseline {
+        Some(baseline) => compare(&measurements, &baseline, options.threshold),
+        None => 0,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match parse_options(&args).and_then(run) {
+        Ok(0) => {}
+        Ok(regressions) => {
+            eprintln!("intcode-bench: {} regressions", regressions);
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("intcode-bench: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,769 @@
+//! Debug Adapter Protocol server for Intcode programs.
+//!
+//! ```text
+//! intcode-dap [--port <port>]
+//! ```
+//!
+//! Speaks the protocol on stdin and stdout, or with `--port` serves one client connecting to
+//! localhost on that port. Port 0 picks a free port, the address is printed to stderr.
+//!
+//! `launch` takes the path of a program in text form or as image in `program`, the first inputs
+//! as array in `input`, an optional symbol map in `symbols` and `stopOnEntry`. Without a map the
+//! symbols of an image are used. Breakpoints are instruction breakpoints on addresses or symbol
+//! names. Steps execute single instructions, step out runs until the innermost call tracked by
+//! `intcode_computer::debug` returns. The registers scope shows `pc` and `relative_base`, the
+//! memory scope every cell of the program and the cells written beyond it.
+//!
+//! Outputs are sent as output events, one value per line. The program stops when it waits for
+//! input, `input <value>, ...` in the debug console queues more. Other console expressions are
+//! `pc`, `relative_base`, `[<address>]` and symbol names for the value of the named cell. Programs
+//! which provably loop forever stop with an exception, see `Machine::detect_loops`. Other
+//! programs run until they stop or the client pauses them, requests are handled between slices of
+//! the run.
+
+use intcode_computer::debug::Stop;
+use intcode_computer::decode::decode;
+use intcode_computer::image::{self, Image};
+use intcode_computer::json::{self, Json};
+use intcode_computer::symbols::SymbolMap;
+use intcode_computer::{Machine, MachineError, StepResult};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::process;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+const USAGE: &str = "usage: intcode-dap [--port <port>]";
+
+/// The only thread, there is one machine per session.
+const THREAD: i64 = 1;
+const REGISTERS: i64 = 1;
+const MEMORY: i64 = 2;
+
+/// Reads the next message, `None` at the end of the stream.
+fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<Json>, Box<dyn Error>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let mut body = vec![0; length.ok_or("message without Content-Length")?];
+    reader.read_exact(&mut body)?;
+    Ok(Some(json::parse(&String::from_utf8(body)?)?))
+}
+
+fn load_symbols(program: &str, map: Option<&str>) -> Result<Option<SymbolMap>, Box<dyn Error>> {
+    if let Some(map) = map {
+        return Ok(Some(SymbolMap::read(map)?));
+    }
+    let bytes = fs::read(program)?;
+    if image::is_image(&bytes) {
+        let symbols = Image::decode(&bytes)?.symbols;
+        if !symbols.is_empty() {
+            return Ok(Some(SymbolMap::from_symbols(&symbols)));
+        }
+    }
+    Ok(None)
+}
+
+/// An address given as number or symbol name.
+fn resolve(machine: Option<&Machine>, reference: &str) -> Option<usize> {
+    reference.parse().ok().or_else(|| {
+        machine
+            .and_then(|machine| machine.symbols())
+            .and_then(|symbols| symbols.address(reference))
+    })
+}
+
+/// Instructions run between looking for new requests, like a pause.
+const SLICE: u64 = 100_000;
+
+/// Why a run stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stopped {
+    /// A step or step out completed.
+    Step,
+    At(Stop),
+    Paused,
+}
+
+/// A continue or step out in progress.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Running {
+    /// Call depth to return below, for a step out.
+    depth: Option<usize>,
+    /// Whether no instruction ran yet, the run does not stop at a breakpoint it starts on.
+    first: bool,
+}
+
+/// Runs for at most [`SLICE`] instructions, `None` if the run goes on.
+fn run_slice(machine: &mut Machine, run: &mut Running) -> Result<Option<Stopped>, MachineError> {
+    for _ in 0..SLICE {
+        if !run.first && machine.has_breakpoint(machine.pc()) {
+            return Ok(Some(Stopped::At(Stop::Breakpoint)));
+        }
+        run.first = false;
+        match machine.try_step()? {
+            StepResult::Continue => {}
+            StepResult::Halt(status) => return Ok(Some(Stopped::At(Stop::Halted(status)))),
+            StepResult::NeedsInput => return Ok(Some(Stopped::At(Stop::NeedsInput))),
+        }
+        if run
+            .depth
+            .is_some_and(|depth| machine.call_stack().len() < depth)
+        {
+            return Ok(Some(Stopped::Step));
+        }
+    }
+    Ok(None)
+}
+
+fn variable(name: impl fmt::Display, value: i64) -> Json {
+    Json::object(vec![
+        ("name", Json::string(name)),
+        ("value", Json::string(value)),
+        ("variablesReference", Json::number(0)),
+    ])
+}
+
+struct Server<W: Write> {
+    out: W,
+    seq: u64,
+    machine: Option<Machine>,
+    /// Breakpoint addresses, also the ones set before the launch.
+    breakpoints: BTreeSet<usize>,
+    stop_on_entry: bool,
+    configured: bool,
+    started: bool,
+    terminated: bool,
+    running: Option<Running>,
+    /// Events to send after the response to the current request.
+    events: Vec<(String, Json)>,
+}
+
+impl<W: Write> Server<W> {
+    fn new(out: W) -> Server<W> {
+        Server {
+            out,
+            seq: 0,
+            machine: None,
+            breakpoints: BTreeSet::new(),
+            stop_on_entry: false,
+            configured: false,
+            started: false,
+            terminated: false,
+            running: None,
+            events: vec![],
+        }
+    }
+
+    fn send(&mut self, mut fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        fields.insert(0, ("seq", Json::number(self.seq)));
+        let text = Json::object(fields).to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", text.len(), text)?;
+        self.out.flush()
+    }
+
+    fn respond(
+        &mut self,
+        request: &Json,
+        command: &str,
+        result: Result<Json, Box<dyn Error>>,
+    ) -> io::Result<()> {
+        let mut fields = vec![
+            ("type", Json::string("response")),
+            (
+                "request_seq",
+                request.get("seq").cloned().unwrap_or(Json::Null),
+            ),
+            ("command", Json::string(command)),
+        ];
+        match result {
+            Ok(body) => {
+                fields.push(("success", Json::Bool(true)));
+                fields.push(("body", body));
+            }
+            Err(error) => {
+                fields.push(("success", Json::Bool(false)));
+                fields.push(("message", Json::string(error)));
+            }
+        }
+        self.send(fields)?;
+        self.send_events()
+    }
+
+    fn send_events(&mut self) -> io::Result<()> {
+        for (event, body) in std::mem::take(&mut self.events) {
+            self.send(vec![
+                ("type", Json::string("event")),
+                ("event", Json::String(event)),
+                ("body", body),
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.events.push((event.to_string(), body));
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) {
+        let mut body = vec![
+            ("reason", Json::string(reason)),
+            ("threadId", Json::number(THREAD)),
+            ("allThreadsStopped", Json::Bool(true)),
+        ];
+        if let Some(description) = description {
+            body.push(("description", Json::string(&description)));
+            body.push(("text", Json::String(description)));
+        }
+        self.event("stopped", Json::object(body));
+    }
+
+    fn machine(&mut self) -> Result<&mut Machine, Box<dyn Error>> {
+        Ok(self.machine.as_mut().ok_or("no program launched")?)
+    }
+
+    fn handle(&mut self, command: &str, arguments: &Json) -> Result<Json, Box<dyn Error>> {
+        let empty = Json::object(vec![]);
+        match command {
+            "initialize" => {
+                self.event("initialized", empty);
+                Ok(Json::object(vec![
+                    ("supportsConfigurationDoneRequest", Json::Bool(true)),
+                    ("supportsInstructionBreakpoints", Json::Bool(true)),
+                    ("supportsDisassembleRequest", Json::Bool(true)),
+                    ("supportsTerminateRequest", Json::Bool(true)),
+                ]))
+            }
+            "launch" => {
+                self.launch(arguments)?;
+                Ok(empty)
+            }
+            "setBreakpoints" => {
+                let breakpoints = arguments
+                    .get("breakpoints")
+                    .and_then(Json::as_array)
+                    .unwrap_or(&[])
+                    .iter()
+                    .map(|_| {
+                        Json::object(vec![
+                            ("verified", Json::Bool(false)),
+                            ("message", Json::string("set breakpoints on addresses")),
+                        ])
+                    })
+                    .collect();
+                Ok(Json::object(vec![(
+                    "breakpoints",
+                    Json::Array(breakpoints),
+                )]))
+            }
+            "setInstructionBreakpoints" => self.set_breakpoints(arguments),
+            "configurationDone" => {
+                self.configured = true;
+                self.start()?;
+                Ok(empty)
+            }
+            "threads" => Ok(Json::object(vec![(
+                "threads",
+                Json::Array(vec![Json::object(vec![
+                    ("id", Json::number(THREAD)),
+                    ("name", Json::string("intcode")),
+                ])]),
+            )])),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => {
+                let cells = self.machine()?.snapshot().iter().count();
+                Ok(Json::object(vec![(
+                    "scopes",
+                    Json::Array(vec![
+                        Json::object(vec![
+                            ("name", Json::string("Registers")),
+                            ("presentationHint", Json::string("registers")),
+                            ("variablesReference", Json::number(REGISTERS)),
+                            ("expensive", Json::Bool(false)),
+                        ]),
+                        Json::object(vec![
+                            ("name", Json::string("Memory")),
+                            ("variablesReference", Json::number(MEMORY)),
+                            ("indexedVariables", Json::number(cells)),
+                            ("expensive", Json::Bool(true)),
+                        ]),
+                    ]),
+                )]))
+            }
+            "variables" => self.variables(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "disassemble" => self.disassemble(arguments),
+            "continue" => {
+                self.resume(None)?;
+                Ok(Json::object(vec![(
+                    "allThreadsContinued",
+                    Json::Bool(true),
+                )]))
+            }
+            "next" | "stepIn" => {
+                self.step()?;
+                Ok(empty)
+            }
+            "stepOut" => {
+                let depth = self.machine()?.call_stack().len();
+                self.resume(Some(depth).filter(|depth| *depth > 0))?;
+                Ok(empty)
+            }
+            "pause" => {
+                if self.running.take().is_none() {
+                    return Err("the program is not running".into());
+                }
+                self.finish(Ok(Stopped::Paused));
+                Ok(empty)
+            }
+            "terminate" => {
+                self.running = None;
+                if !self.terminated {
+                    self.terminated = true;
+                    self.event("terminated", Json::object(vec![]));
+                }
+                Ok(empty)
+            }
+            "disconnect" => Ok(empty),
+            _ => Err(format!("unsupported request {}", command).into()),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<(), Box<dyn Error>> {
+        let program = arguments
+            .get("program")
+            .and_then(Json::as_str)
+            .ok_or("launch needs a program")?;
+        let code =
+            Machine::read_code(program).map_err(|error| format!("{}: {}", program, error))?;
+        let mut machine = Machine::new(code);
+        let map = arguments.get("symbols").and_then(Json::as_str);
+        machine.set_symbols(load_symbols(program, map)?);
+        if let Some(input) = arguments.get("input") {
+            for value in input.as_array().ok_or("input must be an array")? {
+                machine.add_input(value.as_i64().ok_or("inputs must be integers")?);
+            }
+        }
+        machine.track_calls(true);
+        machine.detect_loops(true);
+        for address in &self.breakpoints {
+            machine.set_breakpoint(*address);
+        }
+        self.stop_on_entry = arguments
+            .get("stopOnEntry")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+        self.machine = Some(machine);
+        self.start()
+    }
+
+    /// Starts the program once it is launched and configured.
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.started || !self.configured || self.machine.is_none() {
+            return Ok(());
+        }
+        self.started = true;
+        let machine = self.machine()?;
+        let at_breakpoint = machine.has_breakpoint(machine.pc());
+        if self.stop_on_entry {
+            self.stopped("entry", None);
+        } else if at_breakpoint {
+            self.stopped("instruction breakpoint", None);
+        } else {
+            self.resume(None)?;
+        }
+        Ok(())
+    }
+
+    fn check_stopped(&mut self) -> Result<&mut Machine, Box<dyn Error>> {
+        if self.terminated {
+            return Err("the program has ended".into());
+        }
+        if self.running.is_some() {
+            return Err("the program is running".into());
+        }
+        self.machine()
+    }
+
+    /// Starts a continue, or a step out of the calls above `depth`. The run goes on in slices
+    /// between requests, see [`Server::run_slice`].
+    fn resume(&mut self, depth: Option<usize>) -> Result<(), Box<dyn Error>> {
+        self.check_stopped()?;
+        self.running = Some(Running { depth, first: true });
+        Ok(())
+    }
+
+    /// Executes a single instruction.
+    fn step(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = self.check_stopped()?.try_step().map(|result| match result {
+            StepResult::Continue => Stopped::Step,
+            StepResult::Halt(status) => Stopped::At(Stop::Halted(status)),
+            StepResult::NeedsInput => Stopped::At(Stop::NeedsInput),
+        });
+        self.finish(result);
+        Ok(())
+    }
+
+    /// Continues the current run and sends the events once it stops.
+    fn run_slice(&mut self) -> io::Result<()> {
+        let mut run = match self.running {
+            Some(run) => run,
+            None => return Ok(()),
+        };
+        let machine = match self.machine.as_mut() {
+            Some(machine) => machine,
+            None => return Ok(()),
+        };
+        match run_slice(machine, &mut run).transpose() {
+            None => self.running = Some(run),
+            Some(result) => {
+                self.running = None;
+                self.finish(result);
+            }
+        }
+        self.send_events()
+    }
+
+    /// Queues the outputs and the events for a stop.
+    fn finish(&mut self, result: Result<Stopped, MachineError>) {
+        let (result, outputs) = match self.machine.as_mut() {
+            Some(machine) => (
+                result.map_err(|error| machine.describe_error(&error)),
+                machine.drain_output(),
+            ),
+            None => return,
+        };
+        if !outputs.is_empty() {
+            let output: String = outputs.iter().map(|value| format!("{}\n", value)).collect();
+            self.event(
+                "output",
+                Json::object(vec![
+                    ("category", Json::string("stdout")),
+                    ("output", Json::String(output)),
+                ]),
+            );
+        }
+        match result {
+            Ok(Stopped::Step) => self.stopped("step", None),
+            Ok(Stopped::Paused) => self.stopped("pause", None),
+            Ok(Stopped::At(Stop::Breakpoint)) => self.stopped("instruction breakpoint", None),
+            Ok(Stopped::At(Stop::NeedsInput)) => {
+                self.stopped("pause", Some("Waiting for input".to_string()))
+            }
+            Ok(Stopped::At(Stop::Halted(_))) => {
+                self.terminated = true;
+                self.event("exited", Json::object(vec![("exitCode", Json::number(0))]));
+                self.event("terminated", Json::object(vec![]));
+            }
+            Err(error) => self.stopped("exception", Some(error)),
+        }
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, Box<dyn Error>> {
+        let mut addresses = BTreeSet::new();
+        let mut results = vec![];
+        for breakpoint in arguments
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+        {
+            let reference = breakpoint
+                .get("instructionReference")
+                .and_then(Json::as_str)
+                .unwrap_or("");
+            let offset = breakpoint.get("offset").and_then(Json::as_i64).unwrap_or(0);
+            let address = resolve(self.machine.as_ref(), reference)
+                .and_then(|address| (address as i64).checked_add(offset))
+                .filter(|address| *address >= 0);
+            results.push(match address {
+                Some(address) => {
+                    addresses.insert(address as usize);
+                    Json::object(vec![
+                        ("verified", Json::Bool(true)),
+                        ("instructionReference", Json::string(address)),
+                    ])
+                }
+                None => Json::object(vec![
+                    ("verified", Json::Bool(false)),
+                    (
+                        "message",
+                        Json::String(format!("unknown address {}", reference)),
+                    ),
+                ]),
+            });
+        }
+        if let Some(machine) = &mut self.machine {
+            let old: Vec<usize> = machine.breakpoints().collect();
+            for address in old {
+                machine.remove_breakpoint(address);
+            }
+            for address in &addresses {
+                machine.set_breakpoint(*address);
+            }
+        }
+        self.breakpoints = addresses;
+        Ok(Json::object(vec![("breakpoints", Json::Array(results))]))
+    }
+
+    fn stack_trace(&mut self) -> Result<Json, Box<dyn Error>> {
+        let machine = self.machine()?;
+        let empty = SymbolMap::new();
+        let symbols = machine.symbols().unwrap_or(&empty);
+        let frame = |id: usize, entry: usize, address: usize| {
+            Json::object(vec![
+                ("id", Json::number(id)),
+                ("name", Json::String(symbols.describe_code(entry))),
+                ("line", Json::number(0)),
+                ("column", Json::number(0)),
+                ("instructionPointerReference", Json::string(address)),
+            ])
+        };
+        let calls = machine.call_stack();
+        let mut frames = vec![];
+        let mut address = machine.pc();
+        for (id, call) in calls.iter().rev().enumerate() {
+            frames.push(frame(id, call.entry, address));
+            address = call.call_site;
+        }
+        frames.push(frame(calls.len(), 0, address));
+        Ok(Json::object(vec![
+            ("totalFrames", Json::number(frames.len())),
+            ("stackFrames", Json::Array(frames)),
+        ]))
+    }
+
+    fn variables(&mut self, arguments: &Json) -> Result<Json, Box<dyn Error>> {
+        let machine = self.machine()?;
+        let reference = arguments.get("variablesReference").and_then(Json::as_i64);
+        let variables = match reference {
+            Some(REGISTERS) => vec![
+                variable("pc", machine.pc() as i64),
+                variable("relative_base", machine.relative_base()),
+            ],
+            Some(MEMORY) => {
+                let start = arguments.get("start").and_then(Json::as_i64).unwrap_or(0);
+                let count = match arguments.get("count").and_then(Json::as_i64) {
+                    Some(count) if count > 0 => count as usize,
+                    _ => usize::MAX,
+                };
+                let empty = SymbolMap::new();
+                let symbols = machine.symbols().unwrap_or(&empty);
+                machine
+                    .snapshot()
+                    .iter()
+                    .skip(start.max(0) as usize)
+                    .take(count)
+                    .map(|(address, value)| variable(symbols.describe(address as i128), value))
+                    .collect()
+            }
+            _ => return Err("unknown variables reference".into()),
+        };
+        Ok(Json::object(vec![("variables", Json::Array(variables))]))
+    }
+
+    fn evaluate(&mut self, arguments: &Json) -> Result<Json, Box<dyn Error>> {
+        let expression = arguments
+            .get("expression")
+            .and_then(Json::as_str)
+            .ok_or("evaluate needs an expression")?
+            .trim();
+        let machine = self.machine()?;
+        let result = if let Some(values) = expression.strip_prefix("input ") {
+            let values = Machine::try_parse_code(values)?;
+            for value in &values {
+                machine.add_input(*value);
+            }
+            format!("{} inputs queued", values.len())
+        } else {
+            let value = match expression {
+                "pc" => machine.pc() as i64,
+                "relative_base" => machine.relative_base(),
+                _ => {
+                    let address = match expression
+                        .strip_prefix('[')
+                        .and_then(|expression| expression.strip_suffix(']'))
+                    {
+                        Some(reference) => resolve(Some(machine), reference.trim()),
+                        None => machine
+                            .symbols()
+                            .and_then(|symbols| symbols.address(expression)),
+                    };
+                    let address =
+                        address.ok_or_else(|| format!("cannot evaluate {}", expression))?;
+                    machine.get_state(address)
+                }
+            };
+            value.to_string()
+        };
+        Ok(Json::object(vec![
+            ("result", Json::String(result)),
+            ("variablesReference", Json::number(0)),
+        ]))
+    }
+
+    /// Instructions around a reference, found by decoding the program from the start in its
+    /// current state. Cells which do not decode are listed as data.
+    fn disassemble(&mut self, arguments: &Json) -> Result<Json, Box<dyn Error>> {
+        let machine = self.machine()?;
+        let reference = arguments
+            .get("memoryReference")
+            .and_then(Json::as_str)
+            .unwrap_or("");
+        let base = resolve(Some(machine), reference)
+            .ok_or_else(|| format!("unknown address {}", reference))?;
+        let offset = arguments
+            .get("instructionOffset")
+            .and_then(Json::as_i64)
+            .unwrap_or(0);
+        let count = arguments
+            .get("instructionCount")
+            .and_then(Json::as_i64)
+            .ok_or("disassemble needs an instructionCount")?;
+
+        let code: Vec<i64> = (0..machine.program().len())
+            .map(|address| machine.get_state(address))
+            .collect();
+        let mut starts = vec![];
+        let mut address = 0;
+        while address < code.len() {
+            starts.push(address);
+            address = decode(&code, address).map_or(address + 1, |i| i.next());
+        }
+        // instructions behind the code are single data cells
+        let index = match starts.iter().rposition(|start| *start <= base) {
+            Some(index) if base < code.len() => index as i64,
+            _ => (starts.len() + base - code.len()) as i64,
+        };
+
+        let empty = SymbolMap::new();
+        let symbols = machine.symbols().unwrap_or(&empty);
+        let instructions = (index + offset..index + offset + count)
+            .map(|index| {
+                if index < 0 {
+                    return Json::object(vec![
+                        ("address", Json::string(index)),
+                        ("instruction", Json::string("")),
+                        ("presentationHint", Json::string("invalid")),
+                    ]);
+                }
+                let index = index as usize;
+                let (address, text) = match starts.get(index) {
+                    Some(address) => match decode(&code, *address) {
+                        Ok(instruction) => (*address, instruction.display(symbols).to_string()),
+                        Err(_) => (*address, format!("data {}", code[*address])),
+                    },
+                    None => {
+                        let address = code.len() + index - starts.len();
+                        (address, format!("data {}", machine.get_state(address)))
+                    }
+                };
+                let mut fields = vec![
+                    ("address", Json::string(address)),
+                    ("instruction", Json::String(text)),
+                ];
+                if let Some(name) = symbols.name(address) {
+                    fields.push(("symbol", Json::string(name)));
+                }
+                Json::object(fields)
+            })
+            .collect();
+        Ok(Json::object(vec![(
+            "instructions",
+            Json::Array(instructions),
+        )]))
+    }
+}
+
+fn serve<R: BufRead + Send + 'static, W: Write>(
+    mut input: R,
+    out: W,
+) -> Result<(), Box<dyn Error>> {
+    // messages are read on their own thread, so requests like pause arrive while a program runs
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || loop {
+        let message = read_message(&mut input).map_err(|error| error.to_string());
+        let end = !matches!(message, Ok(Some(_)));
+        if sender.send(message).is_err() || end {
+            break;
+        }
+    });
+    let mut server = Server::new(out);
+    loop {
+        let message = if server.running.is_some() {
+            match messages.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => {
+                    server.run_slice()?;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match messages.recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            }
+        };
+        let message = match message? {
+            Some(message) => message,
+            None => break,
+        };
+        if message.get("type").and_then(Json::as_str) != Some("request") {
+            continue;
+        }
+        let command = message
+            .get("command")
+            .and_then(Json::as_str)
+            .unwrap_or("")
+            .to_string();
+        let arguments = message
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| Json::object(vec![]));
+        let result = server.handle(&command, &arguments);
+        server.respond(&message, &command, result)?;
+        if command == "disconnect" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn listen(port: u16) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    serve(BufReader::new(stream.try_clone()?), stream)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [] => serve(BufReader::new(io::stdin()), io::stdout()),
+        [flag, port] if flag == "--port" => match port.parse() {
+            Ok(port) => listen(port),
+            Err(_) => Err(USAGE.into()),
+        },
+        _ => Err(USAGE.into()),
+    };
+    if let Err(error) = result {
+        eprintln!("intcode-dap: {}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,102 @@
+//! Fuzzer for the Intcode interpreter.
+//!
+//! ```text
+//! intcode-fuzz seed <corpus-dir> <day-dir>...
+//! intcode-fuzz run <corpus-dir> [iterations] [rng-seed]
+//! intcode-fuzz minimize <case-file>
+//! ```
+//!
+//! Cases which make the machine panic are written minimized to `<corpus-dir>/crashes`.
+
+use intcode_computer::fuzz::{self, Case, Rng};
+use std::error::Error;
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+fn usage() -> Box<dyn Error> {
+    "usage: intcode-fuzz seed <corpus-dir> <day-dir>... | run <corpus-dir> [iterations] [rng-seed] \
+     | minimize <case-file>"
+        .into()
+}
+
+fn seed(corpus: &Path, day_dirs: &[String]) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(corpus)?;
+    let cases = fuzz::seed_corpus(day_dirs);
+    for (i, case) in cases.iter().enumerate() {
+        fs::write(corpus.join(format!("seed-{}.txt", i)), case.to_string())?;
+    }
+    println!("wrote {} seeds to {}", cases.len(), corpus.display());
+    Ok(())
+}
+
+fn load_corpus(corpus: &Path) -> Result<Vec<Case>, Box<dyn Error>> {
+    let mut cases = vec![];
+    for entry in fs::read_dir(corpus)? {
+        let path = entry?.path();
+        if path.is_file() {
+            if let Some(case) = Case::load(&path) {
+                cases.push(case);
+            }
+        }
+    }
+    if cases.is_empty() {
+        return Err(format!("no cases in {}", corpus.display()).into());
+    }
+    Ok(cases)
+}
+
+fn run(corpus: &Path, iterations: u64, rng_seed: u64) -> Result<(), Box<dyn Error>> {
+    let cases = load_corpus(corpus)?;
+    let crashes = corpus.join("crashes");
+    let mut rng = Rng::new(rng_seed);
+    let mut crash_count = 0;
+
+    panic::set_hook(Box::new(|_| {}));
+    for iteration in 0..iterations {
+        let mut case = cases[rng.below(cases.len())].clone();
+        let other = &cases[rng.below(cases.len())];
+        for _ in 0..=rng.below(8) {
+            fuzz::mutate(&mut case, other, &mut rng);
+        }
+        if fuzz::panics(&case) {
+            let minimized = fuzz::minimize(&case, fuzz::panics);
+            fs::create_dir_all(&crashes)?;
+            let path = crashes.join(format!("crash-{}-{}.txt", rng_seed, iteration));
+            fs::write(&path, minimized.to_string())?;
+            eprintln!("crash written to {}", path.display());
+            crash_count += 1;
+        }
+    }
+    let _ = panic::take_hook();
+
+    println!("{} iterations, {} crashes", iterations, crash_count);
+    Ok(())
+}
+
+fn minimize(path: &Path) -> Result<(), Box<dyn Error>> {
+    let case = Case::load(path).ok_or("invalid case file")?;
+    panic::set_hook(Box::new(|_| {}));
+    if !fuzz::panics(&case) {
+        let _ = panic::take_hook();
+        return Err("case does not panic".into());
+    }
+    let minimized = fuzz::minimize(&case, fuzz::panics);
+    let _ = panic::take_hook();
+    print!("{}", minimized);
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        Some("seed") if args.len() >= 3 => seed(Path::new(&args[1]), &args[2..]),
+        Some("run") if args.len() >= 2 => {
+            let iterations = args.get(2).map_or(Ok(100_000), |s| s.parse())?;
+            let rng_seed = args.get(3).map_or(Ok(2019), |s| s.parse())?;
+            run(Path::new(&args[1]), iterations, rng_seed)
+        }
+        Some("minimize") if args.len() == 2 => minimize(Path::new(&args[1])),
+        _ => Err(usage()),
+    }
+}
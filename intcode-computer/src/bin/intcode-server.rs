@@ -0,0 +1,167 @@
+//! Hosts Intcode machine sessions for other programs over TCP.
+//!
+//! ```text
+//! intcode-server [--port <port>] [--limit <instructions>] [--memory <cells>]
+//! ```
+//!
+//! Listens on localhost, on a free port unless `--port` is given, and prints the address to
+//! stderr. Clients send requests of the line protocol described in `intcode_computer::server`,
+//! sessions are shared between all connections. Sessions may execute `--limit` instructions,
+//! 100000000 by default, and write `--memory` cells beyond their program, 1000000 by default.
+//! Requests are handled one at a time, a long run delays the requests of other clients.
+//!
+//! Besides the protocol requests, `quit` closes the connection and `shutdown` answers `ok`, closes
+//! all connections and stops the server once the request in progress is handled.
+
+use intcode_computer::server::Sessions;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const USAGE: &str =
+    "usage: intcode-server [--port <port>] [--limit <instructions>] [--memory <cells>]";
+
+/// The command line options.
+struct Options {
+    port: u16,
+    limit: u64,
+    memory_limit: usize,
+}
+
+/// A request line and where to send its response.
+type Request = (String, Sender<String>);
+
+/// What the connection threads share.
+struct Shared {
+    address: SocketAddr,
+    shutdown: AtomicBool,
+    /// Open connections by number, to close them on shutdown.
+    connections: Mutex<HashMap<u64, TcpStream>>,
+}
+
+impl Shared {
+    fn shut_down(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // wakes up the accepting main thread
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+fn serve(stream: TcpStream, requests: Sender<Request>, shared: &Shared) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        match line.trim() {
+            "" => continue,
+            "quit" => break,
+            "shutdown" => {
+                writeln!(out, "ok")?;
+                shared.shut_down();
+                break;
+            }
+            _ => {}
+        }
+        let (sender, response) = mpsc::channel();
+        if requests.send((line, sender)).is_err() {
+            break;
+        }
+        match response.recv() {
+            Ok(response) => writeln!(out, "{}", response)?,
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", options.port))?;
+    let address = listener.local_addr()?;
+    eprintln!("listening on {}", address);
+
+    // machines are not Send, one thread owns all sessions
+    let (requests, receiver) = mpsc::channel::<Request>();
+    let machines = thread::spawn(move || {
+        let mut sessions = Sessions::new(options.limit);
+        sessions.set_memory_limit(Some(options.memory_limit));
+        for (line, response) in receiver {
+            let _ = response.send(sessions.respond(&line));
+        }
+    });
+
+    let shared = Arc::new(Shared {
+        address,
+        shutdown: AtomicBool::new(false),
+        connections: Mutex::new(HashMap::new()),
+    });
+    let mut threads = vec![];
+    for (number, stream) in (0u64..).zip(listener.incoming()) {
+        if shared.shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        let (stream, connection) = match stream.and_then(|s| Ok((s.try_clone()?, s))) {
+            Ok(streams) => streams,
+            Err(error) => {
+                eprintln!("intcode-server: {}", error);
+                continue;
+            }
+        };
+        shared
+            .connections
+            .lock()
+            .unwrap()
+            .insert(number, connection);
+        threads.retain(|thread: &thread::JoinHandle<()>| !thread.is_finished());
+        let requests = requests.clone();
+        let shared = shared.clone();
+        threads.push(thread::spawn(move || {
+            if let Err(error) = serve(stream, requests, &shared) {
+                eprintln!("intcode-server: {}", error);
+            }
+            shared.connections.lock().unwrap().remove(&number);
+        }));
+    }
+
+    for connection in shared.connections.lock().unwrap().values() {
+        let _ = connection.shutdown(Shutdown::Both);
+    }
+    for thread in threads {
+        let _ = thread.join();
+    }
+    drop(requests);
+    let _ = machines.join();
+    Ok(())
+}
+
+fn parse_options(args: &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        port: 0,
+        limit: 100_000_000,
+        memory_limit: 1_000_000,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        match arg.as_str() {
+            "--port" => options.port = value.parse()?,
+            "--limit" => options.limit = value.parse()?,
+            "--memory" => options.memory_limit = value.parse()?,
+            _ => return Err(USAGE.into()),
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = parse_options(&args).and_then(run);
+    if let Err(error) = result {
+        eprintln!("intcode-server: {}", error);
+        process::exit(1);
+    }
+}
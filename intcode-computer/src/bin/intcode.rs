@@ -0,0 +1,441 @@
+//! Runs Intcode programs from the command line.
+//!
+//! ```text
+//! intcode run <program> [--input <value>]... [--poke <address>=<value>]...
+//!             [--format lines|csv|ascii] [--ascii-input] [--dump] [--symbols <map>] [--trace]
+//!             [--detect-loops] [--break <address>|<name>]... [--backtrace]
+//! intcode disassemble <program> [--symbols <map>]
+//! intcode decompile <program>
+//! intcode lint <program>
+//! intcode compile <source>
+//! intcode convert <input> <output> [--symbol <name>=<address>]... [--meta <key>=<value>]...
+//!                 [--symbols <map>]
+//! ```
+//!
+//! Inputs given as arguments are used first, further inputs are read from stdin as they are
+//! needed. Outputs are flushed value by value in every format, so runs can be chained like
+//! `intcode run a.txt | intcode run b.txt`. `--dump` prints the memory to stderr once
+//! the program stops. `--trace` prints every instruction to stderr before it runs.
+//! `--detect-loops` stops with an error once the program provably loops forever without reading
+//! input, see `Machine::detect_loops`. At each `--break`, and on errors with `--backtrace`, the
+//! backtrace of the calls in progress is printed to stderr, see `intcode_computer::debug`. Calls
+//! are only tracked with one of these options. Breakpoints don't stop the run.
+//!
+//! `--symbols` reads a symbol map, see `intcode_computer::symbols`, and shows its names in traces,
+//! errors and disassembly. Without it `run` and `disassemble` use the symbols of an image.
+//!
+//! `decompile` prints the program as C-like pseudocode. `lint` lists suspicious constructs with
+//! their address and severity and fails if any is an error. `compile` translates a source file in the
+//! language of `intcode_computer::compile` and prints the program as comma separated values.
+//!
+//! `convert` writes a program in text form as binary image, see `intcode_computer::image`, and an
+//! image back as text. Images record the input file as `source` unless `--meta` sets it.
+//! `--symbols` adds the entries of a symbol map to the image, or writes the symbols of an image to
+//! a map.
+
+use intcode_computer::decode::disassemble_with;
+use intcode_computer::image::{self, Image};
+use intcode_computer::lint::Severity;
+use intcode_computer::symbols::{self, SymbolMap};
+use intcode_computer::{Machine, StepResult};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::process;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Format {
+    Lines,
+    Csv,
+    Ascii,
+}
+
+struct Options {
+    program: String,
+    inputs: Vec<i64>,
+    pokes: Vec<(usize, i64)>,
+    format: Format,
+    ascii_input: bool,
+    dump: bool,
+    symbols: Option<String>,
+    trace: bool,
+    detect_loops: bool,
+    breaks: Vec<String>,
+    backtrace: bool,
+}
+
+const USAGE: &str = "usage: intcode run <program> [--input <value>]... \
+                     [--poke <address>=<value>]... [--format lines|csv|ascii] [--ascii-input] [--dump] \
+                     [--symbols <map>] [--trace] [--detect-loops] [--break <address>|<name>]... \
+                     [--backtrace]\n\
+                     \x20      intcode disassemble <program> [--symbols <map>]\n\
+                     \x20      intcode decompile <program>\n\
+                     \x20      intcode lint <program>\n\
+                     \x20      intcode compile <source>\n\
+                     \x20      intcode convert <input> <output> [--symbol <name>=<address>]... \
+                     [--meta <key>=<value>]... [--symbols <map>]";
+
+fn parse_options(args: &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut args = args.iter();
+    let program = args.next().ok_or(USAGE)?.clone();
+    let mut options = Options {
+        program,
+        inputs: vec![],
+        pokes: vec![],
+        format: Format::Lines,
+        ascii_input: false,
+        dump: false,
+        symbols: None,
+        trace: false,
+        detect_loops: false,
+        breaks: vec![],
+        backtrace: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--input" => options.inputs.push(value()?.parse()?),
+            "--poke" => {
+                let poke = value()?;
+                let mut parts = poke.splitn(2, '=');
+                let address = parts.next().unwrap_or("").parse()?;
+                let value = parts
+                    .next()
+                    .ok_or("poke needs <address>=<value>")?
+                    .parse()?;
+                options.pokes.push((address, value));
+            }
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "lines" => Format::Lines,
+                    "csv" => Format::Csv,
+                    "ascii" => Format::Ascii,
+                    format => return Err(format!("unknown format {}", format).into()),
+                }
+            }
+            "--ascii-input" => options.ascii_input = true,
+            "--dump" => options.dump = true,
+            "--symbols" => options.symbols = Some(value()?.clone()),
+            "--trace" => options.trace = true,
+            "--detect-loops" => options.detect_loops = true,
+            "--break" => options.breaks.push(value()?.clone()),
+            "--backtrace" => options.backtrace = true,
+            _ => return Err(format!("unknown argument {}\n{}", arg, USAGE).into()),
+        }
+    }
+    Ok(options)
+}
+
+/// Reads inputs from stdin, one line at a time when the program needs them.
+struct StdinInput {
+    pending: VecDeque<i64>,
+    ascii: bool,
+}
+
+impl StdinInput {
+    fn next(&mut self) -> Result<Option<i64>, Box<dyn Error>> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if self.ascii {
+                self.pending.extend(line.bytes().map(i64::from));
+            } else {
+                for token in line.split(|c: char| c == ',' || c.is_whitespace()) {
+                    if !token.is_empty() {
+                        self.pending.push_back(token.parse()?);
+                    }
+                }
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+struct Output<W: Write> {
+    out: W,
+    format: Format,
+    count: usize,
+}
+
+impl<W: Write> Output<W> {
+    fn write(&mut self, value: i64) -> io::Result<()> {
+        match self.format {
+            Format::Lines => writeln!(self.out, "{}", value)?,
+            // the separator comes first, the line ends once the program stops
+            Format::Csv if self.count == 0 => write!(self.out, "{}", value)?,
+            Format::Csv => write!(self.out, ",{}", value)?,
+            Format::Ascii => match value {
+                0..=127 => self.out.write_all(&[value as u8])?,
+                _ => writeln!(self.out, "{}", value)?,
+            },
+        }
+        self.count += 1;
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.format == Format::Csv && self.count > 0 {
+            writeln!(self.out)?;
+        }
+        self.out.flush()
+    }
+}
+
+/// The map given with `--symbols`, otherwise the symbols of `program` if it is an image.
+fn load_symbols(program: &str, map: Option<&str>) -> Result<Option<SymbolMap>, Box<dyn Error>> {
+    if let Some(map) = map {
+        return Ok(Some(SymbolMap::read(map)?));
+    }
+    let bytes = fs::read(program)?;
+    if image::is_image(&bytes) {
+        let symbols = Image::decode(&bytes)?.symbols;
+        if !symbols.is_empty() {
+            return Ok(Some(SymbolMap::from_symbols(&symbols)));
+        }
+    }
+    Ok(None)
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let code = Machine::read_code(&options.program)?;
+    let mut machine = Machine::new(code);
+    machine.set_symbols(load_symbols(&options.program, options.symbols.as_deref())?);
+    for (address, value) in &options.pokes {
+        machine.set_state(*address, *value);
+    }
+    for value in &options.inputs {
+        machine.add_input(*value);
+    }
+    machine.detect_loops(options.detect_loops);
+    machine.track_calls(options.backtrace || !options.breaks.is_empty());
+    for location in &options.breaks {
+        let address = match location.parse() {
+            Ok(address) => address,
+            Err(_) => machine
+                .symbols()
+                .and_then(|symbols| symbols.address(location))
+                .ok_or_else(|| format!("unknown symbol {}", location))?,
+        };
+        machine.set_breakpoint(address);
+    }
+    let mut stdin = StdinInput {
+        pending: VecDeque::new(),
+        ascii: options.ascii_input,
+    };
+    let stdout = io::stdout();
+    let mut output = Output {
+        out: BufWriter::new(stdout.lock()),
+        format: options.format,
+        count: 0,
+    };
+
+    let result = execute(
+        &mut machine,
+        &mut stdin,
+        &mut output,
+        options.trace,
+        options.backtrace,
+    );
+    if options.dump {
+        eprint!("{}", machine.snapshot());
+    }
+    result
+}
+
+fn disassemble(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (program, map) = match args {
+        [program] => (program, None),
+        [program, option, map] if option == "--symbols" => (program, Some(map.as_str())),
+        _ => return Err(USAGE.into()),
+    };
+    let code = Machine::read_code(program)?;
+    let symbols = load_symbols(program, map)?.unwrap_or_default();
+    print!("{}", disassemble_with(&code, &symbols));
+    Ok(())
+}
+
+fn decompile(program: &str) -> Result<(), Box<dyn Error>> {
+    let code = Machine::read_code(program)?;
+    print!("{}", intcode_computer::decompile::decompile(&code));
+    Ok(())
+}
+
+fn lint(program: &str) -> Result<(), Box<dyn Error>> {
+    let code = Machine::read_code(program)?;
+    let findings = intcode_computer::lint::lint(&code);
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(format!("{} errors", errors).into());
+    }
+    Ok(())
+}
+
+fn compile(source: &str) -> Result<(), Box<dyn Error>> {
+    let source = std::fs::read_to_string(source)?;
+    let code = intcode_computer::compile::compile(&source)?;
+    let code: Vec<String> = code.iter().map(|value| value.to_string()).collect();
+    println!("{}", code.join(","));
+    Ok(())
+}
+
+/// Splits `<key>=<value>`.
+fn key_value(arg: &str) -> Result<(&str, &str), Box<dyn Error>> {
+    let mut parts = arg.splitn(2, '=');
+    let key = parts.next().unwrap_or("");
+    let value = parts
+        .next()
+        .ok_or_else(|| format!("{} needs <key>=<value>", arg))?;
+    Ok((key, value))
+}
+
+fn convert(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (input, output) = match args {
+        [input, output, ..] => (input, output),
+        _ => return Err(USAGE.into()),
+    };
+    let mut symbols = SymbolMap::new();
+    let mut metadata = vec![];
+    let mut map = None;
+    let mut args = args[2..].iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--symbol" => {
+                let (name, address) = key_value(value)?;
+                if !symbols::is_valid_name(name) {
+                    return Err(format!("invalid symbol name {:?}", name).into());
+                }
+                symbols.insert(name, address.parse()?);
+            }
+            "--meta" => metadata.push(key_value(value)?),
+            "--symbols" => map = Some(value),
+            _ => return Err(format!("unknown argument {}\n{}", arg, USAGE).into()),
+        }
+    }
+
+    let bytes = fs::read(input)?;
+    if image::is_image(&bytes) {
+        if !symbols.is_empty() || !metadata.is_empty() {
+            return Err("--symbol and --meta need a program in text form".into());
+        }
+        let image = Image::decode(&bytes)?;
+        let code: Vec<String> = image.code.iter().map(|value| value.to_string()).collect();
+        fs::write(output, code.join(",") + "\n")?;
+        if let Some(map) = map {
+            SymbolMap::from_symbols(&image.symbols).write(map)?;
+        }
+        return Ok(());
+    }
+
+    let mut image = Image::new(Machine::try_parse_code(&String::from_utf8(bytes)?)?);
+    image.metadata.insert("source".to_string(), input.clone());
+    for (key, value) in metadata {
+        image.metadata.insert(key.to_string(), value.to_string());
+    }
+    if let Some(map) = map {
+        for (address, name) in SymbolMap::read(map)?.iter() {
+            if symbols.address(name).is_none() && symbols.name(address).is_none() {
+                symbols.insert(name, address);
+            }
+        }
+    }
+    image.symbols = symbols.to_symbols();
+    fs::write(output, image.encode())?;
+    Ok(())
+}
+
+/// Prints the instruction at the program counter to stderr.
+fn trace(machine: &Machine) {
+    let empty = SymbolMap::new();
+    let symbols = machine.symbols().unwrap_or(&empty);
+    let pc = machine.pc();
+    let label = match machine.symbols() {
+        Some(symbols) => format!(" {}", symbols.describe_code(pc)),
+        None => String::new(),
+    };
+    match machine.current_instruction() {
+        Ok(instruction) => eprintln!("{:>6}{}: {}", pc, label, instruction.display(symbols)),
+        Err(error) => eprintln!("{:>6}{}: {}", pc, label, error),
+    }
+}
+
+fn execute<W: Write>(
+    machine: &mut Machine,
+    stdin: &mut StdinInput,
+    output: &mut Output<W>,
+    trace_instructions: bool,
+    backtrace: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut steps = 0u64;
+    loop {
+        if trace_instructions {
+            trace(machine);
+        }
+        if machine.breakpoints().any(|address| address == machine.pc()) {
+            output.out.flush()?;
+            eprint!("breakpoint at {}\n{}", machine.pc(), machine.backtrace());
+        }
+        let result = machine.try_step();
+        steps += 1;
+        // pass output on at every stop and now and then for programs which never stop
+        if !matches!(result, Ok(StepResult::Continue)) || steps.is_multiple_of(4096) {
+            for value in machine.drain_output() {
+                output.write(value)?;
+            }
+        }
+        match result {
+            Ok(StepResult::Continue) => {}
+            Ok(StepResult::Halt(_)) => break,
+            Ok(StepResult::NeedsInput) => {
+                output.out.flush()?;
+                match stdin.next()? {
+                    Some(value) => machine.add_input(value),
+                    None => {
+                        output.finish()?;
+                        return Err("program needs input but stdin is exhausted".into());
+                    }
+                }
+            }
+            Err(error) => {
+                output.finish()?;
+                if backtrace {
+                    eprint!("{}", machine.backtrace());
+                }
+                return Err(machine.describe_error(&error).into());
+            }
+        }
+    }
+    output.finish()?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("run") => parse_options(&args[1..]).and_then(run),
+        Some("disassemble") => disassemble(&args[1..]),
+        Some("decompile") if args.len() == 2 => decompile(&args[1]),
+        Some("lint") if args.len() == 2 => lint(&args[1]),
+        Some("compile") if args.len() == 2 => compile(&args[1]),
+        Some("convert") => convert(&args[1..]),
+        _ => Err(USAGE.into()),
+    };
+    if let Err(error) = result {
+        eprintln!("intcode: {}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,1254 @@
+//! Compiler from a small imperative language to Intcode.
+//!
+//! ```text
+//! var total = 0;          // globals start with a constant
+//! var squares[10];        // arrays have a fixed size, global ones start zeroed
+//!
+//! fn square(x) {
+//!     return x * x;
+//! }
+//!
+//! fn main() {
+//!     var i = 0;
+//!     while (i < 10) {
+//!         squares[i] = square(i);
+//!         total = total + squares[i];
+//!         i = i + 1;
+//!     }
+//!     write(total);
+//! }
+//! ```
+//!
+//! All values are integers. The operators are, from lowest to highest precedence, `||` and `&&`
+//! which short-circuit, `==` `!=` `<` `<=` `>` `>=`, `+` `-`, `*` and the unary `-` and `!`.
+//! There is no division, Intcode has none. `read()` and `write(x)` are op codes 3 and 4. Local
+//! variables start at 0 unless initialized, local arrays are not cleared. Functions without a
+//! `return` value return 0. Execution starts at `main`.
+//!
+//! Functions use the calling convention of the puzzle programs which [`crate::decompile`]
+//! understands: the caller stores the arguments at `[rb+1]`, ... and the return address at
+//! `[rb+0]`, the function allocates its frame with `arb` and returns its value in `[rb+1]`. The
+//! stack starts behind the globals. Array elements are addressed by patching the operand of the
+//! accessing instruction for global arrays, and by moving the relative base for local ones.
+
+use crate::decode::Opcode;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CompileError {}
+
+/// Compiles a program, see the module documentation for the language.
+pub fn compile(source: &str) -> Result<Vec<i64>, CompileError> {
+    let tokens = lex(source)?;
+    let program = Parser { tokens, index: 0 }.program()?;
+    Codegen::new(&program)?.program(&program)
+}
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T, CompileError> {
+    Err(CompileError {
+        line,
+        message: message.into(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    Number(i64),
+    Ident(String),
+    Punct(&'static str),
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+/// Longer punctuation first, so `<=` is not read as `<`.
+const PUNCTUATION: &[&str] = &[
+    "<=", ">=", "==", "!=", "&&", "||", "(", ")", "{", "}", "[", "]", ",", ";", "=", "+", "-", "*",
+    "<", ">", "!",
+];
+
+fn lex(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut tokens = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut rest = line.split("//").next().unwrap_or("").trim_start();
+        while !rest.is_empty() {
+            let length = if rest.starts_with(|c: char| c.is_ascii_digit()) {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let number = rest[..length].parse().or_else(|_| {
+                    error(
+                        line_number,
+                        format!("number {} is too large", &rest[..length]),
+                    )
+                })?;
+                tokens.push(Token {
+                    tok: Tok::Number(number),
+                    line: line_number,
+                });
+                length
+            } else if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push(Token {
+                    tok: Tok::Ident(rest[..length].to_string()),
+                    line: line_number,
+                });
+                length
+            } else {
+                match PUNCTUATION.iter().find(|p| rest.starts_with(*p)) {
+                    Some(punct) => {
+                        tokens.push(Token {
+                            tok: Tok::Punct(punct),
+                            line: line_number,
+                        });
+                        punct.len()
+                    }
+                    None => {
+                        let c = rest.chars().next().expect("non-empty");
+                        return error(line_number, format!("unexpected character {:?}", c));
+                    }
+                }
+            };
+            rest = rest[length..].trim_start();
+        }
+    }
+    let line = source.lines().count().max(1);
+    tokens.push(Token {
+        tok: Tok::End,
+        line,
+    });
+    Ok(tokens)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Var(String),
+    Index(String, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StmtKind {
+    Var {
+        name: String,
+        size: Option<i64>,
+        init: Option<Expr>,
+    },
+    Assign(Expr, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stmt {
+    kind: StmtKind,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Global {
+    name: String,
+    size: Option<i64>,
+    init: i64,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Program {
+    globals: Vec<Global>,
+    functions: Vec<Function>,
+}
+
+/// Binary operators by precedence level, lowest first.
+const LEVELS: &[&[(&str, BinOp)]] = &[
+    &[("||", BinOp::Or)],
+    &[("&&", BinOp::And)],
+    &[
+        ("==", BinOp::Eq),
+        ("!=", BinOp::Ne),
+        ("<", BinOp::Lt),
+        ("<=", BinOp::Le),
+        (">", BinOp::Gt),
+        (">=", BinOp::Ge),
+    ],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)],
+    &[("*", BinOp::Mul)],
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.index].tok
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.index].line
+    }
+
+    fn advance(&mut self) -> Tok {
+        let tok = self.tokens[self.index].tok.clone();
+        if tok != Tok::End {
+            self.index += 1;
+        }
+        tok
+    }
+
+    fn describe(tok: &Tok) -> String {
+        match tok {
+            Tok::Number(n) => n.to_string(),
+            Tok::Ident(name) => name.clone(),
+            Tok::Punct(punct) => punct.to_string(),
+            Tok::End => "end of input".to_string(),
+        }
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Tok::Punct(p) if *p == punct) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(name) if name == keyword)
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), CompileError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            let found = Self::describe(self.peek());
+            error(
+                self.line(),
+                format!("expected {} but found {}", punct, found),
+            )
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match self.advance() {
+            Tok::Ident(name) => Ok(name),
+            tok => error(
+                self.line(),
+                format!("expected a name but found {}", Self::describe(&tok)),
+            ),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, CompileError> {
+        let negative = self.eat("-");
+        match self.advance() {
+            Tok::Number(n) if negative => Ok(-n),
+            Tok::Number(n) => Ok(n),
+            tok => error(
+                self.line(),
+                format!("expected a number but found {}", Self::describe(&tok)),
+            ),
+        }
+    }
+
+    fn program(mut self) -> Result<Program, CompileError> {
+        let mut program = Program::default();
+        loop {
+            let line = self.line();
+            match self.advance() {
+                Tok::End => return Ok(program),
+                Tok::Ident(keyword) if keyword == "var" => {
+                    let name = self.ident()?;
+                    let size = if self.eat("[") {
+                        let size = self.number()?;
+                        self.expect("]")?;
+                        Some(size)
+                    } else {
+                        None
+                    };
+                    let init = if size.is_none() && self.eat("=") {
+                        self.number()?
+                    } else {
+                        0
+                    };
+                    self.expect(";")?;
+                    program.globals.push(Global {
+                        name,
+                        size,
+                        init,
+                        line,
+                    });
+                }
+                Tok::Ident(keyword) if keyword == "fn" => {
+                    let name = self.ident()?;
+                    self.expect("(")?;
+                    let mut params = vec![];
+                    if !self.eat(")") {
+                        loop {
+                            params.push(self.ident()?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    let body = self.block()?;
+                    program.functions.push(Function {
+                        name,
+                        params,
+                        body,
+                        line,
+                    });
+                }
+                tok => {
+                    return error(
+                        line,
+                        format!("expected var or fn but found {}", Self::describe(&tok)),
+                    )
+                }
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = vec![];
+        while !self.eat("}") {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        let kind = if self.is_keyword("var") {
+            self.advance();
+            let name = self.ident()?;
+            let (size, init) = if self.eat("[") {
+                let size = self.number()?;
+                self.expect("]")?;
+                (Some(size), None)
+            } else if self.eat("=") {
+                (None, Some(self.expr(0)?))
+            } else {
+                (None, None)
+            };
+            self.expect(";")?;
+            StmtKind::Var { name, size, init }
+        } else if self.is_keyword("if") {
+            self.advance();
+            return self.if_stmt(line);
+        } else if self.is_keyword("while") {
+            self.advance();
+            self.expect("(")?;
+            let cond = self.expr(0)?;
+            self.expect(")")?;
+            StmtKind::While(cond, self.block()?)
+        } else if self.is_keyword("return") {
+            self.advance();
+            let value = if self.eat(";") {
+                None
+            } else {
+                let value = self.expr(0)?;
+                self.expect(";")?;
+                Some(value)
+            };
+            StmtKind::Return(value)
+        } else {
+            let expr = self.expr(0)?;
+            let kind = if self.eat("=") {
+                match expr {
+                    Expr::Var(_) | Expr::Index(..) => StmtKind::Assign(expr, self.expr(0)?),
+                    _ => return error(line, "can only assign to variables and array elements"),
+                }
+            } else {
+                StmtKind::Expr(expr)
+            };
+            self.expect(";")?;
+            kind
+        };
+        Ok(Stmt { kind, line })
+    }
+
+    fn if_stmt(&mut self, line: usize) -> Result<Stmt, CompileError> {
+        self.expect("(")?;
+        let cond = self.expr(0)?;
+        self.expect(")")?;
+        let then = self.block()?;
+        let otherwise = if self.is_keyword("else") {
+            self.advance();
+            if self.is_keyword("if") {
+                let line = self.line();
+                self.advance();
+                vec![self.if_stmt(line)?]
+            } else {
+                self.block()?
+            }
+        } else {
+            vec![]
+        };
+        Ok(Stmt {
+            kind: StmtKind::If(cond, then, otherwise),
+            line,
+        })
+    }
+
+    fn expr(&mut self, level: usize) -> Result<Expr, CompileError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.expr(level + 1)?;
+        'operators: loop {
+            for (punct, op) in LEVELS[level] {
+                if self.eat(punct) {
+                    let rhs = self.expr(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.eat("-") {
+            return Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?)));
+        }
+        if self.eat("!") {
+            return Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?)));
+        }
+        let line = self.line();
+        match self.advance() {
+            Tok::Number(n) => Ok(Expr::Number(n)),
+            Tok::Punct("(") => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Tok::Ident(name) => {
+                if self.eat("(") {
+                    let mut args = vec![];
+                    if !self.eat(")") {
+                        loop {
+                            args.push(self.expr(0)?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    Ok(Expr::Call(name, args))
+                } else if self.eat("[") {
+                    let index = self.expr(0)?;
+                    self.expect("]")?;
+                    Ok(Expr::Index(name, Box::new(index)))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            }
+            tok => error(
+                line,
+                format!("expected an expression but found {}", Self::describe(&tok)),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Value {
+    Const(i64),
+    /// A label and an offset from it.
+    Label(usize, i64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Operand {
+    Imm(Value),
+    Pos(Value),
+    /// A slot of the current frame, resolved once the size of the frame is known.
+    Slot(i64),
+    /// Relative to the relative base of the current function, where calls pass their values.
+    Out(i64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Place {
+    Local(i64),
+    LocalArray(i64, i64),
+    Global(usize),
+    GlobalArray(usize, i64),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Frame {
+    scopes: Vec<HashMap<String, Place>>,
+    /// First slot not used by variables, slot 0 holds the return address.
+    locals: i64,
+    /// First slot free for temporaries of the current statement.
+    next: i64,
+    /// Highest slot used so far.
+    high: i64,
+    slot_fixups: Vec<(usize, i64)>,
+    /// Cells which hold the frame size, with the sign it is used with.
+    size_fixups: Vec<(usize, i64)>,
+}
+
+struct Codegen {
+    code: Vec<i64>,
+    labels: Vec<Option<usize>>,
+    /// Cells to patch with the address of a label plus an offset.
+    fixups: Vec<(usize, usize, i64)>,
+    /// Label and number of parameters of each function.
+    functions: HashMap<String, (usize, usize)>,
+    globals: HashMap<String, Place>,
+    /// Two cells to move values past relative base adjustments.
+    scratch: usize,
+    stack: usize,
+    frame: Frame,
+    line: usize,
+}
+
+const BUILTINS: &[&str] = &["read", "write"];
+
+fn constant(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Number(n) => Some(*n),
+        Expr::Unary(UnOp::Neg, x) => constant(x)?.checked_neg(),
+        Expr::Unary(UnOp::Not, x) => Some((constant(x)? == 0) as i64),
+        Expr::Binary(op, l, r) => {
+            let (a, b) = (constant(l)?, constant(r)?);
+            match op {
+                BinOp::Or => Some((a != 0 || b != 0) as i64),
+                BinOp::And => Some((a != 0 && b != 0) as i64),
+                BinOp::Eq => Some((a == b) as i64),
+                BinOp::Ne => Some((a != b) as i64),
+                BinOp::Lt => Some((a < b) as i64),
+                BinOp::Le => Some((a <= b) as i64),
+                BinOp::Gt => Some((a > b) as i64),
+                BinOp::Ge => Some((a >= b) as i64),
+                BinOp::Add => a.checked_add(b),
+                BinOp::Sub => a.checked_sub(b),
+                BinOp::Mul => a.checked_mul(b),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether evaluating the expression calls a function, which may change globals.
+fn has_call(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(name, args) => name != "read" || args.iter().any(has_call),
+        Expr::Index(_, index) => has_call(index),
+        Expr::Unary(_, x) => has_call(x),
+        Expr::Binary(_, l, r) => has_call(l) || has_call(r),
+        Expr::Number(_) | Expr::Var(_) => false,
+    }
+}
+
+impl Codegen {
+    fn new(program: &Program) -> Result<Codegen, CompileError> {
+        let mut codegen = Codegen {
+            code: vec![],
+            labels: vec![],
+            fixups: vec![],
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+            scratch: 0,
+            stack: 0,
+            frame: Frame::default(),
+            line: 1,
+        };
+        for function in &program.functions {
+            if BUILTINS.contains(&function.name.as_str())
+                || codegen.functions.contains_key(&function.name)
+            {
+                return error(
+                    function.line,
+                    format!("{} is already defined", function.name),
+                );
+            }
+            let label = codegen.label();
+            codegen
+                .functions
+                .insert(function.name.clone(), (label, function.params.len()));
+        }
+        for global in &program.globals {
+            if codegen.globals.contains_key(&global.name) {
+                return error(global.line, format!("{} is already defined", global.name));
+            }
+            let label = codegen.label();
+            let place = match global.size {
+                Some(size) if size < 1 => return error(global.line, "arrays need a size above 0"),
+                Some(size) => Place::GlobalArray(label, size),
+                None => Place::Global(label),
+            };
+            codegen.globals.insert(global.name.clone(), place);
+        }
+        match program.functions.iter().find(|f| f.name == "main") {
+            None => return error(1, "no main function"),
+            Some(main) if !main.params.is_empty() => {
+                return error(main.line, "main takes no parameters")
+            }
+            Some(_) => {}
+        }
+        codegen.scratch = codegen.label();
+        codegen.stack = codegen.label();
+        Ok(codegen)
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn value(&mut self, cell: usize, value: Value) -> i64 {
+        match value {
+            Value::Const(value) => value,
+            Value::Label(label, offset) => {
+                self.fixups.push((cell, label, offset));
+                0
+            }
+        }
+    }
+
+    fn emit(&mut self, opcode: Opcode, operands: &[Operand]) {
+        let address = self.code.len();
+        let mut op = opcode.code();
+        let mut digit = 100;
+        let mut cells = vec![];
+        for (i, operand) in operands.iter().enumerate() {
+            let cell = address + 1 + i;
+            let (mode, value) = match *operand {
+                Operand::Imm(value) => (1, self.value(cell, value)),
+                Operand::Pos(value) => (0, self.value(cell, value)),
+                Operand::Slot(slot) => {
+                    self.frame.slot_fixups.push((cell, slot));
+                    (2, 0)
+                }
+                Operand::Out(offset) => (2, offset),
+            };
+            op += mode * digit;
+            digit *= 10;
+            cells.push(value);
+        }
+        self.code.push(op);
+        self.code.extend(cells);
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, CompileError> {
+        error(self.line, message)
+    }
+
+    fn program(mut self, program: &Program) -> Result<Vec<i64>, CompileError> {
+        // set up the stack and call main
+        let (main, _) = self.functions["main"];
+        self.emit(Opcode::AdjustRelativeBase, &[imm_label(self.stack)]);
+        self.call_label(main);
+        self.emit(Opcode::Halt, &[]);
+
+        for function in &program.functions {
+            self.function(function)?;
+        }
+        for global in &program.globals {
+            match self.globals[&global.name] {
+                Place::Global(label) => {
+                    self.place(label);
+                    self.code.push(global.init);
+                }
+                Place::GlobalArray(label, size) => {
+                    self.place(label);
+                    self.code.extend((0..size).map(|_| 0));
+                }
+                _ => unreachable!("local global"),
+            }
+        }
+        self.place(self.scratch);
+        self.code.extend(&[0, 0]);
+        self.place(self.stack);
+
+        for (cell, label, offset) in std::mem::take(&mut self.fixups) {
+            self.code[cell] = self.labels[label].expect("placed label") as i64 + offset;
+        }
+        Ok(self.code)
+    }
+
+    fn function(&mut self, function: &Function) -> Result<(), CompileError> {
+        self.line = function.line;
+        let mut params = HashMap::new();
+        for (i, param) in function.params.iter().enumerate() {
+            if params
+                .insert(param.clone(), Place::Local(i as i64 + 1))
+                .is_some()
+            {
+                return self.error(format!("parameter {} is repeated", param));
+            }
+        }
+        let locals = function.params.len() as i64 + 1;
+        self.frame = Frame {
+            scopes: vec![params],
+            locals,
+            next: locals,
+            // slot 1 holds the result
+            high: locals.max(2) - 1,
+            ..Frame::default()
+        };
+
+        let (label, _) = self.functions[&function.name];
+        self.place(label);
+        self.frame.size_fixups.push((self.code.len() + 1, 1));
+        self.emit(Opcode::AdjustRelativeBase, &[imm(0)]);
+        for stmt in &function.body {
+            self.stmt(stmt)?;
+        }
+        self.ret(imm(0));
+
+        let size = self.frame.high + 1;
+        for (cell, sign) in std::mem::take(&mut self.frame.size_fixups) {
+            self.code[cell] = sign * size;
+        }
+        for (cell, slot) in std::mem::take(&mut self.frame.slot_fixups) {
+            self.code[cell] = slot - size;
+        }
+        Ok(())
+    }
+
+    fn ret(&mut self, value: Operand) {
+        self.emit(Opcode::Add, &[value, imm(0), Operand::Slot(1)]);
+        self.frame.size_fixups.push((self.code.len() + 1, -1));
+        self.emit(Opcode::AdjustRelativeBase, &[imm(0)]);
+        self.emit(Opcode::JumpIfTrue, &[imm(1), Operand::Out(0)]);
+    }
+
+    fn temp(&mut self) -> Operand {
+        let slot = self.frame.next;
+        self.frame.next += 1;
+        self.frame.high = self.frame.high.max(slot);
+        Operand::Slot(slot)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Place, CompileError> {
+        self.frame
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+            .cloned()
+            .map_or_else(|| self.error(format!("unknown variable {}", name)), Ok)
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), CompileError> {
+        self.frame.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        self.frame.scopes.pop();
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        self.line = stmt.line;
+        self.frame.next = self.frame.locals;
+        match &stmt.kind {
+            StmtKind::Var { name, size, init } => {
+                let slot = self.frame.locals;
+                let place = match size {
+                    Some(size) if *size < 1 => return self.error("arrays need a size above 0"),
+                    Some(size) => Place::LocalArray(slot, *size),
+                    None => Place::Local(slot),
+                };
+                self.frame.locals += size.unwrap_or(1);
+                self.frame.next = self.frame.locals;
+                self.frame.high = self.frame.high.max(self.frame.locals - 1);
+                match (init, size) {
+                    (Some(init), _) => self.expr_into(init, Operand::Slot(slot))?,
+                    (None, None) => self.emit(Opcode::Add, &[imm(0), imm(0), Operand::Slot(slot)]),
+                    (None, Some(_)) => {}
+                }
+                let scope = self.frame.scopes.last_mut().expect("scope");
+                if scope.insert(name.clone(), place).is_some() {
+                    return self.error(format!("{} is already defined", name));
+                }
+            }
+            StmtKind::Assign(Expr::Index(name, index), value) => {
+                self.store_index(name, index, value)?
+            }
+            StmtKind::Assign(target, value) => {
+                let target = self.expr(target)?;
+                self.expr_into(value, target)?;
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let cond = self.expr(cond)?;
+                let else_label = self.label();
+                self.emit(Opcode::JumpIfFalse, &[cond, imm_label(else_label)]);
+                self.block(then)?;
+                if otherwise.is_empty() {
+                    self.place(else_label);
+                } else {
+                    let end = self.label();
+                    self.emit(Opcode::JumpIfTrue, &[imm(1), imm_label(end)]);
+                    self.place(else_label);
+                    self.block(otherwise)?;
+                    self.place(end);
+                }
+            }
+            StmtKind::While(cond, body) => {
+                let (top, end) = (self.label(), self.label());
+                self.place(top);
+                let cond = self.expr(cond)?;
+                self.emit(Opcode::JumpIfFalse, &[cond, imm_label(end)]);
+                self.block(body)?;
+                self.emit(Opcode::JumpIfTrue, &[imm(1), imm_label(top)]);
+                self.place(end);
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value)?,
+                    None => imm(0),
+                };
+                self.ret(value);
+            }
+            StmtKind::Expr(Expr::Call(name, args)) if name == "write" => {
+                if args.len() != 1 {
+                    return self.error("write takes 1 argument");
+                }
+                let value = self.expr(&args[0])?;
+                self.emit(Opcode::Output, &[value]);
+            }
+            StmtKind::Expr(Expr::Call(name, args)) => {
+                if name == "read" {
+                    let temp = self.temp();
+                    self.expr_into(&Expr::Call(name.clone(), args.clone()), temp)?;
+                } else {
+                    self.call(name, args)?;
+                }
+            }
+            StmtKind::Expr(_) => return self.error("expression has no effect"),
+        }
+        Ok(())
+    }
+
+    /// Evaluates an expression to an operand, using a temporary for computed values.
+    fn expr(&mut self, expr: &Expr) -> Result<Operand, CompileError> {
+        if let Some(value) = constant(expr) {
+            return Ok(imm(value));
+        }
+        match expr {
+            Expr::Var(name) => match self.lookup(name)? {
+                Place::Local(slot) => Ok(Operand::Slot(slot)),
+                Place::Global(label) => Ok(Operand::Pos(Value::Label(label, 0))),
+                _ => self.error(format!("{} is an array", name)),
+            },
+            Expr::Index(name, index) if constant(index).is_some() => {
+                let index = constant(index).expect("constant");
+                match self.lookup(name)? {
+                    Place::LocalArray(slot, size) if (0..size).contains(&index) => {
+                        Ok(Operand::Slot(slot + index))
+                    }
+                    Place::GlobalArray(label, size) if (0..size).contains(&index) => {
+                        Ok(Operand::Pos(Value::Label(label, index)))
+                    }
+                    Place::LocalArray(..) | Place::GlobalArray(..) => {
+                        self.error(format!("index {} is outside of {}", index, name))
+                    }
+                    _ => self.error(format!("{} is not an array", name)),
+                }
+            }
+            _ => {
+                let temp = self.temp();
+                self.expr_into(expr, temp)?;
+                Ok(temp)
+            }
+        }
+    }
+
+    /// Evaluates `expr` before `later` runs, copying globals which a call in `later` may change.
+    fn stable(&mut self, expr: &Expr, later: &[&Expr]) -> Result<Operand, CompileError> {
+        let operand = self.expr(expr)?;
+        if matches!(operand, Operand::Pos(_)) && later.iter().any(|e| has_call(e)) {
+            let temp = self.temp();
+            self.emit(Opcode::Add, &[operand, imm(0), temp]);
+            return Ok(temp);
+        }
+        Ok(operand)
+    }
+
+    /// Evaluates an expression into `dest`, which is only written by the last instruction.
+    fn expr_into(&mut self, expr: &Expr, dest: Operand) -> Result<(), CompileError> {
+        if let Some(value) = constant(expr) {
+            self.emit(Opcode::Add, &[imm(value), imm(0), dest]);
+            return Ok(());
+        }
+        match expr {
+            Expr::Call(name, args) if name == "read" => {
+                if !args.is_empty() {
+                    return self.error("read takes no arguments");
+                }
+                self.emit(Opcode::Input, &[dest]);
+            }
+            Expr::Call(name, _) if name == "write" => return self.error("write has no value"),
+            Expr::Call(name, args) => {
+                self.call(name, args)?;
+                self.emit(Opcode::Add, &[Operand::Out(1), imm(0), dest]);
+            }
+            Expr::Index(name, index) if constant(index).is_none() => {
+                self.load_index(name, index, dest)?
+            }
+            Expr::Unary(op, x) => {
+                let x = self.expr(x)?;
+                match op {
+                    UnOp::Neg => self.emit(Opcode::Mul, &[x, imm(-1), dest]),
+                    UnOp::Not => self.emit(Opcode::Equals, &[x, imm(0), dest]),
+                }
+            }
+            Expr::Binary(op @ (BinOp::And | BinOp::Or), l, r) => {
+                // the result is built in a temporary since `r` may read `dest`
+                let result = self.temp();
+                let end = self.label();
+                let (initial, jump) = match op {
+                    BinOp::And => (0, Opcode::JumpIfFalse),
+                    _ => (1, Opcode::JumpIfTrue),
+                };
+                self.emit(Opcode::Add, &[imm(initial), imm(0), result]);
+                let l = self.expr(l)?;
+                self.emit(jump, &[l, imm_label(end)]);
+                let r = self.expr(r)?;
+                self.emit(Opcode::Equals, &[r, imm(0), result]);
+                self.emit(Opcode::Equals, &[result, imm(0), result]);
+                self.place(end);
+                self.emit(Opcode::Add, &[result, imm(0), dest]);
+            }
+            Expr::Binary(op, l, r) => {
+                let a = self.stable(l, &[r])?;
+                let b = self.expr(r)?;
+                match op {
+                    BinOp::Add => self.emit(Opcode::Add, &[a, b, dest]),
+                    BinOp::Sub => match b {
+                        Operand::Imm(Value::Const(b)) if b != i64::MIN => {
+                            self.emit(Opcode::Add, &[a, imm(-b), dest])
+                        }
+                        _ => {
+                            let negated = self.temp();
+                            self.emit(Opcode::Mul, &[b, imm(-1), negated]);
+                            self.emit(Opcode::Add, &[a, negated, dest]);
+                        }
+                    },
+                    BinOp::Mul => self.emit(Opcode::Mul, &[a, b, dest]),
+                    BinOp::Lt => self.emit(Opcode::LessThan, &[a, b, dest]),
+                    BinOp::Gt => self.emit(Opcode::LessThan, &[b, a, dest]),
+                    BinOp::Eq => self.emit(Opcode::Equals, &[a, b, dest]),
+                    BinOp::Ne | BinOp::Le | BinOp::Ge => {
+                        // the negation of ==, > and <
+                        match op {
+                            BinOp::Ne => self.emit(Opcode::Equals, &[a, b, dest]),
+                            BinOp::Le => self.emit(Opcode::LessThan, &[b, a, dest]),
+                            _ => self.emit(Opcode::LessThan, &[a, b, dest]),
+                        }
+                        self.emit(Opcode::Equals, &[dest, imm(0), dest]);
+                    }
+                    BinOp::And | BinOp::Or => unreachable!("short-circuit operator"),
+                }
+            }
+            _ => {
+                let value = self.expr(expr)?;
+                self.emit(Opcode::Add, &[value, imm(0), dest]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the return address in `[rb+0]` and jumps to the function at `label`.
+    fn call_label(&mut self, label: usize) {
+        let ret = self.label();
+        self.emit(Opcode::Add, &[imm_label(ret), imm(0), Operand::Out(0)]);
+        self.emit(Opcode::JumpIfTrue, &[imm(1), imm_label(label)]);
+        self.place(ret);
+    }
+
+    /// Calls a function, leaving its result in `[rb+1]`.
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<(), CompileError> {
+        let (label, arity) = match self.functions.get(name) {
+            Some(function) => *function,
+            None if name == "read" || name == "write" => {
+                return self.error(format!("{} can not be called here", name))
+            }
+            None => return self.error(format!("unknown function {}", name)),
+        };
+        if args.len() != arity {
+            return self.error(format!("{} takes {} arguments", name, arity));
+        }
+        // arguments are evaluated first as calls among them overwrite the argument slots
+        let mut values = vec![];
+        for (i, arg) in args.iter().enumerate() {
+            let later: Vec<&Expr> = args[i + 1..].iter().collect();
+            values.push(self.stable(arg, &later)?);
+        }
+        for (i, value) in values.into_iter().enumerate() {
+            self.emit(Opcode::Add, &[value, imm(0), Operand::Out(i as i64 + 1)]);
+        }
+        self.call_label(label);
+        Ok(())
+    }
+
+    fn array(&self, name: &str) -> Result<Place, CompileError> {
+        match self.lookup(name)? {
+            place @ (Place::LocalArray(..) | Place::GlobalArray(..)) => Ok(place),
+            _ => self.error(format!("{} is not an array", name)),
+        }
+    }
+
+    fn load_index(&mut self, name: &str, index: &Expr, dest: Operand) -> Result<(), CompileError> {
+        let place = self.array(name)?;
+        let index = self.expr(index)?;
+        match place {
+            Place::GlobalArray(label, _) => {
+                // patch the address read by the next instruction
+                let cell = self.code.len() + 4 + 1;
+                self.emit(Opcode::Add, &[imm_label(label), index, pos(cell)]);
+                self.emit(Opcode::Add, &[pos(0), imm(0), dest]);
+            }
+            Place::LocalArray(slot, _) => {
+                let (value, negated) = (pos_label(self.scratch, 0), pos_label(self.scratch, 1));
+                self.emit(Opcode::Mul, &[index, imm(-1), negated]);
+                self.emit(Opcode::AdjustRelativeBase, &[index]);
+                self.emit(Opcode::Add, &[Operand::Slot(slot), imm(0), value]);
+                self.emit(Opcode::AdjustRelativeBase, &[negated]);
+                self.emit(Opcode::Add, &[value, imm(0), dest]);
+            }
+            _ => unreachable!("not an array"),
+        }
+        Ok(())
+    }
+
+    fn store_index(&mut self, name: &str, index: &Expr, value: &Expr) -> Result<(), CompileError> {
+        let place = self.array(name)?;
+        if let Some(constant) = constant(index) {
+            let dest = self.expr(&Expr::Index(
+                name.to_string(),
+                Box::new(Expr::Number(constant)),
+            ))?;
+            return self.expr_into(value, dest);
+        }
+        let value = self.stable(value, &[index])?;
+        let index = self.expr(index)?;
+        match place {
+            Place::GlobalArray(label, _) => {
+                // patch the address written by the next instruction
+                let cell = self.code.len() + 4 + 3;
+                self.emit(Opcode::Add, &[imm_label(label), index, pos(cell)]);
+                self.emit(Opcode::Add, &[value, imm(0), pos(0)]);
+            }
+            Place::LocalArray(slot, _) => {
+                let (scratch, negated) = (pos_label(self.scratch, 0), pos_label(self.scratch, 1));
+                self.emit(Opcode::Add, &[value, imm(0), scratch]);
+                self.emit(Opcode::Mul, &[index, imm(-1), negated]);
+                self.emit(Opcode::AdjustRelativeBase, &[index]);
+                self.emit(Opcode::Add, &[scratch, imm(0), Operand::Slot(slot)]);
+                self.emit(Opcode::AdjustRelativeBase, &[negated]);
+            }
+            _ => unreachable!("not an array"),
+        }
+        Ok(())
+    }
+}
+
+fn imm(value: i64) -> Operand {
+    Operand::Imm(Value::Const(value))
+}
+
+fn imm_label(label: usize) -> Operand {
+    Operand::Imm(Value::Label(label, 0))
+}
+
+fn pos(address: usize) -> Operand {
+    Operand::Pos(Value::Const(address as i64))
+}
+
+fn pos_label(label: usize, offset: i64) -> Operand {
+    Operand::Pos(Value::Label(label, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    fn run(source: &str, input: &[i64]) -> Vec<i64> {
+        let mut machine = Machine::new(compile(source).unwrap());
+        for value in input {
+            machine.add_input(*value);
+        }
+        match machine.run_until_block() {
+            RunOutcome::Halted(_) => machine.drain_output(),
+            RunOutcome::NeedsInput => panic!("Needs input"),
+        }
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "
+            fn fib(n) {
+                if (n < 2) {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn main() {
+                var n = read();
+                while (n >= 0) {
+                    write(fib(n));
+                    n = n - 1;
+                }
+            }";
+        assert_eq!(
+            run(source, &[10]),
+            vec![55, 34, 21, 13, 8, 5, 3, 2, 1, 1, 0]
+        );
+    }
+
+    #[test]
+    fn test_arrays() {
+        // sorts the input in a global and a local array, the local one recursively
+        let source = "
+            var count = 0;
+            var global[10];
+
+            fn sort_global() {
+                var i = 0;
+                while (i < count) {
+                    var j = i;
+                    while (j > 0 && global[j - 1] > global[j]) {
+                        var swap = global[j];
+                        global[j] = global[j - 1];
+                        global[j - 1] = swap;
+                        j = j - 1;
+                    }
+                    i = i + 1;
+                }
+            }
+
+            fn reverse_local(depth) {
+                var local[3];
+                local[0] = global[depth];
+                local[depth - depth + 2] = -1;
+                if (depth + 1 < count) {
+                    reverse_local(depth + 1);
+                }
+                write(local[0] * -local[2]);
+            }
+
+            fn main() {
+                count = read();
+                var i = 0;
+                while (i != count) {
+                    global[i] = read();
+                    i = i + 1;
+                }
+                sort_global();
+                reverse_local(0);
+            }";
+        assert_eq!(run(source, &[5, 3, -7, 12, 0, 3]), vec![12, 3, 3, 0, -7]);
+    }
+
+    #[test]
+    fn test_operators() {
+        let source = "
+            var calls = 0;
+            fn count() { calls = calls + 1; return 1; }
+            fn main() {
+                var a = read();
+                var b = read();
+                write(a + b * 2 - -3);
+                write((a < b) + (a <= b) * 2 + (a > b) * 4 + (a >= b) * 8);
+                write((a == b) + (a != b) * 2 + !a * 4);
+                write(0 && count());
+                write(1 || count());
+                write(a && count());
+                write(calls + 2 * 3);
+            }";
+        assert_eq!(run(source, &[4, 9]), vec![25, 3, 2, 0, 1, 1, 7]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let check = |source: &str, message: &str| {
+            assert_eq!(compile(source).unwrap_err().to_string(), message);
+        };
+        check("fn main() {\n  x = 1;\n}", "line 2: unknown variable x");
+        check(
+            "fn f(a) {}\nfn main() { f(); }",
+            "line 2: f takes 1 arguments",
+        );
+        check(
+            "fn main() { var a[2]; a[2] = 1; }",
+            "line 1: index 2 is outside of a",
+        );
+        check(
+            "fn main() {\n  var x = 1 +;\n}",
+            "line 2: expected an expression but found ;",
+        );
+        check("fn f() {}", "line 1: no main function");
+    }
+}
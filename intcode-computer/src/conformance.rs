@@ -0,0 +1,303 @@
+//! Conformance table built from the example programs of the day 2, 5 and 9 puzzle descriptions.
+//!
+//! Every execution engine gets one test that calls [`check`] with a closure running a program to
+//! completion. The closure returns the produced output and the final memory, at least as long as
+//! the original program. The compiler and the decompiler don't execute programs and are out of
+//! scope.
+
+use super::*;
+use std::collections::BTreeSet;
+
+pub struct Case {
+    pub name: &'static str,
+    pub code: &'static str,
+    pub input: &'static [i64],
+    pub output: &'static [i64],
+    /// Expected memory after the halt, `None` if only the output is of interest.
+    pub memory: Option<&'static [i64]>,
+}
+
+pub struct Run {
+    pub output: Vec<i64>,
+    pub memory: Vec<i64>,
+    /// Cells of `memory` which are not compared, like the instructions the optimizer rewrote.
+    pub unchecked: BTreeSet<usize>,
+}
+
+const DAY_5_EQUAL_8_POSITION: &str = "3,9,8,9,10,9,4,9,99,-1,8";
+const DAY_5_LESS_THAN_8_POSITION: &str = "3,9,7,9,10,9,4,9,99,-1,8";
+const DAY_5_EQUAL_8_IMMEDIATE: &str = "3,3,1108,-1,8,3,4,3,99";
+const DAY_5_LESS_THAN_8_IMMEDIATE: &str = "3,3,1107,-1,8,3,4,3,99";
+const DAY_5_JUMP_POSITION: &str = "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9";
+const DAY_5_JUMP_IMMEDIATE: &str = "3,3,1105,-1,9,1101,0,0,12,4,12,99,1";
+const DAY_5_COMPARE_TO_8: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,\
+                                  1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,\
+                                  999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+pub const DAY_9_QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+pub const DAY_9_QUINE_OUTPUT: &[i64] = &[
+    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+];
+
+pub const CASES: &[Case] = &[
+    // day 2
+    Case {
+        name: "day 2 example",
+        code: "1,9,10,3,2,3,11,0,99,30,40,50",
+        input: &[],
+        output: &[],
+        memory: Some(&[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]),
+    },
+    Case {
+        name: "day 2 add",
+        code: "1,0,0,0,99",
+        input: &[],
+        output: &[],
+        memory: Some(&[2, 0, 0, 0, 99]),
+    },
+    Case {
+        name: "day 2 multiply",
+        code: "2,3,0,3,99",
+        input: &[],
+        output: &[],
+        memory: Some(&[2, 3, 0, 6, 99]),
+    },
+    Case {
+        name: "day 2 multiply behind halt",
+        code: "2,4,4,5,99,0",
+        input: &[],
+        output: &[],
+        memory: Some(&[2, 4, 4, 5, 99, 9801]),
+    },
+    Case {
+        name: "day 2 overwrite halt",
+        code: "1,1,1,4,99,5,6,0,99",
+        input: &[],
+        output: &[],
+        memory: Some(&[30, 1, 1, 4, 2, 5, 6, 0, 99]),
+    },
+    // day 5
+    Case {
+        name: "day 5 echo",
+        code: "3,0,4,0,99",
+        input: &[42],
+        output: &[42],
+        memory: Some(&[42, 0, 4, 0, 99]),
+    },
+    Case {
+        name: "day 5 parameter modes",
+        code: "1002,4,3,4,33",
+        input: &[],
+        output: &[],
+        memory: Some(&[1002, 4, 3, 4, 99]),
+    },
+    Case {
+        name: "day 5 negative immediate",
+        code: "1101,100,-1,4,0",
+        input: &[],
+        output: &[],
+        memory: Some(&[1101, 100, -1, 4, 99]),
+    },
+    Case {
+        name: "day 5 equal to 8, position mode, equal",
+        code: DAY_5_EQUAL_8_POSITION,
+        input: &[8],
+        output: &[1],
+        memory: Some(&[3, 9, 8, 9, 10, 9, 4, 9, 99, 1, 8]),
+    },
+    Case {
+        name: "day 5 equal to 8, position mode, not equal",
+        code: DAY_5_EQUAL_8_POSITION,
+        input: &[7],
+        output: &[0],
+        memory: Some(&[3, 9, 8, 9, 10, 9, 4, 9, 99, 0, 8]),
+    },
+    Case {
+        name: "day 5 less than 8, position mode, less",
+        code: DAY_5_LESS_THAN_8_POSITION,
+        input: &[5],
+        output: &[1],
+        memory: Some(&[3, 9, 7, 9, 10, 9, 4, 9, 99, 1, 8]),
+    },
+    Case {
+        name: "day 5 less than 8, position mode, equal",
+        code: DAY_5_LESS_THAN_8_POSITION,
+        input: &[8],
+        output: &[0],
+        memory: Some(&[3, 9, 7, 9, 10, 9, 4, 9, 99, 0, 8]),
+    },
+    Case {
+        name: "day 5 equal to 8, immediate mode, equal",
+        code: DAY_5_EQUAL_8_IMMEDIATE,
+        input: &[8],
+        output: &[1],
+        memory: Some(&[3, 3, 1108, 1, 8, 3, 4, 3, 99]),
+    },
+    Case {
+        name: "day 5 equal to 8, immediate mode, not equal",
+        code: DAY_5_EQUAL_8_IMMEDIATE,
+        input: &[9],
+        output: &[0],
+        memory: Some(&[3, 3, 1108, 0, 8, 3, 4, 3, 99]),
+    },
+    Case {
+        name: "day 5 less than 8, immediate mode, less",
+        code: DAY_5_LESS_THAN_8_IMMEDIATE,
+        input: &[-3],
+        output: &[1],
+        memory: Some(&[3, 3, 1107, 1, 8, 3, 4, 3, 99]),
+    },
+    Case {
+        name: "day 5 less than 8, immediate mode, greater",
+        code: DAY_5_LESS_THAN_8_IMMEDIATE,
+        input: &[12],
+        output: &[0],
+        memory: Some(&[3, 3, 1107, 0, 8, 3, 4, 3, 99]),
+    },
+    Case {
+        name: "day 5 jump, position mode, zero",
+        code: DAY_5_JUMP_POSITION,
+        input: &[0],
+        output: &[0],
+        memory: Some(&[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, 0, 0, 1, 9]),
+    },
+    Case {
+        name: "day 5 jump, position mode, non-zero",
+        code: DAY_5_JUMP_POSITION,
+        input: &[5],
+        output: &[1],
+        memory: Some(&[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, 5, 1, 1, 9]),
+    },
+    Case {
+        name: "day 5 jump, immediate mode, zero",
+        code: DAY_5_JUMP_IMMEDIATE,
+        input: &[0],
+        output: &[0],
+        memory: Some(&[3, 3, 1105, 0, 9, 1101, 0, 0, 12, 4, 12, 99, 0]),
+    },
+    Case {
+        name: "day 5 jump, immediate mode, non-zero",
+        code: DAY_5_JUMP_IMMEDIATE,
+        input: &[3],
+        output: &[1],
+        memory: Some(&[3, 3, 1105, 3, 9, 1101, 0, 0, 12, 4, 12, 99, 1]),
+    },
+    Case {
+        name: "day 5 compare to 8, below",
+        code: DAY_5_COMPARE_TO_8,
+        input: &[7],
+        output: &[999],
+        memory: None,
+    },
+    Case {
+        name: "day 5 compare to 8, equal",
+        code: DAY_5_COMPARE_TO_8,
+        input: &[8],
+        output: &[1000],
+        memory: None,
+    },
+    Case {
+        name: "day 5 compare to 8, above",
+        code: DAY_5_COMPARE_TO_8,
+        input: &[9],
+        output: &[1001],
+        memory: None,
+    },
+    // day 9
+    Case {
+        name: "day 9 quine",
+        code: DAY_9_QUINE,
+        input: &[],
+        output: DAY_9_QUINE_OUTPUT,
+        memory: Some(DAY_9_QUINE_OUTPUT),
+    },
+    Case {
+        name: "day 9 16 digit number",
+        code: "1102,34915192,34915192,7,4,7,99,0",
+        input: &[],
+        output: &[1219070632396864],
+        memory: Some(&[1102, 34915192, 34915192, 7, 4, 7, 99, 1219070632396864]),
+    },
+    Case {
+        name: "day 9 large number",
+        code: "104,1125899906842624,99",
+        input: &[],
+        output: &[1125899906842624],
+        memory: Some(&[104, 1125899906842624, 99]),
+    },
+];
+
+/// Runs every case of [`CASES`] through `run` and panics with the engine and case name on the
+/// first mismatch.
+pub fn check<F>(engine: &str, mut run: F)
+where
+    F: FnMut(Vec<i64>, &[i64]) -> Run,
+{
+    for case in CASES {
+        let result = run(Machine::parse_code(case.code), case.input);
+        assert_eq!(
+            result.output, case.output,
+            "{}: output of {:?}",
+            engine, case.name
+        );
+        if let Some(memory) = case.memory {
+            assert!(
+                result.memory.len() >= memory.len(),
+                "{}: memory of {:?} too short",
+                engine,
+                case.name
+            );
+            for (address, expected) in memory.iter().enumerate() {
+                if !result.unchecked.contains(&address) {
+                    assert_eq!(
+                        result.memory[address], *expected,
+                        "{}: memory of {:?} at {}",
+                        engine, case.name, address
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_machine() {
+    check("Machine", |code, input| {
+        let len = code.len();
+        let mut machine = Machine::new(code);
+        for value in input {
+            machine.add_input(*value);
+        }
+        match machine.run_until_block() {
+            RunOutcome::Halted(_) => {}
+            RunOutcome::NeedsInput => panic!("Machine needs input"),
+        }
+        Run {
+            output: machine.drain_output(),
+            memory: (0..len).map(|address| machine.get_state(address)).collect(),
+            unchecked: BTreeSet::new(),
+        }
+    });
+}
+
+#[test]
+fn test_optimizer() {
+    use crate::optimize::{optimize, Options};
+    check("optimizer", |code, input| {
+        let len = code.len();
+        let optimized = optimize(&code, &Options::default());
+        let mut machine = Machine::new(optimized.code.clone());
+        for value in input {
+            machine.add_input(*value);
+        }
+        match machine.run_until_block() {
+            RunOutcome::Halted(_) => {}
+            RunOutcome::NeedsInput => panic!("optimizer needs input"),
+        }
+        Run {
+            output: machine.drain_output(),
+            memory: (0..len).map(|address| machine.get_state(address)).collect(),
+            // rewritten instructions differ from the original program by design
+            unchecked: optimized.rewritten.clone(),
+        }
+    });
+}
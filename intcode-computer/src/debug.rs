@@ -0,0 +1,242 @@
+//! Breakpoints and a shadow call stack for debugging programs.
+//!
+//! Intcode has no call instruction. Programs follow the convention described in
+//! [`crate::decompile`]: the caller stores the return address at `[rb+0]` and jumps, the callee
+//! moves the relative base past its frame and finally jumps back through `[rb+0]`. With
+//! [`Machine::track_calls`] the machine guesses calls and returns from that at run time:
+//!
+//! - a taken jump is a call if `[rb+0]` holds the address behind the jump,
+//! - a jump to an address read in relative mode returns from the innermost frame with that
+//!   return address, dropping the frames above it,
+//! - frames are dropped when the relative base moves below the base they were called with.
+//!
+//! Programs which do not follow the convention get an empty or wrong stack, but run as usual.
+
+use crate::symbols::SymbolMap;
+use crate::{ExitStatus, Machine, MachineError, StepResult};
+use std::fmt::Write;
+
+/// A function call on the shadow call stack.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Address of the called function.
+    pub entry: usize,
+    /// Address of the jump which made the call.
+    pub call_site: usize,
+    pub return_address: usize,
+    /// Relative base of the caller at the call.
+    pub relative_base: i64,
+}
+
+/// Why [`Machine::run_until_stop`] returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Halted(ExitStatus),
+    NeedsInput,
+    /// The pc reached a breakpoint, its instruction did not run yet.
+    Breakpoint,
+}
+
+impl Machine {
+    /// Starts or stops tracking calls, see the [module documentation](self). Starting clears
+    /// the stack, calls made before are not known.
+    pub fn track_calls(&mut self, enabled: bool) {
+        self.calls = if enabled { Some(vec![]) } else { None };
+    }
+
+    /// Frames of the calls in progress, the innermost last. Empty unless calls are tracked.
+    pub fn call_stack(&self) -> &[Frame] {
+        self.calls.as_deref().unwrap_or(&[])
+    }
+
+    /// One line per frame from the current pc outwards, each with the address execution is at
+    /// and the function it is in, like `#1 16 in 10`. Uses names if symbols are loaded.
+    pub fn backtrace(&self) -> String {
+        let empty = SymbolMap::new();
+        let symbols = self.symbols().unwrap_or(&empty);
+        let frames = self.call_stack();
+        let mut trace = String::new();
+        let mut address = self.pc;
+        for (depth, frame) in frames.iter().rev().enumerate() {
+            let entry = symbols.describe_code(frame.entry);
+            let at = symbols.describe_code(address);
+            writeln!(trace, "#{} {} in {}", depth, at, entry).unwrap();
+            address = frame.call_site;
+        }
+        let at = symbols.describe_code(address);
+        let entry = symbols.describe_code(0);
+        writeln!(trace, "#{} {} in {}", frames.len(), at, entry).unwrap();
+        trace
+    }
+
+    /// Records a taken jump from the pc to `target`, `relative` if the target was read in
+    /// relative mode.
+    pub(crate) fn note_jump(&mut self, target: usize, relative: bool) {
+        let call_site = self.pc;
+        let return_address = self.pc + 3;
+        let relative_base = self.relative_base;
+        let is_call =
+            relative_base >= 0 && self.read(relative_base as u128) == return_address as i64;
+        let frames = match &mut self.calls {
+            Some(frames) => frames,
+            None => return,
+        };
+        if relative {
+            if let Some(depth) = frames
+                .iter()
+                .rposition(|frame| frame.return_address == target)
+            {
+                frames.truncate(depth);
+                return;
+            }
+        }
+        if is_call {
+            frames.push(Frame {
+                entry: target,
+                call_site,
+                return_address,
+                relative_base,
+            });
+        }
+    }
+
+    /// Drops frames after the relative base changed.
+    pub(crate) fn note_relative_base(&mut self) {
+        let relative_base = self.relative_base;
+        if let Some(frames) = &mut self.calls {
+            while frames
+                .last()
+                .is_some_and(|frame| frame.relative_base > relative_base)
+            {
+                frames.pop();
+            }
+        }
+    }
+
+    /// Stops the next runs before executing the instruction at `address`.
+    pub fn set_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Returns whether there was a breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn has_breakpoint(&self, address: usize) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().cloned()
+    }
+
+    /// Runs like [`Machine::run_until_block`] and also stops at breakpoints. The instruction at
+    /// the pc always runs, so a run resumes past the breakpoint it stopped at.
+    pub fn run_until_stop(&mut self) -> Result<Stop, MachineError> {
+        let mut first = true;
+        loop {
+            if !first && self.has_breakpoint(self.pc) {
+                return Ok(Stop::Breakpoint);
+            }
+            first = false;
+            match self.try_step()? {
+                StepResult::Continue => {}
+                StepResult::Halt(status) => return Ok(Stop::Halted(status)),
+                StepResult::NeedsInput => return Ok(Stop::NeedsInput),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Main at 0 calls f at 10, which calls g at 24. g ends with `tail`.
+    fn program(tail: &str) -> Machine {
+        let code = format!(
+            "109,100,21101,9,0,0,1105,1,10,99,\
+             109,2,21101,19,0,0,1105,1,24,109,-2,2106,0,0,\
+             109,3,{}",
+            tail
+        );
+        let mut machine = Machine::new(Machine::parse_code(&code));
+        machine.track_calls(true);
+        machine
+    }
+
+    #[test]
+    fn test_backtrace() {
+        // g outputs from a negative address
+        let mut machine = program("4,-1");
+        assert!(machine.run_until_stop().is_err());
+        assert_eq!(
+            machine
+                .call_stack()
+                .iter()
+                .map(|f| f.entry)
+                .collect::<Vec<_>>(),
+            vec![10, 24]
+        );
+        assert_eq!(machine.backtrace(), "#0 26 in 24\n#1 16 in 10\n#2 6 in 0\n");
+
+        let mut symbols = SymbolMap::new();
+        symbols.insert("main", 0);
+        symbols.insert("f", 10);
+        symbols.insert("g", 24);
+        machine.set_symbols(Some(symbols));
+        assert_eq!(
+            machine.backtrace(),
+            "#0 g+2 in g\n#1 f+6 in f\n#2 main+6 in main\n"
+        );
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // g returns
+        let mut machine = program("109,-3,2106,0,0");
+        machine.set_breakpoint(24);
+        machine.set_breakpoint(19);
+        assert_eq!(machine.run_until_stop(), Ok(Stop::Breakpoint));
+        assert_eq!(machine.pc(), 24);
+        assert_eq!(machine.call_stack().len(), 2);
+        assert_eq!(machine.run_until_stop(), Ok(Stop::Breakpoint));
+        assert_eq!(machine.pc(), 19);
+        assert_eq!(machine.backtrace(), "#0 19 in 10\n#1 6 in 0\n");
+
+        assert!(machine.remove_breakpoint(24));
+        assert_eq!(machine.breakpoints().collect::<Vec<_>>(), vec![19]);
+        assert!(matches!(machine.run_until_stop(), Ok(Stop::Halted(_))));
+        assert!(machine.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_compiled_recursion() {
+        // stops for input at the bottom of the recursion
+        let source = "
+            fn down(n) {
+                if (n == 0) {
+                    return read();
+                }
+                return down(n - 1);
+            }
+
+            fn main() {
+                write(down(3));
+            }";
+        let mut machine = Machine::new(crate::compile::compile(source).unwrap());
+        machine.track_calls(true);
+        assert_eq!(machine.run_until_stop(), Ok(Stop::NeedsInput));
+        let frames = machine.call_stack();
+        // main and four calls of down
+        assert_eq!(frames.len(), 5);
+        assert!(frames[2..]
+            .iter()
+            .all(|frame| frame.entry == frames[1].entry));
+        machine.add_input(7);
+        assert!(matches!(machine.run_until_stop(), Ok(Stop::Halted(_))));
+        assert_eq!(machine.drain_output(), vec![7]);
+        assert!(machine.call_stack().is_empty());
+    }
+}
@@ -0,0 +1,330 @@
+//! Decoding of Intcode instructions from a program image, for tools which inspect programs
+//! without running them.
+
+use crate::symbols::SymbolMap;
+use crate::Machine;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Mul,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Param {
+    Position(i64),
+    Immediate(i64),
+    Relative(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: usize,
+    pub opcode: Opcode,
+    pub params: Vec<Param>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpcode(i64),
+    /// A mode digit of a used parameter other than 0, 1 or 2.
+    InvalidMode {
+        param: usize,
+        mode: i64,
+    },
+    /// The instruction reaches beyond the end of the image.
+    Truncated,
+}
+
+impl Opcode {
+    pub fn from_code(op: i64) -> Option<Opcode> {
+        Some(match op {
+            1 => Opcode::Add,
+            2 => Opcode::Mul,
+            3 => Opcode::Input,
+            4 => Opcode::Output,
+            5 => Opcode::JumpIfTrue,
+            6 => Opcode::JumpIfFalse,
+            7 => Opcode::LessThan,
+            8 => Opcode::Equals,
+            9 => Opcode::AdjustRelativeBase,
+            99 => Opcode::Halt,
+            _ => return None,
+        })
+    }
+
+    pub fn code(self) -> i64 {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Mul => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpIfTrue => 5,
+            Opcode::JumpIfFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equals => 8,
+            Opcode::AdjustRelativeBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+
+    pub fn param_count(self) -> usize {
+        Machine::instruction_length(self.code()) - 1
+    }
+
+    /// Index of the parameter the instruction writes to.
+    pub fn write_param(self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => Some(2),
+            Opcode::Input => Some(0),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "add",
+            Opcode::Mul => "mul",
+            Opcode::Input => "in",
+            Opcode::Output => "out",
+            Opcode::JumpIfTrue => "jt",
+            Opcode::JumpIfFalse => "jf",
+            Opcode::LessThan => "lt",
+            Opcode::Equals => "eq",
+            Opcode::AdjustRelativeBase => "arb",
+            Opcode::Halt => "hlt",
+        }
+    }
+}
+
+impl Param {
+    pub fn mode(self) -> i64 {
+        match self {
+            Param::Position(_) => 0,
+            Param::Immediate(_) => 1,
+            Param::Relative(_) => 2,
+        }
+    }
+
+    pub fn value(self) -> i64 {
+        match self {
+            Param::Position(v) | Param::Immediate(v) | Param::Relative(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Param::Position(address) => write!(f, "[{}]", address),
+            Param::Immediate(value) => write!(f, "{}", value),
+            Param::Relative(offset) if *offset < 0 => write!(f, "[rb{}]", offset),
+            Param::Relative(offset) => write!(f, "[rb+{}]", offset),
+        }
+    }
+}
+
+impl Instruction {
+    /// Address of the instruction behind this one.
+    pub fn next(&self) -> usize {
+        self.address + self.params.len() + 1
+    }
+
+    /// Encodes the instruction back into cells.
+    pub fn encode(&self) -> Vec<i64> {
+        let mut op = self.opcode.code();
+        let mut digit = 100;
+        for param in &self.params {
+            op += param.mode() * digit;
+            digit *= 10;
+        }
+        let mut cells = vec![op];
+        cells.extend(self.params.iter().map(|p| p.value()));
+        cells
+    }
+
+    /// Parameters the instruction reads, all except the written one.
+    pub fn reads(&self) -> impl Iterator<Item = Param> + '_ {
+        let write = self.opcode.write_param();
+        self.params
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| Some(*i) != write)
+            .map(|(_, p)| *p)
+    }
+
+    pub fn write(&self) -> Option<Param> {
+        self.opcode.write_param().map(|i| self.params[i])
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.display(&SymbolMap::new()).fmt(f)
+    }
+}
+
+/// An instruction displayed with names, see [`Instruction::display`].
+pub struct Named<'a> {
+    instruction: &'a Instruction,
+    symbols: &'a SymbolMap,
+}
+
+impl Instruction {
+    /// Displays position mode addresses and immediate jump targets by their names in `symbols`.
+    pub fn display<'a>(&'a self, symbols: &'a SymbolMap) -> Named<'a> {
+        Named {
+            instruction: self,
+            symbols,
+        }
+    }
+}
+
+impl fmt::Display for Named<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let instruction = self.instruction;
+        write!(f, "{}", instruction.opcode.mnemonic())?;
+        let jump = matches!(instruction.opcode, Opcode::JumpIfTrue | Opcode::JumpIfFalse);
+        for (i, param) in instruction.params.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            match param {
+                Param::Position(address) => write!(
+                    f,
+                    "{}[{}]",
+                    separator,
+                    self.symbols.describe(*address as i128)
+                )?,
+                Param::Immediate(target) if jump && i == 1 => {
+                    write!(f, "{}{}", separator, self.symbols.describe(*target as i128))?
+                }
+                _ => write!(f, "{}{}", separator, param)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(op) => write!(f, "unknown op code {}", op),
+            DecodeError::InvalidMode { param, mode } => {
+                write!(f, "invalid mode {} of parameter {}", mode, param + 1)
+            }
+            DecodeError::Truncated => write!(f, "instruction truncated by the end of the image"),
+        }
+    }
+}
+
+/// Decodes the instruction at `address`.
+pub fn decode(code: &[i64], address: usize) -> Result<Instruction, DecodeError> {
+    let instruction = *code.get(address).ok_or(DecodeError::Truncated)?;
+    let opcode =
+        Opcode::from_code(instruction % 100).ok_or(DecodeError::UnknownOpcode(instruction))?;
+    let mut modes = instruction / 100;
+    let mut params = Vec::with_capacity(3);
+    for param in 0..opcode.param_count() {
+        let value = *code
+            .get(address + param + 1)
+            .ok_or(DecodeError::Truncated)?;
+        params.push(match modes % 10 {
+            0 => Param::Position(value),
+            1 => Param::Immediate(value),
+            2 => Param::Relative(value),
+            mode => return Err(DecodeError::InvalidMode { param, mode }),
+        });
+        modes /= 10;
+    }
+    Ok(Instruction {
+        address,
+        opcode,
+        params,
+    })
+}
+
+impl Machine {
+    /// Decodes the instruction at the program counter from the current memory.
+    pub fn current_instruction(&self) -> Result<Instruction, DecodeError> {
+        let cells: Vec<i64> = (0..4).map(|i| self.get_state(self.pc() + i)).collect();
+        let instruction = decode(&cells, 0)?;
+        Ok(Instruction {
+            address: self.pc(),
+            ..instruction
+        })
+    }
+}
+
+/// Lists the image from the start, decoding instructions where possible and printing other
+/// cells as data.
+pub fn disassemble(code: &[i64]) -> String {
+    disassemble_with(code, &SymbolMap::new())
+}
+
+/// Like [`disassemble`], with a label line for every named address and names for addressed
+/// cells and jump targets.
+pub fn disassemble_with(code: &[i64], symbols: &SymbolMap) -> String {
+    let mut listing = String::new();
+    let mut address = 0;
+    while address < code.len() {
+        if let Some(name) = symbols.name(address) {
+            listing += &format!("{}:\n", name);
+        }
+        match decode(code, address) {
+            Ok(instruction) => {
+                listing += &format!("{:>6}: {}\n", address, instruction.display(symbols));
+                address = instruction.next();
+            }
+            Err(_) => {
+                listing += &format!("{:>6}: data {}\n", address, code[address]);
+                address += 1;
+            }
+        }
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let code = Machine::parse_code("21101,5,-1,0,204,-3,99");
+        let instruction = decode(&code, 0).unwrap();
+        assert_eq!(
+            instruction.params,
+            vec![
+                Param::Immediate(5),
+                Param::Immediate(-1),
+                Param::Relative(0)
+            ]
+        );
+        assert_eq!(instruction.encode(), &code[..4]);
+        assert_eq!(instruction.to_string(), "add 5, -1, [rb+0]");
+        assert_eq!(decode(&code, 4).unwrap().to_string(), "out [rb-3]");
+        assert_eq!(decode(&code, 5), Err(DecodeError::UnknownOpcode(-3)));
+        assert_eq!(
+            decode(&[301, 0, 0, 0], 0),
+            Err(DecodeError::InvalidMode { param: 0, mode: 3 })
+        );
+        assert_eq!(decode(&[1, 0], 0), Err(DecodeError::Truncated));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let code = Machine::parse_code("1002,4,3,4,33,99");
+        assert_eq!(
+            disassemble(&code),
+            "     0: mul [4], 3, [4]\n     4: data 33\n     5: hlt\n"
+        );
+    }
+}
@@ -0,0 +1,1499 @@
+//! Decompilation of program images into C-like pseudocode.
+//!
+//! Functions follow the calling convention of the puzzle programs: the caller stores the
+//! arguments at `[rb+1]`, `[rb+2]`, ... and the return address at `[rb+0]`, then jumps to the
+//! function. The function moves the relative base past its frame with `arb` on entry, moves it
+//! back before returning through `[rb+0]` and leaves its result in the first argument slot.
+//!
+//! Relative slots are named after their offset from the relative base on entry: `argN` for the
+//! arguments, `varN` for the rest of the frame and `tmpN` for the slots behind the frame, which
+//! hold the arguments of calls. A function takes arguments up to the highest slot it may read
+//! before writing it, every call passes that many apart from trailing slots the caller did not
+//! write on every path to the call. Argument values are folded into the call unless the caller
+//! reads the slot again. Loops are recovered from back edges and if/else from the immediate
+//! post-dominator of a branch. Control flow which fits neither becomes a `goto`.
+
+use crate::decode::{decode, DecodeError, Instruction, Opcode, Param};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Callee {
+    Static(usize),
+    /// The target is read from a cell the program writes, like a function pointer.
+    Indirect(Param),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Next,
+    Halt,
+    Return,
+    Call(Callee),
+    /// A jump with a condition parameter and whether it jumps on non-zero, `None` jumps always.
+    /// The target is `None` when it is only known at run time.
+    Jump {
+        condition: Option<(Param, bool)>,
+        target: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    instruction: Instruction,
+    /// Relative base relative to the one on entry of the function, if known.
+    offset: Option<i64>,
+    flow: Flow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum End {
+    Halt,
+    Return,
+    Goto(usize),
+    Branch { target: usize, next: usize },
+    Dynamic,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    steps: Vec<usize>,
+    end: End,
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    entry: usize,
+    steps: BTreeMap<usize, Step>,
+    errors: BTreeMap<usize, DecodeError>,
+    blocks: BTreeMap<usize, Block>,
+    /// Size of the frame allocated by the first instruction.
+    frame: i64,
+    params: i64,
+    returns_value: bool,
+    /// Slots written on every path to each block, by leader.
+    entered: BTreeMap<usize, BTreeSet<i64>>,
+    /// Slots written anywhere in the function.
+    writes: BTreeSet<i64>,
+}
+
+struct Program {
+    functions: BTreeMap<usize, Function>,
+    /// Position mode cells the program writes.
+    written: BTreeSet<i64>,
+    /// Position mode cells each function reads directly.
+    cell_reads: BTreeMap<usize, BTreeSet<i64>>,
+    /// Position mode cells each function reads including its callees, `None` if it calls through
+    /// a pointer.
+    callee_reads: BTreeMap<usize, Option<BTreeSet<i64>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cond {
+    lhs: String,
+    op: &'static str,
+    rhs: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Line(String),
+    Label(usize),
+    Goto(usize),
+    Break,
+    Continue,
+    If {
+        cond: Cond,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    Loop {
+        cond: Option<Cond>,
+        body: Vec<Stmt>,
+    },
+    DoWhile {
+        cond: Cond,
+        body: Vec<Stmt>,
+    },
+}
+
+/// Decompiles the program starting at address 0 and every function it calls.
+pub fn decompile(code: &[i64]) -> String {
+    let program = Program::new(code);
+    let mut text = String::new();
+    for (i, function) in program.functions.values().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        text += &program.render(function);
+    }
+    text
+}
+
+impl Cond {
+    fn negate(self) -> Cond {
+        let op = match self.op {
+            "<" => ">=",
+            ">=" => "<",
+            "==" => "!=",
+            _ => "==",
+        };
+        Cond { op, ..self }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
+
+impl Stmt {
+    fn branch(cond: Cond, then: Vec<Stmt>, otherwise: Vec<Stmt>) -> Stmt {
+        if then.is_empty() && !otherwise.is_empty() {
+            Stmt::If {
+                cond: cond.negate(),
+                then: otherwise,
+                otherwise: then,
+            }
+        } else {
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            }
+        }
+    }
+}
+
+/// The static target of a jump, unless the program overwrites it.
+fn static_target(
+    code: &[i64],
+    written: &BTreeSet<i64>,
+    instruction: &Instruction,
+) -> Option<usize> {
+    let target = match instruction.params[1] {
+        Param::Immediate(target) if !written.contains(&(instruction.address as i64 + 2)) => target,
+        Param::Position(address) if address >= 0 && !written.contains(&address) => {
+            code.get(address as usize).cloned().unwrap_or(0)
+        }
+        _ => return None,
+    };
+    if target >= 0 {
+        Some(target as usize)
+    } else {
+        None
+    }
+}
+
+/// Whether the jump is preceded by a write of the address behind it to `[rb+0]`.
+fn is_call(code: &[i64], jump: &Instruction) -> bool {
+    if jump.address < 4 {
+        return false;
+    }
+    let store = match decode(code, jump.address - 4) {
+        Ok(store) => store,
+        Err(_) => return false,
+    };
+    let value = match store.params[..] {
+        [Param::Immediate(a), Param::Immediate(b), Param::Relative(0)] => match store.opcode {
+            Opcode::Add => a.checked_add(b),
+            Opcode::Mul => a.checked_mul(b),
+            _ => None,
+        },
+        _ => None,
+    };
+    value == Some(jump.next() as i64)
+}
+
+fn flow(
+    code: &[i64],
+    written: &BTreeSet<i64>,
+    instruction: &Instruction,
+    offset: Option<i64>,
+) -> Flow {
+    let jump_if_nonzero = match instruction.opcode {
+        Opcode::Halt => return Flow::Halt,
+        Opcode::JumpIfTrue => true,
+        Opcode::JumpIfFalse => false,
+        _ => return Flow::Next,
+    };
+    let condition = match instruction.params[0] {
+        Param::Immediate(value) if (value != 0) == jump_if_nonzero => None,
+        Param::Immediate(_) => return Flow::Next,
+        param => Some((param, jump_if_nonzero)),
+    };
+    if condition.is_none() {
+        if let Param::Relative(o) = instruction.params[1] {
+            if offset.map_or(o == 0, |c| c + o == 0) {
+                return Flow::Return;
+            }
+        }
+        if is_call(code, instruction) {
+            return Flow::Call(match static_target(code, written, instruction) {
+                Some(target) => Callee::Static(target),
+                None => Callee::Indirect(instruction.params[1]),
+            });
+        }
+    }
+    Flow::Jump {
+        condition,
+        target: static_target(code, written, instruction),
+    }
+}
+
+impl Function {
+    fn new(code: &[i64], written: &BTreeSet<i64>, entry: usize) -> Function {
+        let mut steps: BTreeMap<usize, Step> = BTreeMap::new();
+        let mut errors = BTreeMap::new();
+        let mut pending = vec![(entry, Some(0))];
+        while let Some((address, mut offset)) = pending.pop() {
+            if let Some(step) = steps.get(&address) {
+                if step.offset == offset || step.offset.is_none() {
+                    continue;
+                }
+                // reached with different relative bases
+                offset = None;
+            }
+            let instruction = match decode(code, address) {
+                Ok(instruction) => instruction,
+                Err(error) => {
+                    errors.insert(address, error);
+                    continue;
+                }
+            };
+            let flow = flow(code, written, &instruction, offset);
+            let after = match (instruction.opcode, instruction.params.first()) {
+                (Opcode::AdjustRelativeBase, Some(Param::Immediate(v))) => offset.map(|c| c + v),
+                (Opcode::AdjustRelativeBase, _) => None,
+                _ => offset,
+            };
+            match flow {
+                Flow::Next | Flow::Call(_) => pending.push((instruction.next(), after)),
+                Flow::Jump { condition, target } => {
+                    if condition.is_some() {
+                        pending.push((instruction.next(), after));
+                    }
+                    if let Some(target) = target {
+                        pending.push((target, after));
+                    }
+                }
+                Flow::Halt | Flow::Return => {}
+            }
+            steps.insert(
+                address,
+                Step {
+                    instruction,
+                    offset,
+                    flow,
+                },
+            );
+        }
+
+        let frame = match steps
+            .get(&entry)
+            .map(|s| (s.instruction.opcode, s.instruction.params.first()))
+        {
+            Some((Opcode::AdjustRelativeBase, Some(Param::Immediate(frame)))) if *frame > 0 => {
+                *frame
+            }
+            _ => 0,
+        };
+        let returns_value = steps
+            .values()
+            .any(|s| match (s.instruction.write(), s.offset) {
+                (Some(Param::Relative(o)), Some(c)) => c + o == 1,
+                _ => false,
+            });
+        let mut function = Function {
+            entry,
+            steps,
+            errors,
+            blocks: BTreeMap::new(),
+            frame,
+            params: 0,
+            returns_value,
+            entered: BTreeMap::new(),
+            writes: BTreeSet::new(),
+        };
+        function.blocks = function.split_blocks();
+        function
+    }
+
+    fn split_blocks(&self) -> BTreeMap<usize, Block> {
+        let mut leaders: BTreeSet<usize> = self.errors.keys().cloned().collect();
+        leaders.insert(self.entry);
+        for step in self.steps.values() {
+            if let Flow::Jump { condition, target } = step.flow {
+                leaders.extend(target);
+                if condition.is_some() {
+                    leaders.insert(step.instruction.next());
+                }
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &leader in &leaders {
+            let mut steps = vec![];
+            let mut address = leader;
+            let end = loop {
+                let step = match self.steps.get(&address) {
+                    Some(step) => step,
+                    None => break End::Error,
+                };
+                steps.push(address);
+                let next = step.instruction.next();
+                match step.flow {
+                    Flow::Halt => break End::Halt,
+                    Flow::Return => break End::Return,
+                    Flow::Jump {
+                        condition: None,
+                        target: Some(target),
+                    } => break End::Goto(target),
+                    Flow::Jump {
+                        condition: None,
+                        target: None,
+                    } => break End::Dynamic,
+                    Flow::Jump {
+                        condition: Some(_),
+                        target: Some(target),
+                    } => break End::Branch { target, next },
+                    Flow::Jump {
+                        condition: Some(_),
+                        target: None,
+                    } => break End::Goto(next),
+                    Flow::Next | Flow::Call(_) => {
+                        if leaders.contains(&next) {
+                            break End::Goto(next);
+                        }
+                        address = next;
+                    }
+                }
+            };
+            blocks.insert(leader, Block { steps, end });
+        }
+        blocks
+    }
+
+    /// Number of arguments, the highest slot above the return address which may be read before
+    /// it is written, found by a liveness analysis over the blocks.
+    fn arity(&self) -> i64 {
+        let mut uses: BTreeMap<usize, BTreeSet<i64>> = BTreeMap::new();
+        let mut defs: BTreeMap<usize, BTreeSet<i64>> = BTreeMap::new();
+        for (&leader, block) in &self.blocks {
+            let used = uses.entry(leader).or_default();
+            let defined = defs.entry(leader).or_default();
+            for step in block.steps.iter().map(|a| &self.steps[a]) {
+                let c = match step.offset {
+                    Some(c) => c,
+                    None => continue,
+                };
+                for param in step.instruction.reads() {
+                    if let Param::Relative(o) = param {
+                        if !defined.contains(&(c + o)) {
+                            used.insert(c + o);
+                        }
+                    }
+                }
+                if let Some(Param::Relative(o)) = step.instruction.write() {
+                    defined.insert(c + o);
+                }
+            }
+        }
+
+        let mut live: BTreeMap<usize, BTreeSet<i64>> = BTreeMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &leader in self.blocks.keys().rev() {
+                let mut slots = uses[&leader].clone();
+                for successor in self.successors(leader) {
+                    if let Some(out) = live.get(&successor) {
+                        slots.extend(out.difference(&defs[&leader]));
+                    }
+                }
+                if live.get(&leader) != Some(&slots) {
+                    live.insert(leader, slots);
+                    changed = true;
+                }
+            }
+        }
+        live.get(&self.entry)
+            .and_then(|slots| slots.iter().next_back())
+            .map_or(0, |slot| (*slot).max(0))
+    }
+
+    fn successors(&self, block: usize) -> Vec<usize> {
+        match self.blocks[&block].end {
+            End::Goto(target) => vec![target],
+            End::Branch { target, next } if target == next => vec![target],
+            End::Branch { target, next } => vec![target, next],
+            _ => vec![],
+        }
+    }
+}
+
+/// Adds the slot `step` writes to `slots`, the result slot for calls of functions with a result.
+fn define(functions: &BTreeMap<usize, Function>, step: &Step, slots: &mut BTreeSet<i64>) {
+    let c = match step.offset {
+        Some(c) => c,
+        None => return,
+    };
+    if let Some(Param::Relative(o)) = step.instruction.write() {
+        slots.insert(c + o);
+    }
+    if let Flow::Call(callee) = step.flow {
+        if returns_value(functions, callee) {
+            slots.insert(c + 1);
+        }
+    }
+}
+
+/// The slots written on every path to each block of `function`.
+fn entered_slots(
+    functions: &BTreeMap<usize, Function>,
+    function: &Function,
+) -> BTreeMap<usize, BTreeSet<i64>> {
+    let mut entered: BTreeMap<usize, BTreeSet<i64>> = BTreeMap::new();
+    entered.insert(function.entry, BTreeSet::new());
+    let mut pending = vec![function.entry];
+    while let Some(leader) = pending.pop() {
+        let mut slots = entered[&leader].clone();
+        for step in function.blocks[&leader]
+            .steps
+            .iter()
+            .map(|a| &function.steps[a])
+        {
+            define(functions, step, &mut slots);
+        }
+        for successor in function.successors(leader) {
+            let known = match entered.get(&successor) {
+                Some(known) => known.intersection(&slots).cloned().collect(),
+                None => slots.clone(),
+            };
+            if entered.get(&successor) != Some(&known) {
+                entered.insert(successor, known);
+                pending.push(successor);
+            }
+        }
+    }
+    entered
+}
+
+fn callee_name(entry: usize) -> String {
+    match entry {
+        0 => "main".to_string(),
+        entry => format!("func_{}", entry),
+    }
+}
+
+impl Program {
+    fn new(code: &[i64]) -> Program {
+        // a first pass without overwritten cells finds the writes, and the functions which are
+        // only called through pointers later on
+        let first = Self::discover(code, &BTreeSet::new(), vec![0]);
+        let written: BTreeSet<i64> = first
+            .values()
+            .flat_map(|f| f.steps.values())
+            .filter_map(|s| match s.instruction.write() {
+                Some(Param::Position(address)) => Some(address),
+                _ => None,
+            })
+            .collect();
+        let mut functions = Self::discover(code, &written, first.keys().cloned().collect());
+
+        for function in functions
+            .values_mut()
+            .filter(|function| function.entry != 0)
+        {
+            function.params = function.arity();
+        }
+        let slots: Vec<_> = functions
+            .values()
+            .map(|function| {
+                let mut writes = BTreeSet::new();
+                for step in function.steps.values() {
+                    define(&functions, step, &mut writes);
+                }
+                (entered_slots(&functions, function), writes)
+            })
+            .collect();
+        for (function, (entered, writes)) in functions.values_mut().zip(slots) {
+            function.entered = entered;
+            function.writes = writes;
+        }
+        let mut cell_reads: BTreeMap<usize, BTreeSet<i64>> = BTreeMap::new();
+        for function in functions.values() {
+            let reads = cell_reads.entry(function.entry).or_default();
+            for step in function.steps.values() {
+                for param in step.instruction.reads() {
+                    if let Param::Position(address) = param {
+                        reads.insert(address);
+                    }
+                }
+            }
+        }
+
+        let mut callee_reads: BTreeMap<usize, Option<BTreeSet<i64>>> = cell_reads
+            .iter()
+            .map(|(entry, reads)| (*entry, Some(reads.clone())))
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for function in functions.values() {
+                let mut reads = callee_reads[&function.entry].clone();
+                for step in function.steps.values() {
+                    match step.flow {
+                        Flow::Call(Callee::Static(callee)) => {
+                            reads = match (reads, &callee_reads[&callee]) {
+                                (Some(reads), Some(other)) => Some(&reads | other),
+                                _ => None,
+                            }
+                        }
+                        Flow::Call(Callee::Indirect(_)) => reads = None,
+                        _ => {}
+                    }
+                }
+                if reads != callee_reads[&function.entry] {
+                    callee_reads.insert(function.entry, reads);
+                    changed = true;
+                }
+            }
+        }
+        Program {
+            written,
+            functions,
+            cell_reads,
+            callee_reads,
+        }
+    }
+
+    fn discover(
+        code: &[i64],
+        written: &BTreeSet<i64>,
+        mut pending: Vec<usize>,
+    ) -> BTreeMap<usize, Function> {
+        let mut functions = BTreeMap::new();
+        while let Some(entry) = pending.pop() {
+            if functions.contains_key(&entry) {
+                continue;
+            }
+            let function = Function::new(code, written, entry);
+            for step in function.steps.values() {
+                if let Flow::Call(Callee::Static(callee)) = step.flow {
+                    pending.push(callee);
+                }
+            }
+            functions.insert(entry, function);
+        }
+        functions
+    }
+
+    fn slot(&self, function: &Function, slot: i64) -> String {
+        if 1 <= slot && slot <= function.params {
+            format!("arg{}", slot)
+        } else if slot == 0 {
+            "return_address".to_string()
+        } else if slot < 0 {
+            format!("caller{}", -slot)
+        } else if slot < function.frame {
+            format!("var{}", slot)
+        } else if function.entry == 0 && !function.writes.contains(&slot) {
+            // main starts with the relative base at 0, a slot it never writes is the loaded cell
+            format!("mem[{}]", slot)
+        } else {
+            format!("tmp{}", slot - function.frame)
+        }
+    }
+
+    fn operand(&self, function: &Function, param: Param, offset: Option<i64>) -> String {
+        match (param, offset) {
+            (Param::Immediate(value), _) => value.to_string(),
+            (Param::Position(address), _) => format!("mem[{}]", address),
+            (Param::Relative(o), Some(c)) => self.slot(function, c + o),
+            (Param::Relative(o), None) if o < 0 => format!("mem[rb{}]", o),
+            (Param::Relative(o), None) => format!("mem[rb+{}]", o),
+        }
+    }
+
+    /// The value an arithmetic or comparison instruction writes.
+    fn value(&self, function: &Function, step: &Step) -> Option<String> {
+        let params = &step.instruction.params;
+        let operand = |i: usize| self.param(function, step, i);
+        let (a, b) = match step.instruction.opcode {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => {
+                (operand(0), operand(1))
+            }
+            _ => return None,
+        };
+        Some(match step.instruction.opcode {
+            Opcode::Add if b == "0" => a,
+            Opcode::Add if a == "0" => b,
+            Opcode::Add => match params[1] {
+                Param::Immediate(v) if v < 0 => format!("{} - {}", a, -(v as i128)),
+                _ => format!("{} + {}", a, b),
+            },
+            Opcode::Mul if b == "1" => a,
+            Opcode::Mul if a == "1" => b,
+            Opcode::Mul if b == "-1" => format!("-{}", a),
+            Opcode::Mul if a == "-1" => format!("-{}", b),
+            Opcode::Mul => format!("{} * {}", a, b),
+            Opcode::LessThan => format!("{} < {}", a, b),
+            _ => format!("{} == {}", a, b),
+        })
+    }
+
+    /// The statement for an instruction which neither jumps nor calls, `None` for relative base
+    /// adjustments with a known offset and jumps which are never taken.
+    fn statement(&self, function: &Function, step: &Step) -> Option<String> {
+        let params = &step.instruction.params;
+        let operand = |i: usize| self.param(function, step, i);
+        Some(match step.instruction.opcode {
+            Opcode::Input => format!("{} = read();", operand(0)),
+            Opcode::Output => format!("write({});", operand(0)),
+            Opcode::AdjustRelativeBase => match (params[0], step.offset) {
+                (Param::Immediate(_), Some(_)) => return None,
+                _ => format!("rb += {};", operand(0)),
+            },
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => match step.flow {
+                Flow::Next => return None,
+                _ => format!("goto {};", operand(1)),
+            },
+            Opcode::Halt => "halt();".to_string(),
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => {
+                let (target, value) = (operand(2), self.value(function, step)?);
+                if target == value {
+                    return None;
+                }
+                format!("{} = {};", target, value)
+            }
+        })
+    }
+
+    /// Parameter `i` of an instruction, reading it from memory if the program overwrites it.
+    fn param(&self, function: &Function, step: &Step, i: usize) -> String {
+        let cell = (step.instruction.address + 1 + i) as i64;
+        if !self.written.contains(&cell) {
+            return self.operand(function, step.instruction.params[i], step.offset);
+        }
+        match step.instruction.params[i] {
+            Param::Immediate(_) => format!("mem[{}]", cell),
+            Param::Position(_) => format!("mem[mem[{}]]", cell),
+            Param::Relative(_) => format!("mem[rb + mem[{}]]", cell),
+        }
+    }
+
+    fn call(&self, function: &Function, step: &Step, args: Vec<String>) -> String {
+        let callee = match step.flow {
+            Flow::Call(callee) => callee,
+            _ => unreachable!("not a call"),
+        };
+        let call = match callee {
+            Callee::Static(entry) => format!("{}({})", callee_name(entry), args.join(", ")),
+            Callee::Indirect(Param::Immediate(_)) => {
+                format!("(*{})({})", self.param(function, step, 1), args.join(", "))
+            }
+            Callee::Indirect(target) => {
+                let target = self.operand(function, target, step.offset);
+                format!("(*{})({})", target, args.join(", "))
+            }
+        };
+        if returns_value(&self.functions, callee) {
+            let result = self.operand(function, Param::Relative(1), step.offset);
+            format!("{} = {};", result, call)
+        } else {
+            format!("{};", call)
+        }
+    }
+
+    /// Statements of a block apart from its end. Arguments written right before a call are
+    /// folded into the call.
+    fn statements(&self, function: &Function, leader: usize) -> Vec<Stmt> {
+        let block = &function.blocks[&leader];
+        let mut defined = function.entered.get(&leader).cloned().unwrap_or_default();
+        let mut stmts = vec![];
+        // pure values written to argument slots since the last statement of another kind
+        let mut folded: Vec<(i64, String)> = vec![];
+        let mut outgoing = BTreeSet::new();
+        for (i, step) in block.steps.iter().map(|a| &function.steps[a]).enumerate() {
+            let before_call = block
+                .steps
+                .get(i + 1)
+                .is_some_and(|a| matches!(function.steps[a].flow, Flow::Call(_)));
+            let written = defined.clone();
+            define(&self.functions, step, &mut defined);
+            if before_call && step.instruction.write() == Some(Param::Relative(0)) {
+                continue;
+            }
+            match step.flow {
+                Flow::Call(callee) => {
+                    let mut arity = match callee {
+                        Callee::Static(entry) => self.functions[&entry].params,
+                        Callee::Indirect(_) => contiguous(&outgoing),
+                    };
+                    // trailing slots the caller may not have written are only read by accident
+                    if let Some(c) = step.offset {
+                        while arity > 0 && !written.contains(&(c + arity)) {
+                            arity -= 1;
+                        }
+                    }
+                    // folded values of slots the callee does not read or which are read again
+                    // stay statements
+                    let start = stmts.len() - folded.len();
+                    let result = returns_value(&self.functions, callee);
+                    let mut values = BTreeMap::new();
+                    let mut kept = vec![];
+                    for ((k, value), stmt) in folded.drain(..).zip(stmts.drain(start..)) {
+                        let read_again = !(result && k == 1)
+                            && step
+                                .offset
+                                .is_none_or(|c| self.slot_live(function, leader, i + 1, c + k));
+                        if k <= arity && !read_again {
+                            values.insert(k, value);
+                        } else {
+                            kept.push(stmt);
+                        }
+                    }
+                    stmts.extend(kept);
+                    let args = (1..=arity)
+                        .map(|k| match values.get(&k) {
+                            Some(value) => value.clone(),
+                            None => self.operand(function, Param::Relative(k), step.offset),
+                        })
+                        .collect();
+                    outgoing.clear();
+                    if returns_value(&self.functions, callee) {
+                        outgoing.insert(1);
+                    }
+                    stmts.push(Stmt::Line(self.call(function, step, args)));
+                }
+                Flow::Jump {
+                    condition: Some((_, nonzero)),
+                    target: None,
+                } => {
+                    folded.clear();
+                    let cond = self.condition(function, step, nonzero);
+                    let target = self.param(function, step, 1);
+                    stmts.push(Stmt::Line(format!("if ({}) goto *{};", cond, target)));
+                }
+                Flow::Next => {
+                    if step.instruction.opcode == Opcode::AdjustRelativeBase {
+                        folded.clear();
+                        outgoing.clear();
+                    }
+                    let line = match self.statement(function, step) {
+                        Some(line) => line,
+                        None => continue,
+                    };
+                    let reads_outgoing = step
+                        .instruction
+                        .reads()
+                        .any(|p| matches!(p, Param::Relative(j) if j >= 1));
+                    match (step.instruction.write(), self.value(function, step)) {
+                        (Some(Param::Relative(k)), Some(value)) if k >= 1 && !reads_outgoing => {
+                            outgoing.insert(k);
+                            folded.push((k, value));
+                        }
+                        (Some(Param::Relative(k)), _) if k >= 1 => {
+                            outgoing.insert(k);
+                            folded.clear();
+                        }
+                        _ => folded.clear(),
+                    }
+                    stmts.push(Stmt::Line(line));
+                }
+                _ => {}
+            }
+        }
+        stmts
+    }
+
+    /// Whether `slot` may be read before it is written, from step `index` of the block at
+    /// `leader` on. Calls read the slots of their arguments.
+    fn slot_live(&self, function: &Function, leader: usize, index: usize, slot: i64) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![(leader, index)];
+        'blocks: while let Some((leader, index)) = pending.pop() {
+            let block = &function.blocks[&leader];
+            for step in block.steps[index..].iter().map(|a| &function.steps[a]) {
+                let k = match step.offset {
+                    Some(c) => slot - c,
+                    None => return true,
+                };
+                if step.instruction.reads().any(|p| p == Param::Relative(k)) {
+                    return true;
+                }
+                if let Flow::Call(callee) = step.flow {
+                    let arity = match callee {
+                        Callee::Static(entry) => self.functions[&entry].params,
+                        Callee::Indirect(_) => i64::MAX,
+                    };
+                    if 1 <= k && k <= arity {
+                        return true;
+                    }
+                    if k == 1 && returns_value(&self.functions, callee) {
+                        continue 'blocks;
+                    }
+                }
+                if step.instruction.write() == Some(Param::Relative(k)) {
+                    continue 'blocks;
+                }
+            }
+            match block.end {
+                End::Dynamic => return true,
+                End::Return if function.returns_value && slot == 1 => return true,
+                _ => {}
+            }
+            for successor in function.successors(leader) {
+                if visited.insert(successor) {
+                    pending.push((successor, 0));
+                }
+            }
+        }
+        false
+    }
+
+    fn condition(&self, function: &Function, jump: &Step, nonzero: bool) -> Cond {
+        let cond = Cond {
+            lhs: self.param(function, jump, 0),
+            op: "!=",
+            rhs: "0".to_string(),
+        };
+        if nonzero {
+            cond
+        } else {
+            cond.negate()
+        }
+    }
+
+    /// Whether `name`, the position mode `cell` if it is one, may be read after leaving a block
+    /// for `starts` before it is written.
+    fn live(&self, function: &Function, starts: Vec<usize>, name: &str, cell: Option<i64>) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut pending = starts;
+        'blocks: while let Some(start) = pending.pop() {
+            if !visited.insert(start) {
+                continue;
+            }
+            let block = &function.blocks[&start];
+            for step in block.steps.iter().map(|a| &function.steps[a]) {
+                let write = step.instruction.opcode.write_param();
+                let mut reads = (0..step.instruction.params.len()).filter(|i| Some(*i) != write);
+                if reads.any(|i| self.param(function, step, i) == name) {
+                    return true;
+                }
+                if let Flow::Call(callee) = step.flow {
+                    let reads = match callee {
+                        Callee::Static(entry) => self.callee_reads[&entry].as_ref(),
+                        Callee::Indirect(_) => None,
+                    };
+                    let reads_cell = match (cell, reads) {
+                        (Some(cell), Some(reads)) => reads.contains(&cell),
+                        (Some(_), None) => true,
+                        (None, _) => false,
+                    };
+                    if reads_cell || name.starts_with("tmp") {
+                        return true;
+                    }
+                }
+                if write.is_some_and(|i| self.param(function, step, i) == name) {
+                    continue 'blocks;
+                }
+            }
+            match block.end {
+                End::Dynamic => return true,
+                End::Return => {
+                    let read_by_others = cell.is_some_and(|cell| {
+                        self.cell_reads
+                            .iter()
+                            .any(|(entry, reads)| *entry != function.entry && reads.contains(&cell))
+                    });
+                    let result = function.returns_value && self.slot(function, 1) == name;
+                    if read_by_others || result {
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+            pending.extend(function.successors(start));
+        }
+        false
+    }
+
+    /// The condition of the branch ending `block`, folding the comparison computing it if its
+    /// result is not used anywhere else.
+    fn branch_condition(&self, function: &Function, start: usize, stmts: &mut Vec<Stmt>) -> Cond {
+        let block = &function.blocks[&start];
+        let jump = &function.steps[block.steps.last().expect("branch")];
+        let (param, nonzero) = match jump.flow {
+            Flow::Jump {
+                condition: Some(condition),
+                ..
+            } => condition,
+            _ => unreachable!("branch without condition"),
+        };
+        let compare = block
+            .steps
+            .len()
+            .checked_sub(2)
+            .map(|i| &function.steps[&block.steps[i]])
+            .filter(|s| matches!(s.instruction.opcode, Opcode::LessThan | Opcode::Equals));
+        if let Some(compare) = compare {
+            let name = self.param(function, jump, 0);
+            let written = self.param(function, compare, 2);
+            let unused = match param {
+                Param::Position(address) => {
+                    !self.live(function, function.successors(start), &name, Some(address))
+                }
+                Param::Relative(_) if jump.offset.is_some() => {
+                    !self.live(function, function.successors(start), &name, None)
+                }
+                _ => false,
+            };
+            if written == name && unused {
+                stmts.pop();
+                let operand = |i: usize| self.param(function, compare, i);
+                let op = match compare.instruction.opcode {
+                    Opcode::LessThan => "<",
+                    _ => "==",
+                };
+                let cond = Cond {
+                    lhs: operand(0),
+                    op,
+                    rhs: operand(1),
+                };
+                return if nonzero { cond } else { cond.negate() };
+            }
+        }
+        self.condition(function, jump, nonzero)
+    }
+
+    fn render(&self, function: &Function) -> String {
+        let mut structurer = Structurer::new(self, function);
+        let body = structurer.region(function.entry, None, &Context::default());
+        let body = tidy(body, &structurer.gotos);
+
+        let kind = if function.returns_value && function.entry != 0 {
+            "int"
+        } else {
+            "void"
+        };
+        let args: Vec<String> = (1..=function.params)
+            .map(|k| self.slot(function, k))
+            .collect();
+        let mut text = format!(
+            "{} {}({}) {{\n",
+            kind,
+            callee_name(function.entry),
+            args.join(", ")
+        );
+        write_statements(&mut text, &body, 1);
+        text += "}\n";
+        text
+    }
+}
+
+/// Whether a call leaves a result in `[rb+1]`, which is assumed for calls through pointers.
+fn returns_value(functions: &BTreeMap<usize, Function>, callee: Callee) -> bool {
+    match callee {
+        Callee::Static(entry) => functions.get(&entry).is_some_and(|f| f.returns_value),
+        Callee::Indirect(_) => true,
+    }
+}
+
+/// Number of argument slots written without a gap, starting at 1.
+fn contiguous(slots: &BTreeSet<i64>) -> i64 {
+    (1..).take_while(|k| slots.contains(k)).count() as i64
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Context {
+    /// Header and follow block of the innermost loop.
+    header: Option<usize>,
+    follow: Option<usize>,
+}
+
+struct Structurer<'p> {
+    program: &'p Program,
+    function: &'p Function,
+    ipdom: BTreeMap<usize, usize>,
+    /// Loop headers with the block behind the loop.
+    loops: BTreeMap<usize, Option<usize>>,
+    done: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+}
+
+/// Solves `set(n) = {n} ∪ ⋂ set(m)` over the `edges` of every node, with the `roots` only
+/// containing themselves. Gives dominators on predecessor edges and post-dominators on successor
+/// edges.
+fn dominators(
+    nodes: &BTreeSet<usize>,
+    roots: &BTreeSet<usize>,
+    edges: &BTreeMap<usize, Vec<usize>>,
+) -> BTreeMap<usize, BTreeSet<usize>> {
+    let mut sets: BTreeMap<usize, BTreeSet<usize>> = nodes
+        .iter()
+        .map(|&n| {
+            let set = if roots.contains(&n) {
+                [n].iter().cloned().collect()
+            } else {
+                nodes.clone()
+            };
+            (n, set)
+        })
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in nodes.iter().filter(|n| !roots.contains(n)) {
+            let mut set = edges[&node]
+                .iter()
+                .map(|m| sets[m].clone())
+                .reduce(|a, b| a.intersection(&b).cloned().collect())
+                .unwrap_or_default();
+            set.insert(node);
+            if set != sets[&node] {
+                sets.insert(node, set);
+                changed = true;
+            }
+        }
+    }
+    sets
+}
+
+/// The closest strict dominator of each node, for the result of [`dominators`].
+fn immediate(sets: &BTreeMap<usize, BTreeSet<usize>>) -> BTreeMap<usize, usize> {
+    sets.iter()
+        .filter_map(|(node, set)| {
+            let strict: BTreeSet<usize> = set.iter().filter(|d| *d != node).cloned().collect();
+            strict
+                .iter()
+                .find(|d| sets[d] == strict)
+                .map(|d| (*node, *d))
+        })
+        .collect()
+}
+
+impl<'p> Structurer<'p> {
+    fn new(program: &'p Program, function: &'p Function) -> Self {
+        let nodes: BTreeSet<usize> = function.blocks.keys().cloned().collect();
+        let successors: BTreeMap<usize, Vec<usize>> =
+            nodes.iter().map(|&n| (n, function.successors(n))).collect();
+        let mut predecessors: BTreeMap<usize, Vec<usize>> =
+            nodes.iter().map(|&n| (n, vec![])).collect();
+        for (node, targets) in &successors {
+            for target in targets {
+                predecessors.get_mut(target).expect("block").push(*node);
+            }
+        }
+
+        let exits = nodes
+            .iter()
+            .filter(|n| successors[n].is_empty())
+            .cloned()
+            .collect();
+        let ipdom = immediate(&dominators(&nodes, &exits, &successors));
+        let entry = [function.entry].iter().cloned().collect();
+        let dom = dominators(&nodes, &entry, &predecessors);
+
+        let mut bodies: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for (&node, targets) in &successors {
+            for &header in targets.iter().filter(|h| dom[&node].contains(h)) {
+                let body = bodies
+                    .entry(header)
+                    .or_insert_with(|| [header].iter().cloned().collect());
+                let mut pending = vec![node];
+                while let Some(n) = pending.pop() {
+                    if body.insert(n) {
+                        pending.extend(predecessors[&n].iter().cloned());
+                    }
+                }
+            }
+        }
+        let loops = bodies
+            .iter()
+            .map(|(header, body)| {
+                let exits: BTreeSet<usize> = body
+                    .iter()
+                    .flat_map(|n| successors[n].iter())
+                    .filter(|s| !body.contains(s))
+                    .cloned()
+                    .collect();
+                let follow = match ipdom.get(header) {
+                    Some(d) if exits.contains(d) => Some(*d),
+                    _ => exits.iter().next().cloned(),
+                };
+                (*header, follow)
+            })
+            .collect();
+
+        Structurer {
+            program,
+            function,
+            ipdom,
+            loops,
+            done: BTreeSet::new(),
+            gotos: BTreeSet::new(),
+        }
+    }
+
+    /// Structures the blocks from `start` until `stop`.
+    fn region(&mut self, start: usize, stop: Option<usize>, context: &Context) -> Vec<Stmt> {
+        let mut stmts = vec![];
+        let mut current = Some(start);
+        while let Some(address) = current {
+            if Some(address) == stop {
+                break;
+            }
+            if Some(address) == context.follow {
+                stmts.push(Stmt::Break);
+                break;
+            }
+            if Some(address) == context.header && self.done.contains(&address) {
+                stmts.push(Stmt::Continue);
+                break;
+            }
+            if self.done.contains(&address) {
+                self.gotos.insert(address);
+                stmts.push(Stmt::Goto(address));
+                break;
+            }
+            if let Some(&follow) = self.loops.get(&address) {
+                if context.header != Some(address) {
+                    let inner = Context {
+                        header: Some(address),
+                        follow,
+                    };
+                    let body = self.region(address, None, &inner);
+                    stmts.push(Stmt::Loop { cond: None, body });
+                    current = follow;
+                    continue;
+                }
+            }
+            self.done.insert(address);
+            stmts.push(Stmt::Label(address));
+            current = self.block(address, stop, context, &mut stmts);
+        }
+        stmts
+    }
+
+    /// Adds the statements of a block and returns the block to continue with.
+    fn block(
+        &mut self,
+        start: usize,
+        stop: Option<usize>,
+        context: &Context,
+        stmts: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        let (program, function) = (self.program, self.function);
+        let block = &function.blocks[&start];
+        let mut lines = program.statements(function, start);
+        let last = block.steps.last().map(|a| &function.steps[a]);
+        match block.end {
+            End::Halt => lines.push(Stmt::Line("halt();".to_string())),
+            End::Return if function.returns_value => {
+                let result = program.slot(function, 1);
+                lines.push(Stmt::Line(format!("return {};", result)));
+            }
+            End::Return => lines.push(Stmt::Line("return;".to_string())),
+            End::Dynamic => {
+                let jump = last.expect("jump");
+                let target = program.param(function, jump, 1);
+                lines.push(Stmt::Line(format!("goto *{};", target)));
+            }
+            End::Error => {
+                let address = last.map_or(start, |s| s.instruction.next());
+                let error = &function.errors[&address];
+                lines.push(Stmt::Line(format!("/* {} at {} */", error, address)));
+            }
+            End::Goto(target) => {
+                stmts.extend(lines);
+                return Some(target);
+            }
+            End::Branch { target, next } => {
+                let cond = program.branch_condition(function, start, &mut lines);
+                stmts.extend(lines);
+                let merge = self.ipdom.get(&start).cloned().or(stop);
+                let taken = self.region(target, merge, context);
+                let fallthrough = self.region(next, merge, context);
+                stmts.push(Stmt::branch(cond.negate(), fallthrough, taken));
+                return merge;
+            }
+        }
+        stmts.extend(lines);
+        None
+    }
+}
+
+/// Drops labels nobody jumps to, turns loops starting with a conditional break into `while`
+/// loops and drops `continue` at the end of loops.
+fn tidy(stmts: Vec<Stmt>, gotos: &BTreeSet<usize>) -> Vec<Stmt> {
+    stmts
+        .into_iter()
+        .filter(|s| !matches!(s, Stmt::Label(a) if !gotos.contains(a)))
+        .map(|stmt| match stmt {
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            } => Stmt::branch(cond, tidy(then, gotos), tidy(otherwise, gotos)),
+            Stmt::Loop { cond: None, body } => tidy_loop(tidy(body, gotos)),
+            stmt => stmt,
+        })
+        .collect()
+}
+
+/// Turns a conditional break at the start of an endless loop into a `while` condition, and a
+/// conditional repetition at its end into a `do`-`while` condition.
+fn tidy_loop(mut body: Vec<Stmt>) -> Stmt {
+    strip_continue(&mut body);
+    let is_break = |stmt: &Stmt| match stmt {
+        Stmt::If {
+            cond,
+            then,
+            otherwise,
+        } if then == &[Stmt::Break] && otherwise.is_empty() => Some(cond.clone()),
+        _ => None,
+    };
+    if let Some(cond) = body.first().and_then(is_break) {
+        body.remove(0);
+        return Stmt::Loop {
+            cond: Some(cond.negate()),
+            body,
+        };
+    }
+    if let [Stmt::If {
+        cond,
+        then,
+        otherwise,
+    }, Stmt::Break] = &body[..]
+    {
+        if then.last() == Some(&Stmt::Continue) && otherwise.is_empty() {
+            let body = then[..then.len() - 1].to_vec();
+            return Stmt::Loop {
+                cond: Some(cond.clone()),
+                body,
+            };
+        }
+    }
+    if let Some(cond) = body.last().and_then(is_break) {
+        body.pop();
+        return Stmt::DoWhile {
+            cond: cond.negate(),
+            body,
+        };
+    }
+    let n = body.len();
+    if n >= 2 && body[n - 1] == Stmt::Break {
+        if let Stmt::If {
+            cond,
+            then,
+            otherwise,
+        } = &body[n - 2]
+        {
+            if then == &[Stmt::Continue] && otherwise.is_empty() {
+                let cond = cond.clone();
+                body.truncate(n - 2);
+                return Stmt::DoWhile { cond, body };
+            }
+        }
+    }
+    Stmt::Loop { cond: None, body }
+}
+
+fn strip_continue(stmts: &mut Vec<Stmt>) {
+    match stmts.last_mut() {
+        Some(Stmt::Continue) => {
+            stmts.pop();
+        }
+        Some(Stmt::If {
+            then, otherwise, ..
+        }) => {
+            strip_continue(then);
+            strip_continue(otherwise);
+            if let Some(Stmt::If {
+                cond,
+                then,
+                otherwise,
+            }) = stmts.pop()
+            {
+                stmts.push(Stmt::branch(cond, then, otherwise));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn write_statements(text: &mut String, stmts: &[Stmt], depth: usize) {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Line(line) => *text += &format!("{}{}\n", indent, line),
+            Stmt::Label(address) => *text += &format!("{}label_{}:\n", indent, address),
+            Stmt::Goto(address) => *text += &format!("{}goto label_{};\n", indent, address),
+            Stmt::Break => *text += &format!("{}break;\n", indent),
+            Stmt::Continue => *text += &format!("{}continue;\n", indent),
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                *text += &indent;
+                write_if(text, cond, then, otherwise, depth);
+            }
+            Stmt::Loop { cond, body } => {
+                let cond = cond.as_ref().map_or("1".to_string(), |c| c.to_string());
+                *text += &format!("{}while ({}) {{\n", indent, cond);
+                write_statements(text, body, depth + 1);
+                *text += &format!("{}}}\n", indent);
+            }
+            Stmt::DoWhile { cond, body } => {
+                *text += &format!("{}do {{\n", indent);
+                write_statements(text, body, depth + 1);
+                *text += &format!("{}}} while ({});\n", indent, cond);
+            }
+        }
+    }
+}
+
+fn write_if(text: &mut String, cond: &Cond, then: &[Stmt], otherwise: &[Stmt], depth: usize) {
+    let indent = "    ".repeat(depth);
+    *text += &format!("if ({}) {{\n", cond);
+    write_statements(text, then, depth + 1);
+    match otherwise {
+        [] => *text += &format!("{}}}\n", indent),
+        [Stmt::If {
+            cond,
+            then,
+            otherwise,
+        }] => {
+            *text += &format!("{}}} else ", indent);
+            write_if(text, cond, then, otherwise, depth);
+        }
+        _ => {
+            *text += &format!("{}}} else {{\n", indent);
+            write_statements(text, otherwise, depth + 1);
+            *text += &format!("{}}}\n", indent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn test_loop_and_call() {
+        // squares the inputs counting down from the input to 1 with a function at 32
+        let code = Machine::parse_code(
+            "109,100,3,200,1008,200,0,201,1005,201,31,21001,200,0,1,21101,22,0,0,1105,1,32,\
+             204,1,1001,200,-1,200,1105,1,4,99,109,2,22202,-1,-1,-1,109,-2,2105,1,0",
+        );
+        let expected = "void main() {
+    mem[200] = read();
+    while (mem[200] != 0) {
+        tmp1 = func_32(mem[200]);
+        write(tmp1);
+        mem[200] = mem[200] - 1;
+    }
+    halt();
+}
+
+int func_32(arg1) {
+    arg1 = arg1 * arg1;
+    return arg1;
+}
+";
+        assert_eq!(decompile(&code), expected);
+
+        let mut machine = Machine::new(code);
+        machine.add_input(3);
+        let _ = machine.run_until_block();
+        assert_eq!(machine.drain_output(), vec![9, 4, 1]);
+    }
+
+    #[test]
+    fn test_constant_jump_condition() {
+        // jumps which are never taken fall through to the halt
+        for code in &["1105,0,0,99", "1106,1,0,99"] {
+            assert_eq!(
+                decompile(&Machine::parse_code(code)),
+                "void main() {\n    halt();\n}\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_arity_of_callee() {
+        // the second call only writes the first argument and reuses the second, which stays a
+        // statement before the first call
+        let code = Machine::parse_code(
+            "109,100,21101,5,0,1,21101,6,0,2,21101,17,0,0,1105,1,33,204,1,\
+             21101,7,0,1,21101,30,0,0,1105,1,33,204,1,99,\
+             109,3,22201,-2,-1,-2,109,-3,2105,1,0",
+        );
+        let listing = decompile(&code);
+        assert!(listing.contains("tmp2 = 6;\n    tmp1 = func_33(5, tmp2);"));
+        assert!(listing.contains("tmp1 = func_33(7, tmp2);"));
+        assert!(listing.contains("int func_33(arg1, arg2) {"));
+
+        let mut machine = Machine::new(code);
+        let _ = machine.run_until_block();
+        assert_eq!(machine.drain_output(), vec![11, 13]);
+    }
+
+    #[test]
+    fn test_overwritten_operand() {
+        // the add sets the address the output reads from
+        let code = Machine::parse_code("1101,7,0,5,4,0,99");
+        assert_eq!(
+            decompile(&code),
+            "void main() {\n    mem[5] = 7;\n    write(mem[mem[5]]);\n    halt();\n}\n"
+        );
+    }
+
+    /// The temporaries of `listing` which are read before a function assigns them.
+    fn undefined_temporaries(listing: &str) -> Vec<String> {
+        let mut undefined = vec![];
+        let mut defined = BTreeSet::new();
+        for line in listing.lines().map(str::trim) {
+            if line.starts_with("int ") || line.starts_with("void ") {
+                defined.clear();
+            }
+            let (target, value) = match line.split_once(" = ") {
+                Some((target, value)) if target.starts_with("tmp") => (Some(target), value),
+                _ => (None, line),
+            };
+            let words = value.split(|c: char| !c.is_ascii_alphanumeric());
+            for word in words.filter(|word| word.starts_with("tmp")) {
+                if !defined.contains(word) {
+                    undefined.push(word.to_string());
+                }
+            }
+            defined.extend(target);
+        }
+        undefined
+    }
+
+    #[test]
+    fn test_puzzle_programs() {
+        for input in &[
+            include_str!("../../aoc-2019-9/input.txt"),
+            include_str!("../../aoc-2019-17/input.txt"),
+            include_str!("../../aoc-2019-19/input.txt"),
+        ] {
+            let listing = decompile(&Machine::parse_code(input));
+            assert_eq!(undefined_temporaries(&listing), Vec::<String>::new());
+        }
+        let listing = decompile(&Machine::parse_code(include_str!(
+            "../../aoc-2019-19/input.txt"
+        )));
+        // the call leaves out the fourth slot the callee reads, and keeps the third which the
+        // next call reads again
+        assert!(listing.contains("tmp3 = mem[221];\n    tmp1 = func_225(259, mem[221], tmp3);"));
+        assert!(listing.contains("int func_225(arg1, arg2, arg3, arg4) {"));
+    }
+}
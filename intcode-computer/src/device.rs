@@ -0,0 +1,356 @@
+//! Peripheral devices which exchange values with a [`Machine`].
+//!
+//! A [`Device`] receives every output of the machine and answers its input requests.
+//! [`Machine::run_device`] connects the two until the program halts or the device has no more
+//! input. The devices of the puzzles are here: the [`TileScreen`] and [`Joystick`] of the arcade
+//! cabinet, combined in the self-playing [`Arcade`], and the camera and drive of the hull
+//! [`PaintingRobot`].
+
+use crate::geometry::{Direction, Grid, Pos};
+use crate::{Machine, RunOutcome};
+
+pub trait Device {
+    /// Handles one output of the machine.
+    fn output(&mut self, value: i64);
+
+    /// Next input for the machine, `None` stops [`Machine::run_device`].
+    fn input(&mut self) -> Option<i64>;
+}
+
+impl Machine {
+    /// Runs the machine with `device` attached until the program halts or the device returns no
+    /// input. Outputs are passed on whenever the machine blocks. Panics on any
+    /// [`MachineError`](crate::MachineError) like [`Machine::step`].
+    pub fn run_device<D: Device + ?Sized>(&mut self, device: &mut D) -> RunOutcome {
+        loop {
+            let outcome = self.run_until_block();
+            for value in self.drain_output() {
+                device.output(value);
+            }
+            match outcome {
+                RunOutcome::Halted(_) => return outcome,
+                RunOutcome::NeedsInput => match device.input() {
+                    Some(value) => self.add_input(value),
+                    None => return outcome,
+                },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Tile {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+
+impl Tile {
+    pub fn from_id(id: i64) -> Option<Tile> {
+        match id {
+            0 => Some(Tile::Empty),
+            1 => Some(Tile::Wall),
+            2 => Some(Tile::Block),
+            3 => Some(Tile::Paddle),
+            4 => Some(Tile::Ball),
+            _ => None,
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Wall => '#',
+            Tile::Block => '=',
+            Tile::Paddle => '-',
+            Tile::Ball => 'o',
+        }
+    }
+}
+
+/// Screen drawing `x, y, tile` triples. The triple at `-1, 0` sets the score instead.
+#[derive(Debug, Clone, Default)]
+pub struct TileScreen {
+    tiles: Grid<Tile>,
+    score: Option<i64>,
+    ball: Option<Pos>,
+    paddle: Option<Pos>,
+    pending: Vec<i64>,
+}
+
+impl TileScreen {
+    pub fn new() -> TileScreen {
+        TileScreen::default()
+    }
+
+    pub fn tile(&self, pos: Pos) -> Tile {
+        *self.tiles.get(pos).unwrap_or(&Tile::Empty)
+    }
+
+    pub fn count(&self, tile: Tile) -> usize {
+        self.tiles.iter().filter(|(_, t)| **t == tile).count()
+    }
+
+    pub fn score(&self) -> Option<i64> {
+        self.score
+    }
+
+    /// Position the ball was last drawn at.
+    pub fn ball(&self) -> Option<Pos> {
+        self.ball
+    }
+
+    /// Position the paddle was last drawn at.
+    pub fn paddle(&self) -> Option<Pos> {
+        self.paddle
+    }
+
+    /// Draws the tiles within their bounding box, one line per row.
+    pub fn render(&self) -> String {
+        self.tiles
+            .render(|tile| tile.unwrap_or(&Tile::Empty).symbol())
+    }
+}
+
+impl Device for TileScreen {
+    /// Panics on a tile id other than 0 to 4.
+    fn output(&mut self, value: i64) {
+        self.pending.push(value);
+        if self.pending.len() < 3 {
+            return;
+        }
+        let pos = Pos::new(self.pending[0], self.pending[1]);
+        let id = self.pending[2];
+        self.pending.clear();
+        if pos == Pos::new(-1, 0) {
+            self.score = Some(id);
+            return;
+        }
+        let tile = Tile::from_id(id).unwrap_or_else(|| panic!("Unknown tile {}", id));
+        match tile {
+            Tile::Ball => self.ball = Some(pos),
+            Tile::Paddle => self.paddle = Some(pos),
+            _ => {}
+        }
+        self.tiles.insert(pos, tile);
+    }
+
+    /// The screen has no input.
+    fn input(&mut self) -> Option<i64> {
+        None
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tilt {
+    Left = -1,
+    Neutral = 0,
+    Right = 1,
+}
+
+impl Tilt {
+    /// The tilt which moves from `x` towards `target`.
+    pub fn towards(x: i64, target: i64) -> Tilt {
+        match target.cmp(&x) {
+            std::cmp::Ordering::Less => Tilt::Left,
+            std::cmp::Ordering::Equal => Tilt::Neutral,
+            std::cmp::Ordering::Greater => Tilt::Right,
+        }
+    }
+}
+
+/// Input device reporting its tilt on every input request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Joystick {
+    pub tilt: Tilt,
+}
+
+impl Joystick {
+    pub fn new() -> Joystick {
+        Joystick {
+            tilt: Tilt::Neutral,
+        }
+    }
+}
+
+impl Default for Joystick {
+    fn default() -> Joystick {
+        Joystick::new()
+    }
+}
+
+impl Device for Joystick {
+    /// Outputs are ignored.
+    fn output(&mut self, _value: i64) {}
+
+    fn input(&mut self) -> Option<i64> {
+        Some(self.tilt as i64)
+    }
+}
+
+/// The arcade cabinet, its joystick follows the ball with the paddle.
+#[derive(Debug, Clone, Default)]
+pub struct Arcade {
+    pub screen: TileScreen,
+    pub joystick: Joystick,
+}
+
+impl Arcade {
+    pub fn new() -> Arcade {
+        Arcade::default()
+    }
+}
+
+impl Device for Arcade {
+    fn output(&mut self, value: i64) {
+        self.screen.output(value);
+    }
+
+    fn input(&mut self) -> Option<i64> {
+        if let (Some(ball), Some(paddle)) = (self.screen.ball(), self.screen.paddle()) {
+            self.joystick.tilt = Tilt::towards(paddle.x, ball.x);
+        }
+        self.joystick.input()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Color {
+    Black = 0,
+    White = 1,
+}
+
+/// Hull painting robot with a camera reporting the color of the panel below it. Outputs
+/// alternate between the color to paint and the turn, 0 for left and 1 for right, after which
+/// the robot moves one panel forward. It starts at `0, 0` facing up, towards negative `y`.
+#[derive(Debug, Clone)]
+pub struct PaintingRobot {
+    position: Pos,
+    direction: Direction,
+    panels: Grid<Color>,
+    start: Color,
+    color: Option<Color>,
+}
+
+impl PaintingRobot {
+    /// A robot on a black hull, except for the starting panel.
+    pub fn new(start: Color) -> PaintingRobot {
+        PaintingRobot {
+            position: Pos::ORIGIN,
+            direction: Direction::Up,
+            panels: Grid::sparse(),
+            start,
+            color: None,
+        }
+    }
+
+    pub fn position(&self) -> Pos {
+        self.position
+    }
+
+    pub fn color(&self, pos: Pos) -> Color {
+        match self.panels.get(pos) {
+            Some(color) => *color,
+            None if pos == Pos::ORIGIN => self.start,
+            None => Color::Black,
+        }
+    }
+
+    /// Number of panels painted at least once.
+    pub fn painted(&self) -> usize {
+        self.panels.len()
+    }
+
+    /// Draws the white panels within their bounding box, one line per row.
+    pub fn render(&self) -> String {
+        let mut white = Grid::sparse();
+        for (pos, _) in self.panels.iter().chain(Some((Pos::ORIGIN, &self.start))) {
+            if self.color(pos) == Color::White {
+                white.insert(pos, ());
+            }
+        }
+        white.render(|panel| if panel.is_some() { '#' } else { ' ' })
+    }
+}
+
+impl Device for PaintingRobot {
+    /// Panics on a color or turn other than 0 or 1.
+    fn output(&mut self, value: i64) {
+        match self.color.take() {
+            None => {
+                self.color = Some(match value {
+                    0 => Color::Black,
+                    1 => Color::White,
+                    _ => panic!("Unknown color {}", value),
+                });
+            }
+            Some(color) => {
+                if value != 0 && value != 1 {
+                    panic!("Unknown turn {}", value);
+                }
+                self.panels.insert(self.position, color);
+                self.direction = match value {
+                    0 => self.direction.turn_left(),
+                    _ => self.direction.turn_right(),
+                };
+                self.position = self.position.step(self.direction);
+            }
+        }
+    }
+
+    fn input(&mut self) -> Option<i64> {
+        Some(self.color(self.position) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_screen() {
+        // draws a wall, a ball and a paddle, sets the score and waits for the joystick
+        let code = Machine::parse_code(
+            "104,0,104,0,104,1,104,2,104,0,104,4,104,-1,104,0,104,12,3,50,104,1,104,1,104,3,99",
+        );
+        let mut machine = Machine::new(code);
+        let mut screen = TileScreen::new();
+        assert_eq!(machine.run_device(&mut screen), RunOutcome::NeedsInput);
+        assert_eq!(screen.count(Tile::Wall), 1);
+        assert_eq!(screen.ball(), Some(Pos::new(2, 0)));
+        assert_eq!(screen.paddle(), None);
+        assert_eq!(screen.score(), Some(12));
+
+        let mut joystick = Joystick::new();
+        joystick.tilt = Tilt::towards(5, 2);
+        assert_eq!(joystick.input(), Some(-1));
+        machine.add_input(joystick.input().unwrap());
+        assert!(matches!(
+            machine.run_device(&mut screen),
+            RunOutcome::Halted(_)
+        ));
+        assert_eq!(screen.paddle(), Some(Pos::new(1, 1)));
+        assert_eq!(screen.render(), "# o\n - \n");
+    }
+
+    #[test]
+    fn test_painting_robot() {
+        // the moves of the puzzle description, the camera inputs are discarded
+        let mut code = vec![];
+        for pair in &[(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)] {
+            code.extend(&[3, 100, 104, pair.0, 104, pair.1]);
+        }
+        code.push(99);
+        let mut machine = Machine::new(code);
+        let mut robot = PaintingRobot::new(Color::Black);
+        assert!(matches!(
+            machine.run_device(&mut robot),
+            RunOutcome::Halted(_)
+        ));
+        assert_eq!(robot.painted(), 6);
+        assert_eq!(robot.position(), Pos::new(0, -1));
+        assert_eq!(robot.render(), "  #\n  #\n## \n");
+    }
+}
@@ -0,0 +1,158 @@
+//! Memory dumps of a [`Machine`] and differences between them.
+
+use crate::Machine;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Cells per row of a dump.
+const ROW: u128 = 8;
+
+/// Copy of the memory of a machine, covering the program and every cell written beyond it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    cells: BTreeMap<u128, i64>,
+}
+
+/// A memory cell which differs between two snapshots.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub address: u128,
+    pub old: i64,
+    pub new: i64,
+}
+
+impl Machine {
+    pub fn snapshot(&self) -> Snapshot {
+        let mut cells: BTreeMap<u128, i64> = self
+            .state
+            .iter()
+            .enumerate()
+            .map(|(address, value)| (address as u128, value))
+            .collect();
+        cells.extend(self.extended_state.iter().map(|(a, v)| (*a, *v)));
+        Snapshot { cells }
+    }
+
+    /// Lists the cells which differ from `other`, `old` values are the ones of `self`.
+    pub fn diff(&self, other: &Machine) -> Vec<Change> {
+        self.snapshot().diff(&other.snapshot())
+    }
+}
+
+impl Snapshot {
+    pub fn get(&self, address: u128) -> i64 {
+        *self.cells.get(&address).unwrap_or(&0)
+    }
+
+    /// The copied cells ordered by address.
+    pub fn iter(&self) -> impl Iterator<Item = (u128, i64)> + '_ {
+        self.cells.iter().map(|(address, value)| (*address, *value))
+    }
+
+    pub fn diff(&self, other: &Snapshot) -> Vec<Change> {
+        let mut addresses: Vec<u128> = self
+            .cells
+            .keys()
+            .chain(other.cells.keys())
+            .cloned()
+            .collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        addresses
+            .into_iter()
+            .map(|address| Change {
+                address,
+                old: self.get(address),
+                new: other.get(address),
+            })
+            .filter(|change| change.old != change.new)
+            .collect()
+    }
+}
+
+fn printable(value: i64) -> char {
+    match value {
+        32..=126 => value as u8 as char,
+        _ => '.',
+    }
+}
+
+/// Prints rows of eight cells with their address and an ASCII column. Gaps in the extended
+/// memory are marked with `...`.
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .cells
+            .values()
+            .map(|v| v.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let mut previous_row = None;
+        let mut rows: Vec<u128> = self.cells.keys().map(|a| a / ROW * ROW).collect();
+        rows.dedup();
+        for row in rows {
+            if let Some(previous) = previous_row {
+                if row > previous + ROW {
+                    writeln!(f, "...")?;
+                }
+            }
+            previous_row = Some(row);
+
+            let values: Vec<i64> = (row..row + ROW).map(|a| self.get(a)).collect();
+            write!(f, "{:>8}:", row)?;
+            for value in &values {
+                write!(f, " {:>width$}", value, width = width)?;
+            }
+            let ascii: String = values.iter().map(|v| printable(*v)).collect();
+            writeln!(f, "  |{}|", ascii)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>8}: {} -> {}", self.address, self.old, self.new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump() {
+        let mut machine = Machine::new(Machine::parse_code("72,105,33,99,1,2,3,4,5"));
+        machine.set_state(100, -7);
+        let expected = "       0:  72 105  33  99   1   2   3   4  |Hi!c....|\n\
+                        \x20      8:   5   0   0   0   0   0   0   0  |........|\n\
+                        ...\n\
+                        \x20     96:   0   0   0   0  -7   0   0   0  |........|\n";
+        assert_eq!(machine.snapshot().to_string(), expected);
+    }
+
+    #[test]
+    fn test_diff() {
+        let code = Machine::parse_code("1101,2,3,5,99,0");
+        let before = Machine::new(code.clone());
+        let mut after = Machine::new(code);
+        let _ = after.run_until_block();
+        after.set_state(1000, 1);
+        assert_eq!(
+            before.diff(&after),
+            vec![
+                Change {
+                    address: 5,
+                    old: 0,
+                    new: 5
+                },
+                Change {
+                    address: 1000,
+                    old: 0,
+                    new: 1
+                }
+            ]
+        );
+        assert!(after.diff(&after).is_empty());
+    }
+}
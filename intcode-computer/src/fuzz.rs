@@ -0,0 +1,294 @@
+//! Building blocks for fuzzing [`Machine`] with arbitrary programs and input streams.
+//!
+//! The `intcode-fuzz` binary drives these: it seeds a corpus from the puzzle inputs, mutates the
+//! cases and minimizes every case which makes the machine panic.
+
+use crate::{Machine, MachineError, StepResult};
+use std::fmt;
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+/// Upper bound of executed instructions per case, fuzzed programs loop forever easily.
+pub const INSTRUCTION_LIMIT: usize = 100_000;
+/// Upper bound of memory cells written beyond the program.
+pub const MEMORY_LIMIT: usize = 4096;
+
+/// Values which tend to reach the interesting corners of the interpreter.
+const INTERESTING: &[i64] = &[
+    0,
+    1,
+    -1,
+    2,
+    3,
+    4,
+    99,
+    109,
+    203,
+    204,
+    1101,
+    1105,
+    1106,
+    21101,
+    22201,
+    i64::MAX,
+    i64::MIN,
+    i64::MAX / 2,
+    1 << 40,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub code: Vec<i64>,
+    pub input: Vec<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Halted,
+    NeedsInput,
+    Error(MachineError),
+    OutOfInstructions,
+}
+
+impl Case {
+    /// Parses a case stored as the program on the first line and the comma separated input on an
+    /// optional second line.
+    pub fn parse(text: &str) -> Option<Case> {
+        let mut lines = text.lines();
+        let code = Machine::try_parse_code(lines.next()?).ok()?;
+        let input = match lines.next() {
+            Some(line) if !line.trim().is_empty() => Machine::try_parse_code(line).ok()?,
+            _ => vec![],
+        };
+        Some(Case { code, input })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Option<Case> {
+        Self::parse(&fs::read_to_string(path).ok()?)
+    }
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |values: &[i64]| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        writeln!(f, "{}", join(&self.code))?;
+        writeln!(f, "{}", join(&self.input))
+    }
+}
+
+/// Runs a case within [`INSTRUCTION_LIMIT`] and [`MEMORY_LIMIT`]. A panic inside is a bug of the
+/// interpreter.
+pub fn execute(case: &Case) -> Outcome {
+    let mut machine = Machine::new(case.code.clone());
+    machine.set_memory_limit(Some(MEMORY_LIMIT));
+    for value in &case.input {
+        machine.add_input(*value);
+    }
+    for _ in 0..INSTRUCTION_LIMIT {
+        match machine.try_step() {
+            Ok(StepResult::Continue) => {}
+            Ok(StepResult::Halt(_)) => return Outcome::Halted,
+            Ok(StepResult::NeedsInput) => return Outcome::NeedsInput,
+            Err(error) => return Outcome::Error(error),
+        }
+    }
+    Outcome::OutOfInstructions
+}
+
+/// Returns whether executing the case panics. Install a silent panic hook to keep the output
+/// readable when calling this in a loop.
+pub fn panics(case: &Case) -> bool {
+    panic::catch_unwind(|| execute(case)).is_err()
+}
+
+/// Reads the `input.txt` of every given day directory which holds an Intcode program.
+pub fn seed_corpus<P: AsRef<Path>>(day_dirs: &[P]) -> Vec<Case> {
+    day_dirs
+        .iter()
+        .filter_map(|dir| {
+            let text = fs::read_to_string(dir.as_ref().join("input.txt")).ok()?;
+            let code = Machine::try_parse_code(&text).ok()?;
+            Some(Case {
+                code,
+                input: vec![],
+            })
+        })
+        .collect()
+}
+
+/// A xorshift generator, good enough to drive mutations and independent of any crate.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound.max(1) as u64) as usize
+    }
+
+    fn value(&mut self) -> i64 {
+        match self.below(4) {
+            0 => INTERESTING[self.below(INTERESTING.len())],
+            1 => self.next_u64() as i64,
+            _ => self.below(64) as i64 - 16,
+        }
+    }
+}
+
+/// Applies one random mutation to the case. `other` is spliced in by some mutations.
+pub fn mutate(case: &mut Case, other: &Case, rng: &mut Rng) {
+    let len = case.code.len();
+    match rng.below(7) {
+        0 if len > 0 => case.code[rng.below(len)] = rng.value(),
+        1 => case.code.insert(rng.below(len + 1), rng.value()),
+        2 if len > 1 => {
+            case.code.remove(rng.below(len));
+        }
+        3 if len > 0 => {
+            // flip a mode digit
+            let i = rng.below(len);
+            let digit = [100, 1000, 10000][rng.below(3)];
+            let mode = (case.code[i] / digit) % 10;
+            case.code[i] =
+                case.code[i].wrapping_add((rng.below(3) as i64 - mode).wrapping_mul(digit));
+        }
+        4 if !other.code.is_empty() => {
+            let start = rng.below(other.code.len());
+            let end = start + rng.below(other.code.len() - start) + 1;
+            let at = rng.below(len + 1);
+            case.code
+                .splice(at..at, other.code[start..end].iter().cloned());
+        }
+        5 => case.input.push(rng.value()),
+        _ if !case.input.is_empty() => {
+            let i = rng.below(case.input.len());
+            case.input[i] = rng.value();
+        }
+        _ => case.code.push(rng.value()),
+    }
+}
+
+/// Shrinks a case while `fails` still holds: drops chunks of code and input and moves values
+/// towards zero.
+pub fn minimize<F: FnMut(&Case) -> bool>(case: &Case, mut fails: F) -> Case {
+    let mut best = case.clone();
+    loop {
+        let before = best.clone();
+        best.code = shrink_list(&best, |c| &mut c.code, &mut fails);
+        best.input = shrink_list(&best, |c| &mut c.input, &mut fails);
+        for i in 0..best.code.len() {
+            for candidate in &[0, 1, best.code[i] / 2] {
+                if *candidate == best.code[i] {
+                    continue;
+                }
+                let mut smaller = best.clone();
+                smaller.code[i] = *candidate;
+                if fails(&smaller) {
+                    best = smaller;
+                    break;
+                }
+            }
+        }
+        if best == before {
+            return best;
+        }
+    }
+}
+
+fn shrink_list<F, L>(case: &Case, list: L, fails: &mut F) -> Vec<i64>
+where
+    F: FnMut(&Case) -> bool,
+    L: Fn(&mut Case) -> &mut Vec<i64>,
+{
+    let mut best = case.clone();
+    let mut chunk = list(&mut best).len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start < list(&mut best).len() {
+            let mut smaller = best.clone();
+            let values = list(&mut smaller);
+            let end = usize::min(start + chunk, values.len());
+            values.drain(start..end);
+            if fails(&smaller) {
+                best = smaller;
+            } else {
+                start += chunk;
+            }
+        }
+        chunk /= 2;
+    }
+    list(&mut best).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute_outcomes() {
+        let case = Case::parse("3,0,4,0,99\n7").unwrap();
+        assert_eq!(execute(&case), Outcome::Halted);
+        let case = Case::parse("3,0,4,0,99\n").unwrap();
+        assert_eq!(execute(&case), Outcome::NeedsInput);
+        let case = Case::parse("1105,1,0").unwrap();
+        assert_eq!(execute(&case), Outcome::OutOfInstructions);
+        let case = Case::parse("1101,1,1,-1,99").unwrap();
+        assert_eq!(
+            execute(&case),
+            Outcome::Error(MachineError::NegativeAddress { pc: 0, address: -1 })
+        );
+    }
+
+    #[test]
+    fn test_execute_bounds_memory() {
+        // writes to a huge address and then to ever new addresses below it
+        let case = Case::parse("109,9223372036854775807,21101,0,0,0,109,-1,1105,1,2").unwrap();
+        assert_eq!(
+            execute(&case),
+            Outcome::Error(MachineError::MemoryLimitExceeded {
+                pc: 2,
+                address: (i64::MAX - MEMORY_LIMIT as i64) as u128,
+            })
+        );
+    }
+
+    #[test]
+    fn test_execute_survives_mutations() {
+        let mut rng = Rng::new(2019);
+        let seed =
+            Case::parse("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99").unwrap();
+        let mut case = seed.clone();
+        for _ in 0..2000 {
+            mutate(&mut case, &seed, &mut rng);
+            let _ = execute(&case);
+            if case.code.len() > 100 {
+                case = seed.clone();
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimize() {
+        let case = Case::parse("1,0,0,0,1,2,3,1106,0,0,99\n1,2,3").unwrap();
+        let minimized = minimize(&case, |c| c.code.contains(&1106));
+        assert_eq!(minimized.code, vec![1106]);
+        assert!(minimized.input.is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+//! Positions, directions and grids for the maps of the puzzles.
+//!
+//! Coordinates follow the screen: `x` grows to the right and `y` downwards, so
+//! [`Direction::Up`] decreases `y`.
+
+use std::collections::HashMap;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Pos {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Pos {
+    pub const ORIGIN: Pos = Pos::new(0, 0);
+
+    pub const fn new(x: i64, y: i64) -> Self {
+        Pos { x, y }
+    }
+
+    pub fn manhattan(self, other: Pos) -> i64 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    pub fn step(self, direction: Direction) -> Pos {
+        self + direction.delta()
+    }
+
+    /// The four positions sharing an edge with this one, in the order of [`Direction::ALL`].
+    pub fn neighbours(self) -> impl Iterator<Item = Pos> {
+        Direction::ALL.iter().map(move |d| self.step(*d))
+    }
+
+    /// The eight positions sharing an edge or a corner with this one, clockwise from the top.
+    pub fn neighbours8(self) -> impl Iterator<Item = Pos> {
+        const DELTAS: [Pos; 8] = [
+            Pos::new(0, -1),
+            Pos::new(1, -1),
+            Pos::new(1, 0),
+            Pos::new(1, 1),
+            Pos::new(0, 1),
+            Pos::new(-1, 1),
+            Pos::new(-1, 0),
+            Pos::new(-1, -1),
+        ];
+        DELTAS.iter().map(move |d| self + *d)
+    }
+}
+
+impl Add for Pos {
+    type Output = Pos;
+
+    fn add(self, other: Pos) -> Pos {
+        Pos::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Pos {
+    type Output = Pos;
+
+    fn sub(self, other: Pos) -> Pos {
+        Pos::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl AddAssign for Pos {
+    fn add_assign(&mut self, other: Pos) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Pos {
+    fn sub_assign(&mut self, other: Pos) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<i64> for Pos {
+    type Output = Pos;
+
+    fn mul(self, factor: i64) -> Pos {
+        Pos::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl Neg for Pos {
+    type Output = Pos;
+
+    fn neg(self) -> Pos {
+        Pos::new(-self.x, -self.y)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    /// Clockwise from `Up`.
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    /// Parses `U`, `R`, `D`, `L` and the arrows `^`, `>`, `v`, `<`.
+    pub fn from_char(c: char) -> Option<Direction> {
+        match c {
+            'U' | '^' => Some(Direction::Up),
+            'R' | '>' => Some(Direction::Right),
+            'D' | 'v' => Some(Direction::Down),
+            'L' | '<' => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    pub fn turn_left(self) -> Direction {
+        Direction::ALL[(self as usize + 3) % 4]
+    }
+
+    pub fn turn_right(self) -> Direction {
+        Direction::ALL[(self as usize + 1) % 4]
+    }
+
+    pub fn reverse(self) -> Direction {
+        Direction::ALL[(self as usize + 2) % 4]
+    }
+
+    /// The position one step from the origin.
+    pub fn delta(self) -> Pos {
+        match self {
+            Direction::Up => Pos::new(0, -1),
+            Direction::Right => Pos::new(1, 0),
+            Direction::Down => Pos::new(0, 1),
+            Direction::Left => Pos::new(-1, 0),
+        }
+    }
+}
+
+/// Cells at positions, stored densely for rectangular maps or sparsely for cells scattered over
+/// an unknown area.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    cells: Cells<T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Cells<T> {
+    Dense {
+        min: Pos,
+        width: usize,
+        height: usize,
+        cells: Vec<T>,
+    },
+    Sparse(HashMap<Pos, T>),
+}
+
+impl<T> Grid<T> {
+    /// A rectangle of `width` times `height` cells from `min`, each set to `fill`.
+    pub fn dense(min: Pos, width: usize, height: usize, fill: T) -> Grid<T>
+    where
+        T: Clone,
+    {
+        Grid {
+            cells: Cells::Dense {
+                min,
+                width,
+                height,
+                cells: vec![fill; width * height],
+            },
+        }
+    }
+
+    /// A grid without cells, which grows with every insert.
+    pub fn sparse() -> Grid<T> {
+        Grid {
+            cells: Cells::Sparse(HashMap::new()),
+        }
+    }
+
+    /// Parses a text map into a dense grid from the origin, one line per row. `None` if the lines
+    /// differ in length.
+    pub fn parse(text: &str, mut cell: impl FnMut(char) -> T) -> Option<Grid<T>> {
+        let mut width = None;
+        let mut cells = vec![];
+        let mut height = 0;
+        for line in text.lines() {
+            let before = cells.len();
+            cells.extend(line.chars().map(&mut cell));
+            if *width.get_or_insert(cells.len() - before) != cells.len() - before {
+                return None;
+            }
+            height += 1;
+        }
+        Some(Grid {
+            cells: Cells::Dense {
+                min: Pos::ORIGIN,
+                width: width.unwrap_or(0),
+                height,
+                cells,
+            },
+        })
+    }
+
+    /// Parses a text map into a sparse grid, keeping the characters for which `cell` returns a
+    /// value.
+    pub fn parse_sparse(text: &str, mut cell: impl FnMut(char) -> Option<T>) -> Grid<T> {
+        let mut cells = HashMap::new();
+        for (y, line) in text.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if let Some(value) = cell(c) {
+                    cells.insert(Pos::new(x as i64, y as i64), value);
+                }
+            }
+        }
+        Grid {
+            cells: Cells::Sparse(cells),
+        }
+    }
+
+    fn index(&self, pos: Pos) -> Option<usize> {
+        match &self.cells {
+            Cells::Dense {
+                min, width, height, ..
+            } => {
+                let (x, y) = (pos.x - min.x, pos.y - min.y);
+                if (0..*width as i64).contains(&x) && (0..*height as i64).contains(&y) {
+                    Some(y as usize * width + x as usize)
+                } else {
+                    None
+                }
+            }
+            Cells::Sparse(_) => None,
+        }
+    }
+
+    pub fn get(&self, pos: Pos) -> Option<&T> {
+        match &self.cells {
+            Cells::Dense { cells, .. } => self.index(pos).map(|i| &cells[i]),
+            Cells::Sparse(cells) => cells.get(&pos),
+        }
+    }
+
+    pub fn get_mut(&mut self, pos: Pos) -> Option<&mut T> {
+        let index = self.index(pos);
+        match &mut self.cells {
+            Cells::Dense { cells, .. } => index.map(move |i| &mut cells[i]),
+            Cells::Sparse(cells) => cells.get_mut(&pos),
+        }
+    }
+
+    pub fn contains(&self, pos: Pos) -> bool {
+        self.get(pos).is_some()
+    }
+
+    /// Sets a cell and returns its previous value. Panics outside of a dense grid.
+    pub fn insert(&mut self, pos: Pos, value: T) -> Option<T> {
+        if let Cells::Sparse(cells) = &mut self.cells {
+            return cells.insert(pos, value);
+        }
+        let cell = self
+            .get_mut(pos)
+            .unwrap_or_else(|| panic!("{:?} is outside of the grid", pos));
+        Some(std::mem::replace(cell, value))
+    }
+
+    /// Number of cells.
+    pub fn len(&self) -> usize {
+        match &self.cells {
+            Cells::Dense { cells, .. } => cells.len(),
+            Cells::Sparse(cells) => cells.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The cells with their positions, row by row for dense grids and in no order for sparse ones.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (Pos, &T)> + '_> {
+        match &self.cells {
+            Cells::Dense {
+                min, width, cells, ..
+            } => Box::new(cells.iter().enumerate().map(move |(i, value)| {
+                let pos = Pos::new((i % width) as i64, (i / width) as i64);
+                (*min + pos, value)
+            })),
+            Cells::Sparse(cells) => Box::new(cells.iter().map(|(pos, value)| (*pos, value))),
+        }
+    }
+
+    /// Smallest and largest coordinates of the cells, `None` for an empty grid.
+    pub fn bounds(&self) -> Option<(Pos, Pos)> {
+        match &self.cells {
+            _ if self.is_empty() => None,
+            Cells::Dense {
+                min, width, height, ..
+            } => Some((*min, *min + Pos::new(*width as i64 - 1, *height as i64 - 1))),
+            Cells::Sparse(cells) => cells.keys().fold(None, |bounds, pos| {
+                let (min, max) = bounds.unwrap_or((*pos, *pos));
+                Some((
+                    Pos::new(min.x.min(pos.x), min.y.min(pos.y)),
+                    Pos::new(max.x.max(pos.x), max.y.max(pos.y)),
+                ))
+            }),
+        }
+    }
+
+    /// Draws the bounding box one line per row, `cell` gets `None` for gaps of sparse grids.
+    pub fn render(&self, cell: impl Fn(Option<&T>) -> char) -> String {
+        let mut lines = String::new();
+        if let Some((min, max)) = self.bounds() {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    lines.push(cell(self.get(Pos::new(x, y))));
+                }
+                lines.push('\n');
+            }
+        }
+        lines
+    }
+}
+
+impl<T> Default for Grid<T> {
+    fn default() -> Grid<T> {
+        Grid::sparse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pos_and_direction() {
+        let pos = Pos::new(3, -4);
+        assert_eq!(pos.manhattan(Pos::ORIGIN), 7);
+        assert_eq!(pos + Pos::new(1, 1) * 2 - Pos::new(5, 0), Pos::new(0, -2));
+        assert_eq!(-pos, Pos::new(-3, 4));
+        assert_eq!(pos.step(Direction::Up), Pos::new(3, -5));
+
+        let mut direction = Direction::Up;
+        for expected in &[Direction::Left, Direction::Down, Direction::Right] {
+            direction = direction.turn_left();
+            assert_eq!(direction, *expected);
+        }
+        assert_eq!(direction.turn_right(), Direction::Down);
+        assert_eq!(direction.reverse(), Direction::Left);
+        assert_eq!(Direction::from_char('v'), Some(Direction::Down));
+        assert_eq!(Pos::ORIGIN.neighbours().count(), 4);
+        assert!(Pos::ORIGIN
+            .neighbours8()
+            .all(|p| p.x.abs() <= 1 && p.y.abs() <= 1 && p != Pos::ORIGIN));
+    }
+
+    #[test]
+    fn test_dense_grid() {
+        let mut grid = Grid::parse("#..\n.#.\n", |c| c == '#').unwrap();
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid.get(Pos::new(1, 1)), Some(&true));
+        assert_eq!(grid.get(Pos::new(3, 0)), None);
+        assert_eq!(grid.insert(Pos::new(2, 1), true), Some(false));
+        assert_eq!(grid.bounds(), Some((Pos::ORIGIN, Pos::new(2, 1))));
+        let on: Vec<Pos> = grid.iter().filter(|(_, on)| **on).map(|(p, _)| p).collect();
+        assert_eq!(on, vec![Pos::new(0, 0), Pos::new(1, 1), Pos::new(2, 1)]);
+        assert_eq!(
+            grid.render(|on| if on == Some(&true) { '#' } else { '.' }),
+            "#..\n.##\n"
+        );
+        assert_eq!(Grid::parse("#..\n.#\n", |c| c), None);
+    }
+
+    #[test]
+    fn test_sparse_grid() {
+        let mut grid = Grid::parse_sparse("..#\n#..\n", |c| if c == '#' { Some(c) } else { None });
+        assert_eq!(grid.len(), 2);
+        grid.insert(Pos::new(-1, 3), 'x');
+        assert_eq!(grid.bounds(), Some((Pos::new(-1, 0), Pos::new(2, 3))));
+        assert_eq!(
+            grid.render(|c| *c.unwrap_or(&' ')),
+            "   #\n #  \n    \nx   \n"
+        );
+        assert!(Grid::<char>::sparse().bounds().is_none());
+    }
+}
@@ -0,0 +1,353 @@
+//! Binary program images.
+//!
+//! ```text
+//! magic    "ICIM"
+//! version  1 byte, currently 1
+//! flags    1 byte, bit 0: symbol table, bit 1: metadata
+//! code     varint count, then each word as zigzag varint
+//! symbols  varint count, then each name as string and its address as varint
+//! metadata varint count, then each key and value as string
+//! checksum CRC-32 of all previous bytes, 4 bytes little endian
+//! ```
+//!
+//! Varints are LEB128, 7 bits per byte with the low bits first. Zigzag maps words of small
+//! magnitude to small varints: 0, -1, 1, -2, ... become 0, 1, 2, 3, .... Strings are a varint
+//! length and UTF-8 bytes. Symbols and metadata are only present if their flag is set.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+pub const MAGIC: &[u8; 4] = b"ICIM";
+pub const VERSION: u8 = 1;
+
+const SYMBOLS: u8 = 1;
+const METADATA: u8 = 2;
+
+/// A named address in a program.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub name: String,
+    pub address: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Image {
+    pub code: Vec<i64>,
+    pub symbols: Vec<Symbol>,
+    /// Free-form entries like `source` for the file the program was converted from.
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownFlags(u8),
+    /// The image ends in the middle of a field.
+    Truncated,
+    /// A varint longer than 64 bits or a value which does not fit its field.
+    Overflow {
+        offset: usize,
+    },
+    InvalidUtf8 {
+        offset: usize,
+    },
+    ChecksumMismatch {
+        stored: u32,
+        computed: u32,
+    },
+    /// Bytes between the last field and the checksum.
+    TrailingBytes {
+        offset: usize,
+    },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::BadMagic => write!(f, "not a program image"),
+            ImageError::UnsupportedVersion(version) => {
+                write!(f, "unsupported image version {}", version)
+            }
+            ImageError::UnknownFlags(flags) => write!(f, "unknown image flags {:#04x}", flags),
+            ImageError::Truncated => write!(f, "truncated image"),
+            ImageError::Overflow { offset } => write!(f, "value too large at byte {}", offset),
+            ImageError::InvalidUtf8 { offset } => write!(f, "invalid UTF-8 at byte {}", offset),
+            ImageError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "checksum mismatch: stored {:08x}, computed {:08x}",
+                stored, computed
+            ),
+            ImageError::TrailingBytes { offset } => write!(f, "trailing bytes at {}", offset),
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+/// Whether `bytes` start like an image, as opposed to a program in text form.
+pub fn is_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// CRC-32 as used by zip and PNG.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_string(out: &mut Vec<u8>, string: &str) {
+    write_varint(out, string.len() as u64);
+    out.extend(string.as_bytes());
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, ImageError> {
+        let byte = *self.bytes.get(self.offset).ok_or(ImageError::Truncated)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, ImageError> {
+        let start = self.offset;
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                return Err(ImageError::Overflow { offset: start });
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ImageError::Overflow { offset: start })
+    }
+
+    /// A varint used as a count or address.
+    fn size(&mut self) -> Result<usize, ImageError> {
+        let offset = self.offset;
+        let value = self.varint()?;
+        if value > usize::MAX as u64 {
+            return Err(ImageError::Overflow { offset });
+        }
+        Ok(value as usize)
+    }
+
+    /// A count of items which take at least a byte each, which keeps allocations in bounds.
+    fn count(&mut self) -> Result<usize, ImageError> {
+        let count = self.size()?;
+        if count > self.bytes.len() - self.offset {
+            return Err(ImageError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, ImageError> {
+        let length = self.count()?;
+        let start = self.offset;
+        self.offset += length;
+        String::from_utf8(self.bytes[start..self.offset].to_vec())
+            .map_err(|_| ImageError::InvalidUtf8 { offset: start })
+    }
+}
+
+impl Image {
+    pub fn new(code: Vec<i64>) -> Image {
+        Image {
+            code,
+            ..Image::default()
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        let mut flags = 0;
+        if !self.symbols.is_empty() {
+            flags |= SYMBOLS;
+        }
+        if !self.metadata.is_empty() {
+            flags |= METADATA;
+        }
+        out.push(flags);
+        write_varint(&mut out, self.code.len() as u64);
+        for word in &self.code {
+            write_varint(&mut out, zigzag(*word));
+        }
+        if flags & SYMBOLS != 0 {
+            write_varint(&mut out, self.symbols.len() as u64);
+            for symbol in &self.symbols {
+                write_string(&mut out, &symbol.name);
+                write_varint(&mut out, symbol.address as u64);
+            }
+        }
+        if flags & METADATA != 0 {
+            write_varint(&mut out, self.metadata.len() as u64);
+            for (key, value) in &self.metadata {
+                write_string(&mut out, key);
+                write_string(&mut out, value);
+            }
+        }
+        let checksum = crc32(&out);
+        out.extend(&checksum.to_le_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+        if !is_image(bytes) {
+            return Err(ImageError::BadMagic);
+        }
+        if bytes.len() < MAGIC.len() + 2 + 4 {
+            return Err(ImageError::Truncated);
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 4);
+        let mut reader = Reader {
+            bytes: body,
+            offset: MAGIC.len(),
+        };
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(ImageError::UnsupportedVersion(version));
+        }
+        let stored = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        let computed = crc32(body);
+        if stored != computed {
+            return Err(ImageError::ChecksumMismatch { stored, computed });
+        }
+        let flags = reader.byte()?;
+        if flags & !(SYMBOLS | METADATA) != 0 {
+            return Err(ImageError::UnknownFlags(flags));
+        }
+
+        let mut image = Image::default();
+        let count = reader.count()?;
+        image.code.reserve(count);
+        for _ in 0..count {
+            image.code.push(unzigzag(reader.varint()?));
+        }
+        if flags & SYMBOLS != 0 {
+            for _ in 0..reader.count()? {
+                let name = reader.string()?;
+                let address = reader.size()?;
+                image.symbols.push(Symbol { name, address });
+            }
+        }
+        if flags & METADATA != 0 {
+            for _ in 0..reader.count()? {
+                let key = reader.string()?;
+                let value = reader.string()?;
+                image.metadata.insert(key, value);
+            }
+        }
+        if reader.offset != body.len() {
+            return Err(ImageError::TrailingBytes {
+                offset: reader.offset,
+            });
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut image = Image::new(vec![0, -1, 1, 63, -64, 64, i64::MAX, i64::MIN, 1105]);
+        assert_eq!(Image::decode(&image.encode()), Ok(image.clone()));
+
+        image.symbols.push(Symbol {
+            name: "main".to_string(),
+            address: 4,
+        });
+        image
+            .metadata
+            .insert("source".to_string(), "input.txt".to_string());
+        let bytes = image.encode();
+        assert!(is_image(&bytes));
+        assert_eq!(Image::decode(&bytes), Ok(image));
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_compact() {
+        // words below 64 take a byte, 99 takes two
+        let code = vec![
+            1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3, 2, 1, 10, 19, 99,
+        ];
+        let text = code.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        let bytes = Image::new(code).encode();
+        assert_eq!(bytes.len(), 4 + 2 + 1 + 22 + 4);
+        assert!(bytes.len() < text.join(",").len());
+    }
+
+    #[test]
+    fn test_corruption() {
+        let bytes = Image::new(vec![1101, 2, 3, 0, 99]).encode();
+        let mut corrupt = bytes.clone();
+        corrupt[8] ^= 1;
+        assert!(matches!(
+            Image::decode(&corrupt),
+            Err(ImageError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(Image::decode(b"1,2,3"), Err(ImageError::BadMagic));
+        // a word missing, with a valid checksum
+        let mut truncated = bytes[..bytes.len() - 5].to_vec();
+        truncated.extend(&crc32(&truncated).to_le_bytes());
+        assert_eq!(Image::decode(&truncated), Err(ImageError::Truncated));
+        let mut version = bytes;
+        version[4] = 2;
+        assert_eq!(
+            Image::decode(&version),
+            Err(ImageError::UnsupportedVersion(2))
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! A small JSON value type with parser and writer, shared by the binaries of the crate.
+//!
+//! Objects keep the order of their fields. [`Json`] displays as compact JSON text.
+
+use std::fmt;
+
+/// A JSON value. Numbers keep their text, so 64 bit integers stay exact.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn number(n: impl fmt::Display) -> Json {
+        Json::Number(n.to_string())
+    }
+
+    pub fn string(s: impl fmt::Display) -> Json {
+        Json::String(s.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    index: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.index < self.text.len() && self.text[self.index].is_ascii_whitespace() {
+            self.index += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.text.get(self.index) == Some(&c) {
+            self.index += 1;
+            Ok(())
+        } else {
+            Err(format!("expected {:?} at byte {}", c as char, self.index))
+        }
+    }
+
+    /// Consumes `c` if it comes next.
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.text.get(self.index) == Some(&c);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    /// The four hex digits of a `\u` escape.
+    fn code_unit(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.index..self.index + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| format!("invalid escape at byte {}", self.index))?;
+        self.index += 4;
+        Ok(digits)
+    }
+
+    pub fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+        loop {
+            let byte = *self
+                .text
+                .get(self.index)
+                .ok_or("unterminated string".to_string())?;
+            self.index += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.text.get(self.index).cloned();
+                    self.index += 1;
+                    let c = match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut unit = self.code_unit()?;
+                            if (0xd800..0xdc00).contains(&unit)
+                                && self.text[self.index..].starts_with(b"\\u")
+                            {
+                                self.index += 2;
+                                let low = self.code_unit()?;
+                                unit =
+                                    0x10000 + ((unit - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(format!("invalid escape at byte {}", self.index - 1)),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        let rest = &self.text[self.index..];
+        for (literal, value) in &[
+            (&b"null"[..], Json::Null),
+            (b"true", Json::Bool(true)),
+            (b"false", Json::Bool(false)),
+        ] {
+            if rest.starts_with(literal) {
+                self.index += literal.len();
+                return Ok(value.clone());
+            }
+        }
+        match self.text.get(self.index) {
+            Some(b'{') => {
+                self.index += 1;
+                let mut fields = vec![];
+                if !self.eat(b'}') {
+                    loop {
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        fields.push((key, self.value()?));
+                        if !self.eat(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b'}')?;
+                }
+                Ok(Json::Object(fields))
+            }
+            Some(b'[') => {
+                self.index += 1;
+                let mut values = vec![];
+                if !self.eat(b']') {
+                    loop {
+                        values.push(self.value()?);
+                        if !self.eat(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b']')?;
+                }
+                Ok(Json::Array(values))
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            _ => {
+                let start = self.index;
+                while self.index < self.text.len()
+                    && matches!(
+                        self.text[self.index],
+                        b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+                    )
+                {
+                    self.index += 1;
+                }
+                let number = String::from_utf8_lossy(&self.text[start..self.index]).into_owned();
+                if number.parse::<f64>().is_err() {
+                    return Err(format!("expected a value at byte {}", start));
+                }
+                Ok(Json::Number(number))
+            }
+        }
+    }
+}
+
+/// Parses a complete JSON text.
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = JsonParser {
+        text: text.as_bytes(),
+        index: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.index < parser.text.len() {
+        return Err(format!("unexpected text at byte {}", parser.index));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text =
+            r#"{"seq":1,"big":9223372036854775807,"ok":true,"none":null,"list":[1.5,-2e3,"a\"b"]}"#;
+        let json = parse(text).unwrap();
+        assert_eq!(json.get("big").and_then(Json::as_i64), Some(i64::MAX));
+        assert_eq!(json.get("ok").and_then(Json::as_bool), Some(true));
+        assert_eq!(json.get("none"), Some(&Json::Null));
+        let list = json.get("list").and_then(Json::as_array).unwrap();
+        assert_eq!(list[1].as_f64(), Some(-2000.0));
+        assert_eq!(list[2].as_str(), Some("a\"b"));
+        assert_eq!(json.to_string(), text);
+    }
+
+    #[test]
+    fn test_escapes_and_errors() {
+        let json = parse(r#" "tab\t\u00e9\ud83d\ude00\n" "#).unwrap();
+        assert_eq!(json.as_str(), Some("tab\t\u{e9}\u{1f600}\n"));
+        assert_eq!(json.to_string(), "\"tab\\t\u{e9}\u{1f600}\\n\"");
+        assert!(parse("[1,").is_err());
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("1 2").is_err());
+    }
+}
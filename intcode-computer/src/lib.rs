@@ -1,237 +1,577 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::BTreeSet;
 use std::collections::VecDeque;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 use std::rc::Rc;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub struct Pos {
-    pub x: i64,
-    pub y: i64,
-}
+use debug::Frame;
+use loops::LoopDetector;
+use program::{Cells, Memory};
+use protection::{Guard, Protection};
+use symbols::{SymbolError, SymbolMap};
 
-impl Pos {
-    pub const fn new(x: i64, y: i64) -> Self {
-        Pos { x, y }
-    }
-}
+pub use geometry::Pos;
+pub use program::{Program, ProgramError};
+
+pub mod amplifier;
+pub mod analysis;
+pub mod compile;
+pub mod debug;
+pub mod decode;
+pub mod decompile;
+pub mod device;
+pub mod dump;
+pub mod fuzz;
+pub mod geometry;
+pub mod image;
+#[doc(hidden)]
+pub mod json;
+pub mod lint;
+pub mod loops;
+pub mod optimize;
+pub mod outputs;
+pub mod program;
+pub mod protection;
+pub mod search;
+pub mod server;
+pub mod symbolic;
+pub mod symbols;
 
 #[derive(Debug, Clone)]
 pub struct Machine {
     pc: usize,
-    state: Vec<i64>,
-    extended_state: HashMap<u128, i64>,
-    relative_base: usize,
+    program: Program,
+    state: Memory,
+    extended_state: Cells,
+    relative_base: i64,
     input: Rc<RefCell<VecDeque<i64>>>,
     output: Rc<RefCell<VecDeque<i64>>>,
+    memory_limit: Option<usize>,
+    instructions: u64,
+    inputs_consumed: u64,
+    outputs_produced: u64,
+    guard: Guard,
+    symbols: Option<Rc<SymbolMap>>,
+    loops: Option<Box<LoopDetector>>,
+    calls: Option<Vec<Frame>>,
+    breakpoints: BTreeSet<usize>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StepResult {
-    Halt(i64),
+    Halt(ExitStatus),
     NeedsInput,
     Continue,
 }
 
-impl Machine {
-    pub fn new(state: Vec<i64>) -> Machine {
-        Machine {
-            pc: 0,
-            state,
-            extended_state: HashMap::new(),
-            relative_base: 0,
-            input: Rc::new(RefCell::new(VecDeque::new())),
-            output: Rc::new(RefCell::new(VecDeque::new())),
+/// How a run of the machine ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    Halted(ExitStatus),
+    NeedsInput,
+}
+
+/// Statistics of a halted machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExitStatus {
+    /// Address of the halt instruction, the pc stays there.
+    pub halt_address: usize,
+    /// Instructions executed before the halt.
+    pub instructions: u64,
+    pub inputs_consumed: u64,
+    pub outputs_produced: u64,
+    /// Whether input was left in the queue.
+    pub input_pending: bool,
+}
+
+/// Errors detected while executing an instruction. `pc` is the address of that instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    UnknownOpcode {
+        pc: usize,
+        opcode: i64,
+    },
+    /// A mode digit other than 0, 1 or 2, or immediate mode on a write parameter.
+    InvalidMode {
+        pc: usize,
+        mode: u8,
+    },
+    NegativeAddress {
+        pc: usize,
+        address: i128,
+    },
+    Overflow {
+        pc: usize,
+    },
+    /// A write to memory beyond the program would exceed the limit set with
+    /// [`Machine::set_memory_limit`].
+    MemoryLimitExceeded {
+        pc: usize,
+        address: u128,
+    },
+    /// Access to a region added with [`Machine::protect`].
+    ProtectionViolation {
+        pc: usize,
+        address: u128,
+        protection: Protection,
+    },
+    /// The machine got into the same state as `period` instructions before without consuming
+    /// input, see [`Machine::detect_loops`]. `pc` is the address of the next instruction.
+    InfiniteLoop {
+        pc: usize,
+        period: u64,
+    },
+}
+
+impl MachineError {
+    /// Formats the error with `pc` for instruction addresses and `address` for data addresses.
+    pub(crate) fn write_with(
+        &self,
+        f: &mut fmt::Formatter,
+        pc: &dyn Fn(usize) -> String,
+        address: &dyn Fn(i128) -> String,
+    ) -> fmt::Result {
+        match self {
+            MachineError::UnknownOpcode { pc: at, opcode } => {
+                write!(f, "Unknown op code: {} at {}", opcode, pc(*at))
+            }
+            MachineError::InvalidMode { pc: at, mode } => {
+                write!(f, "Invalid mode {} at {}", mode, pc(*at))
+            }
+            MachineError::NegativeAddress { pc: at, address } => {
+                write!(f, "negativ address {} at {}", address, pc(*at))
+            }
+            MachineError::Overflow { pc: at } => write!(f, "integer overflow at {}", pc(*at)),
+            MachineError::MemoryLimitExceeded {
+                pc: at,
+                address: to,
+            } => write!(
+                f,
+                "memory limit exceeded writing {} at {}",
+                address(*to as i128),
+                pc(*at)
+            ),
+            MachineError::ProtectionViolation {
+                pc: at,
+                address: to,
+                protection,
+            } => write!(
+                f,
+                "{:?} violation accessing {} at {}",
+                protection,
+                address(*to as i128),
+                pc(*at)
+            ),
+            MachineError::InfiniteLoop { pc: at, period } => write!(
+                f,
+                "infinite loop at {}, the state repeats every {} instructions",
+                pc(*at),
+                period
+            ),
         }
     }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_with(f, &|pc| pc.to_string(), &|address| address.to_string())
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+impl Machine {
+    /// Loads a program, given as a `Vec<i64>` or a [`Program`] shared with other machines.
+    pub fn new(program: impl Into<Program>) -> Machine {
+        Self::new_with_in_out(
+            program,
+            Rc::new(RefCell::new(VecDeque::new())),
+            Rc::new(RefCell::new(VecDeque::new())),
+        )
+    }
 
     pub fn new_with_in_out(
-        state: Vec<i64>,
+        program: impl Into<Program>,
         input: Rc<RefCell<VecDeque<i64>>>,
         output: Rc<RefCell<VecDeque<i64>>>,
     ) -> Machine {
+        let program = program.into();
         Machine {
             pc: 0,
-            state,
-            extended_state: HashMap::new(),
+            state: Memory::new(&program),
+            program,
+            extended_state: Cells::default(),
             relative_base: 0,
             input,
             output,
+            memory_limit: None,
+            instructions: 0,
+            inputs_consumed: 0,
+            outputs_produced: 0,
+            guard: Guard::default(),
+            symbols: None,
+            loops: None,
+            calls: None,
+            breakpoints: BTreeSet::new(),
         }
     }
 
+    /// Reads a program in text form or as binary [`image::Image`].
     pub fn read_code<P: AsRef<Path>>(path: P) -> Result<Vec<i64>, std::io::Error> {
         let mut file = File::open(path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        if image::is_image(&contents) {
+            return image::Image::decode(&contents)
+                .map(|image| image.code)
+                .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error));
+        }
+        let contents = String::from_utf8(contents)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
         Ok(Self::parse_code(&contents))
     }
 
     pub fn parse_code(code: &str) -> Vec<i64> {
-        code.trim()
-            .split(',')
-            .map(|v| i64::from_str_radix(v, 10).expect("No integer"))
-            .collect()
+        Self::try_parse_code(code).expect("No integer")
+    }
+
+    pub fn try_parse_code(code: &str) -> Result<Vec<i64>, std::num::ParseIntError> {
+        code.trim().split(',').map(|v| v.trim().parse()).collect()
+    }
+
+    /// Limits the number of memory cells beyond the program which may be written. Without a
+    /// limit a program writing to ever new addresses grows `extended_state` without bound.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    /// Names addresses in the messages of [`Machine::describe_error`] and the panics of
+    /// [`Machine::step`]. Forks share the map.
+    pub fn set_symbols(&mut self, symbols: Option<SymbolMap>) {
+        self.symbols = symbols.map(Rc::new);
+    }
+
+    /// Reads a symbol map file, see [`symbols`].
+    pub fn load_symbols<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SymbolError> {
+        self.set_symbols(Some(SymbolMap::read(path)?));
+        Ok(())
+    }
+
+    pub fn symbols(&self) -> Option<&SymbolMap> {
+        self.symbols.as_deref()
+    }
+
+    /// The message of `error`, with names for its addresses if symbols are loaded.
+    pub fn describe_error(&self, error: &MachineError) -> String {
+        match &self.symbols {
+            Some(symbols) => symbols.describe_error(error),
+            None => error.to_string(),
+        }
     }
 
     pub fn get_mode_digits(mut instruction: i64) -> [u8; 3] {
         instruction /= 100;
         let mut modes = [0u8; 3];
-        for d in 0..3 {
-            modes[d] = (instruction % 10) as u8;
+        for mode in modes.iter_mut() {
+            *mode = (instruction % 10) as u8;
             instruction /= 10;
         }
         modes
     }
 
-    pub fn get_param(&self, mode: u8, value: i64) -> i64 {
-        if mode == 1 {
-            return value;
-        }
-        let location = if mode == 0 {
-            assert!(value >= 0, "negativ address");
-            value as u128
-        } else if mode == 2 {
-            let location = value as i128 + self.relative_base as i128;
-            assert!(location >= 0, "negativ address");
-            location as u128
-        } else {
-            panic!("Invalid mode");
-        };
-
+    fn read(&self, location: u128) -> i64 {
         if location >= self.state.len() as u128 {
             *self.extended_state.get(&location).unwrap_or(&0)
         } else {
-            self.state[location as usize]
+            self.state.get(location as usize)
+        }
+    }
+
+    /// Reads the parameter at `offset` behind the current instruction.
+    fn read_param(&self, offset: usize) -> i64 {
+        self.read(self.pc as u128 + offset as u128)
+    }
+
+    fn address(&self, mode: u8, value: i64) -> Result<u128, MachineError> {
+        let location = match mode {
+            0 => value as i128,
+            2 => value as i128 + self.relative_base as i128,
+            mode => return Err(self.invalid_mode(mode)),
+        };
+        if location < 0 {
+            return Err(self.negative_address(location));
+        }
+        Ok(location as u128)
+    }
+
+    // the errors are built out of line, keeping them off the path of every instruction
+
+    #[cold]
+    fn invalid_mode(&self, mode: u8) -> MachineError {
+        MachineError::InvalidMode { pc: self.pc, mode }
+    }
+
+    #[cold]
+    fn negative_address(&self, address: i128) -> MachineError {
+        MachineError::NegativeAddress {
+            pc: self.pc,
+            address,
+        }
+    }
+
+    #[cold]
+    fn overflow(&self) -> MachineError {
+        MachineError::Overflow { pc: self.pc }
+    }
+
+    #[cold]
+    fn unknown_opcode(&self, opcode: i64) -> MachineError {
+        MachineError::UnknownOpcode {
+            pc: self.pc,
+            opcode,
         }
     }
 
-    pub fn write_memory(&mut self, mode: u8, location: i64, value: i64) {
-        let location = if mode == 0 {
-            location as i128
-        } else if mode == 2 {
-            location as i128 + self.relative_base as i128
+    pub fn get_param(&self, mode: u8, value: i64) -> Result<i64, MachineError> {
+        if mode == 1 {
+            return Ok(value);
+        }
+        Ok(self.read(self.address(mode, value)?))
+    }
+
+    pub fn write_memory(
+        &mut self,
+        mode: u8,
+        location: i64,
+        value: i64,
+    ) -> Result<(), MachineError> {
+        let location = self.address(mode, location)?;
+        let old = if self.guard.is_active() {
+            self.check_write(location)?;
+            Some(self.read(location))
         } else {
-            panic!("Invalid mode");
+            None
         };
-        assert!(location >= 0, "negativ address");
-        let location = location as u128;
 
         if self.state.len() as u128 <= location {
+            if let Some(limit) = self.memory_limit {
+                if self.extended_state.len() >= limit
+                    && !self.extended_state.contains_key(&location)
+                {
+                    return Err(MachineError::MemoryLimitExceeded {
+                        pc: self.pc,
+                        address: location,
+                    });
+                }
+            }
+            self.note_write(location, value, false);
             self.extended_state.insert(location, value);
         } else {
-            let location = location as usize;
-            self.state[location] = value
+            self.note_write(location, value, false);
+            self.state.set(location as usize, value)
+        }
+        if let Some(old) = old {
+            self.record_write(location, old, value);
+        }
+        Ok(())
+    }
+
+    fn jump(&mut self, target: i64, mode: u8) -> Result<(), MachineError> {
+        if target < 0 {
+            return Err(self.negative_address(target as i128));
         }
+        if self.calls.is_some() {
+            self.note_jump(target as usize, mode == 2);
+        }
+        self.pc = target as usize;
+        Ok(())
     }
 
+    /// Executes one instruction, panicking on any [`MachineError`].
     pub fn step(&mut self) -> StepResult {
-        let instruction = self.state[self.pc];
+        match self.try_step() {
+            Ok(result) => result,
+            Err(error) => panic!("{}", self.describe_error(&error)),
+        }
+    }
+
+    pub fn try_step(&mut self) -> Result<StepResult, MachineError> {
+        let result = self.execute()?;
+        if let StepResult::Continue = result {
+            self.instructions += 1;
+            self.check_loop()?;
+        }
+        Ok(result)
+    }
+
+    fn execute(&mut self) -> Result<StepResult, MachineError> {
+        let instruction = self.read_param(0);
         let op = instruction % 100;
+        if self.guard.is_active() {
+            self.check_execute(op)?;
+        }
         let mode = Self::get_mode_digits(instruction);
         match op {
             1 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
-                let in2 = self.get_param(mode[1], self.state[self.pc + 2]);
-                let out = self.state[self.pc + 3];
-                self.write_memory(mode[2], out, in1 + in2);
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
+                let in2 = self.get_param(mode[1], self.read_param(2))?;
+                let out = self.read_param(3);
+                let sum = in1.checked_add(in2).ok_or_else(|| self.overflow())?;
+                self.write_memory(mode[2], out, sum)?;
                 self.pc += 4;
-                StepResult::Continue
+                Ok(StepResult::Continue)
             }
             2 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
-                let in2 = self.get_param(mode[1], self.state[self.pc + 2]);
-                let out = self.state[self.pc + 3];
-                self.write_memory(mode[2], out, in1 * in2);
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
+                let in2 = self.get_param(mode[1], self.read_param(2))?;
+                let out = self.read_param(3);
+                let product = in1.checked_mul(in2).ok_or_else(|| self.overflow())?;
+                self.write_memory(mode[2], out, product)?;
                 self.pc += 4;
-                StepResult::Continue
+                Ok(StepResult::Continue)
             }
             // input
             3 => {
                 if self.input.borrow().is_empty() {
-                    StepResult::NeedsInput
+                    Ok(StepResult::NeedsInput)
                 } else {
-                    let out = self.state[self.pc + 1];
+                    let out = self.read_param(1);
+                    // check the address before consuming the input
+                    self.address(mode[0], out)?;
                     let value = self.input.borrow_mut().pop_front().expect("input empty");
-                    self.write_memory(mode[0], out, value);
+                    self.write_memory(mode[0], out, value)?;
+                    self.inputs_consumed += 1;
                     self.pc += 2;
-                    StepResult::Continue
+                    Ok(StepResult::Continue)
                 }
             }
             // output
             4 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
                 self.output.borrow_mut().push_back(in1);
+                self.outputs_produced += 1;
                 self.pc += 2;
-                StepResult::Continue
+                Ok(StepResult::Continue)
             }
 
             // jump-if-true
             5 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
-                let in2 = self.get_param(mode[1], self.state[self.pc + 2]);
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
+                let in2 = self.get_param(mode[1], self.read_param(2))?;
                 if in1 != 0 {
-                    assert!(in2 >= 0, "address out of range");
-                    self.pc = in2 as usize;
+                    self.jump(in2, mode[1])?;
                 } else {
                     self.pc += 3;
                 }
-                StepResult::Continue
+                Ok(StepResult::Continue)
             }
             // jump-if-false
             6 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
-                let in2 = self.get_param(mode[1], self.state[self.pc + 2]);
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
+                let in2 = self.get_param(mode[1], self.read_param(2))?;
                 if in1 == 0 {
-                    assert!(in2 >= 0, "address out of range");
-                    self.pc = in2 as usize;
+                    self.jump(in2, mode[1])?;
                 } else {
                     self.pc += 3;
                 }
                 //
-                StepResult::Continue
+                Ok(StepResult::Continue)
             }
             // less than
             7 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
-                let in2 = self.get_param(mode[1], self.state[self.pc + 2]);
-                let out = self.state[self.pc + 3];
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
+                let in2 = self.get_param(mode[1], self.read_param(2))?;
+                let out = self.read_param(3);
 
                 if in1 < in2 {
-                    self.write_memory(mode[2], out, 1);
+                    self.write_memory(mode[2], out, 1)?;
                 } else {
-                    self.write_memory(mode[2], out, 0);
+                    self.write_memory(mode[2], out, 0)?;
                 }
                 self.pc += 4;
-                StepResult::Continue
+                Ok(StepResult::Continue)
             }
             // equals
             8 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
-                let in2 = self.get_param(mode[1], self.state[self.pc + 2]);
-                let out = self.state[self.pc + 3];
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
+                let in2 = self.get_param(mode[1], self.read_param(2))?;
+                let out = self.read_param(3);
                 if in1 == in2 {
-                    self.write_memory(mode[2], out, 1);
+                    self.write_memory(mode[2], out, 1)?;
                 } else {
-                    self.write_memory(mode[2], out, 0);
+                    self.write_memory(mode[2], out, 0)?;
                 }
                 self.pc += 4;
-                StepResult::Continue
+                Ok(StepResult::Continue)
             }
             // relative base offset
             9 => {
-                let in1 = self.get_param(mode[0], self.state[self.pc + 1]);
-                self.relative_base = (self.relative_base as i64 + in1) as usize;
+                let in1 = self.get_param(mode[0], self.read_param(1))?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(in1)
+                    .ok_or_else(|| self.overflow())?;
+                self.note_relative_base();
                 self.pc += 2;
-                StepResult::Continue
-            }
-            99 => StepResult::Halt(self.state[0]),
-            opcode => {
-                panic!("Unknown op code: {}", opcode);
+                Ok(StepResult::Continue)
             }
+            99 => Ok(StepResult::Halt(self.exit_status())),
+            opcode => Err(self.unknown_opcode(opcode)),
+        }
+    }
+
+    /// Copies the machine with its own input and output queues. Memory pages are shared until
+    /// either machine writes to them, which makes searches branching on machine states cheap.
+    pub fn fork(&self) -> Machine {
+        Machine {
+            input: Rc::new(RefCell::new(self.input.borrow().clone())),
+            output: Rc::new(RefCell::new(self.output.borrow().clone())),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the machine to the state right after loading its program, reusing its
+    /// allocations. The queues are emptied, also when they are shared with other machines.
+    /// Protection regions, the memory limit and the tracking of self-modification are kept.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.state.reset(&self.program);
+        self.extended_state.clear();
+        self.relative_base = 0;
+        self.input.borrow_mut().clear();
+        self.output.borrow_mut().clear();
+        self.instructions = 0;
+        self.inputs_consumed = 0;
+        self.outputs_produced = 0;
+        self.guard.reset();
+        if self.loops.is_some() {
+            self.detect_loops(true);
         }
+        if self.calls.is_some() {
+            self.track_calls(true);
+        }
+    }
+
+    /// Address of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /// Instructions executed so far.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// The program the machine was loaded with.
+    pub fn program(&self) -> &Program {
+        &self.program
     }
 
     pub fn add_input(&mut self, input: i64) {
@@ -250,51 +590,53 @@ impl Machine {
     }
 
     pub fn set_state(&mut self, address: usize, value: i64) {
-        self.state[address] = value;
+        self.note_write(address as u128, value, true);
+        if address < self.state.len() {
+            self.state.set(address, value);
+        } else {
+            self.extended_state.insert(address as u128, value);
+        }
     }
 
-    pub fn run(&mut self, noun: i64, verb: i64) -> i64 {
-        self.state[1] = noun;
-        self.state[2] = verb;
-        loop {
-            match self.step() {
-                StepResult::Halt(i) => {
-                    return i;
-                }
-                StepResult::NeedsInput => {
-                    panic!("Needs input");
-                }
-                _ => {}
-            }
+    pub fn get_state(&self, address: usize) -> i64 {
+        self.read(address as u128)
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        ExitStatus {
+            halt_address: self.pc,
+            instructions: self.instructions,
+            inputs_consumed: self.inputs_consumed,
+            outputs_produced: self.outputs_produced,
+            input_pending: !self.input.borrow().is_empty(),
         }
     }
 
-    pub fn run_with_input(&mut self, input: i64) -> i64 {
+    pub fn run(&mut self, noun: i64, verb: i64) -> RunOutcome {
+        self.set_state(1, noun);
+        self.set_state(2, verb);
+        self.run_until_block()
+    }
+
+    pub fn run_with_input(&mut self, input: i64) -> RunOutcome {
         self.add_input(input);
-        loop {
-            match self.step() {
-                StepResult::Halt(i) => {
-                    return i;
-                }
-                StepResult::NeedsInput => {
-                    panic!("Needs input");
-                }
-                _ => {}
-            }
-        }
+        self.run_until_block()
     }
 
-    pub fn run_until_block(&mut self) -> StepResult {
+    pub fn run_until_block(&mut self) -> RunOutcome {
         loop {
             match self.step() {
-                StepResult::Halt(i) => return StepResult::Halt(i),
-                StepResult::NeedsInput => return StepResult::NeedsInput,
-                _ => {}
+                StepResult::Halt(status) => return RunOutcome::Halted(status),
+                StepResult::NeedsInput => return RunOutcome::NeedsInput,
+                StepResult::Continue => {}
             }
         }
     }
 }
 
+#[cfg(test)]
+mod conformance;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,7 +644,7 @@ mod tests {
     #[test]
     fn test_day_9_quine() {
         let input = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
-        let code = Machine::parse_code(&input);
+        let code = Machine::parse_code(input);
         let mut machine = Machine::new(code.clone());
         let _ = machine.run_with_input(1);
         let output = machine.drain_output();
@@ -312,7 +654,7 @@ mod tests {
     #[test]
     fn test_day_9_16_digit_number() {
         let input = "1102,34915192,34915192,7,4,7,99,0";
-        let code = Machine::parse_code(&input);
+        let code = Machine::parse_code(input);
         let mut machine = Machine::new(code);
         let _ = machine.run_with_input(1);
         let output = machine.get_output();
@@ -321,10 +663,41 @@ mod tests {
     #[test]
     fn test_day_9_large_number() {
         let input = "104,1125899906842624,99";
-        let code = Machine::parse_code(&input);
+        let code = Machine::parse_code(input);
         let mut machine = Machine::new(code);
         let _ = machine.run_with_input(1);
         let output = machine.get_output();
         assert_eq!(output, 1125899906842624);
     }
+
+    #[test]
+    fn test_exit_status() {
+        let code = Machine::parse_code("3,0,4,0,3,0,99");
+        let mut machine = Machine::new(code);
+        assert_eq!(machine.run_until_block(), RunOutcome::NeedsInput);
+        machine.add_input(7);
+        assert_eq!(machine.run_until_block(), RunOutcome::NeedsInput);
+        machine.add_input(8);
+        machine.add_input(9);
+        let status = ExitStatus {
+            halt_address: 6,
+            instructions: 3,
+            inputs_consumed: 2,
+            outputs_produced: 1,
+            input_pending: true,
+        };
+        assert_eq!(
+            machine.run_until_block(),
+            RunOutcome::Halted(status.clone())
+        );
+        // the machine stays halted
+        assert_eq!(machine.run_until_block(), RunOutcome::Halted(status));
+    }
+
+    #[test]
+    fn test_run_needs_input() {
+        let code = Machine::parse_code("3,0,99");
+        let mut machine = Machine::new(code);
+        assert_eq!(machine.run(0, 0), RunOutcome::NeedsInput);
+    }
 }
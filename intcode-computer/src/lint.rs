@@ -0,0 +1,309 @@
+//! Static checks which report suspicious constructs before a program runs.
+//!
+//! The checks build on the reachability of [`Analysis`], so they share its limits: code reached
+//! only through dynamic jumps or written at run time is reported as unreachable, and jumps
+//! through memory are judged by the initial content of the image.
+//!
+//! Invalid instructions are errors only where execution certainly gets to them unmodified, that
+//! is along unconditional control flow from the entry point and in cells no instruction writes
+//! to. Elsewhere they are likely data behind a branch and reported as warnings.
+
+use crate::analysis::{static_value, successors, Analysis, Jump, Successors};
+use crate::decode::{decode, DecodeError, Opcode, Param};
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Likely intended, like data after the last instruction.
+    Info,
+    /// Suspicious, but the program may still run fine.
+    Warning,
+    /// The machine fails if execution gets there.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// A write parameter in immediate mode, which the machine rejects.
+    ImmediateWrite {
+        param: usize,
+    },
+    UnknownOpcode(i64),
+    /// A mode digit other than 0, 1 or 2 of a used parameter.
+    InvalidMode {
+        param: usize,
+        mode: i64,
+    },
+    /// A mode digit other than 0 beyond the parameters of the instruction, which is ignored.
+    UnusedMode {
+        param: usize,
+        mode: i64,
+    },
+    /// An instruction reaching beyond the end of the image.
+    Truncated,
+    /// A jump to a constant address which is negative or beyond the end of the image.
+    JumpOutOfImage {
+        target: i64,
+    },
+    /// A run of `len` cells which starts with a valid instruction but is never executed.
+    Unreachable {
+        len: usize,
+    },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::ImmediateWrite { param } => {
+                write!(f, "write parameter {} in immediate mode", param + 1)
+            }
+            Lint::UnknownOpcode(op) => write!(f, "unknown op code {}", op),
+            Lint::InvalidMode { param, mode } => {
+                write!(f, "invalid mode {} of parameter {}", mode, param + 1)
+            }
+            Lint::UnusedMode { param, mode } => {
+                write!(f, "mode {} of unused parameter {}", mode, param + 1)
+            }
+            Lint::Truncated => write!(f, "instruction truncated by the end of the image"),
+            Lint::JumpOutOfImage { target } => {
+                write!(f, "jump to {} outside of the image", target)
+            }
+            Lint::Unreachable { len } => write!(f, "{} cells of unreachable code", len),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub address: usize,
+    pub severity: Severity,
+    pub lint: Lint,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.address, self.severity, self.lint)
+    }
+}
+
+/// Cells written by position mode parameters of reachable instructions.
+fn written_cells(analysis: &Analysis) -> BTreeSet<i64> {
+    analysis
+        .instructions
+        .values()
+        .filter_map(|instruction| match instruction.write() {
+            Some(Param::Position(cell)) => Some(cell),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Addresses execution gets to unless the program halts before, following only unconditional
+/// control flow through unmodified instructions.
+fn certainly_reached(
+    code: &[i64],
+    analysis: &Analysis,
+    written: &BTreeSet<i64>,
+) -> BTreeSet<usize> {
+    let mut reached = BTreeSet::new();
+    let mut address = 0;
+    while reached.insert(address) {
+        let instruction = match analysis.instructions.get(&address) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        if (address..instruction.next()).any(|cell| written.contains(&(cell as i64))) {
+            break;
+        }
+        address = match successors(code, instruction) {
+            Successors {
+                falls_through: true,
+                jump: Jump::None,
+            } => instruction.next(),
+            Successors {
+                falls_through: false,
+                jump: Jump::Static(target),
+            } if !reads_written(instruction.params[1], written) => target,
+            _ => break,
+        };
+    }
+    reached
+}
+
+fn reads_written(param: Param, written: &BTreeSet<i64>) -> bool {
+    matches!(param, Param::Position(cell) if written.contains(&cell))
+}
+
+/// Checks the program reachable from address 0, returning the findings ordered by address.
+pub fn lint(code: &[i64]) -> Vec<Finding> {
+    let analysis = Analysis::new(code);
+    let written = written_cells(&analysis);
+    let certain = certainly_reached(code, &analysis, &written);
+    let mut findings = vec![];
+    let mut add = |address, severity, lint| {
+        findings.push(Finding {
+            address,
+            severity,
+            lint,
+        })
+    };
+
+    // jumps beyond the image are reported as such, not as decode errors where they land
+    for (address, error) in analysis.errors.range(..code.len()) {
+        let severity = if certain.contains(address) && !written.contains(&(*address as i64)) {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        let lint = match *error {
+            DecodeError::UnknownOpcode(op) => Lint::UnknownOpcode(op),
+            DecodeError::InvalidMode { param, mode } => Lint::InvalidMode { param, mode },
+            DecodeError::Truncated => Lint::Truncated,
+        };
+        add(*address, severity, lint);
+    }
+
+    // cells used as data, which are not reported as unreachable
+    let mut data = BTreeSet::new();
+    for (address, instruction) in &analysis.instructions {
+        let opcode = instruction.opcode;
+        let mut modes = code[*address] / 100;
+        for param in 0..3 {
+            let mode = modes % 10;
+            modes /= 10;
+            if param >= opcode.param_count() && mode != 0 {
+                add(
+                    *address,
+                    Severity::Warning,
+                    Lint::UnusedMode { param, mode },
+                );
+            }
+        }
+        if let Some(Param::Immediate(_)) = instruction.write() {
+            let param = opcode.write_param().unwrap();
+            add(*address, Severity::Error, Lint::ImmediateWrite { param });
+        }
+        if let Opcode::JumpIfTrue | Opcode::JumpIfFalse = opcode {
+            let target = instruction.params[1];
+            match static_value(code, target) {
+                _ if reads_written(target, &written) => {}
+                Some(target) if target < 0 => {
+                    add(*address, Severity::Error, Lint::JumpOutOfImage { target })
+                }
+                Some(target) if target as usize >= code.len() => {
+                    add(*address, Severity::Warning, Lint::JumpOutOfImage { target })
+                }
+                _ => {}
+            }
+        }
+        for param in &instruction.params {
+            if let Param::Position(cell) = param {
+                data.insert(*cell);
+            }
+        }
+    }
+
+    // runs of cells outside reachable instructions which start with a valid instruction
+    let mut address = 0;
+    while address < code.len() {
+        if let Some(instruction) = analysis.instructions.get(&address) {
+            address = instruction.next();
+            continue;
+        }
+        let start = address;
+        while address < code.len()
+            && !analysis.instructions.contains_key(&address)
+            && !analysis.errors.contains_key(&address)
+        {
+            address += 1;
+        }
+        if start == address {
+            address += 1;
+        } else if !data.contains(&(start as i64)) && decode(&code[..address], start).is_ok() {
+            let len = address - start;
+            add(start, Severity::Info, Lint::Unreachable { len });
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.address);
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    fn lints(code: &str) -> Vec<(usize, Lint)> {
+        lint(&Machine::parse_code(code))
+            .into_iter()
+            .map(|finding| (finding.address, finding.lint))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        // the cell at 9 is data
+        assert_eq!(lints("3,9,1005,9,8,4,9,99,99,0"), vec![]);
+        assert_eq!(lints("109,1,203,0,204,0,99"), vec![]);
+    }
+
+    #[test]
+    fn test_findings() {
+        let findings = lints("11101,1,2,3,1105,1,100,42");
+        assert_eq!(
+            findings,
+            vec![
+                (0, Lint::ImmediateWrite { param: 2 }),
+                (4, Lint::JumpOutOfImage { target: 100 }),
+            ]
+        );
+        assert_eq!(
+            lints("1106,0,-1,2,1,2,3,99,40099,104,1,99"),
+            vec![
+                (0, Lint::JumpOutOfImage { target: -1 }),
+                (3, Lint::Unreachable { len: 9 }),
+            ]
+        );
+        let finding = &lint(&Machine::parse_code("304,0,99"))[0];
+        assert_eq!(finding.severity, Severity::Error);
+        assert_eq!(
+            finding.to_string(),
+            "0: error: invalid mode 3 of parameter 1"
+        );
+        assert_eq!(
+            lints("40004,0,99"),
+            vec![(0, Lint::UnusedMode { param: 2, mode: 4 })]
+        );
+        assert_eq!(lints("1105,1,3,42"), vec![(3, Lint::UnknownOpcode(42))]);
+    }
+
+    #[test]
+    fn test_severity() {
+        let severities = |code| {
+            lint(&Machine::parse_code(code))
+                .into_iter()
+                .map(|finding| (finding.address, finding.severity))
+                .collect::<Vec<_>>()
+        };
+        // data behind a branch which depends on memory
+        assert_eq!(
+            severities("1005,7,6,42,99,99,99,0"),
+            vec![(3, Severity::Warning), (4, Severity::Info)]
+        );
+        // the instruction at 4 is written before it runs
+        assert_eq!(severities("1101,1,98,4,0"), vec![(4, Severity::Warning)]);
+        assert_eq!(severities("1101,1,98,5,0"), vec![(4, Severity::Error)]);
+    }
+}
@@ -0,0 +1,224 @@
+//! Detection of infinite loops by finding repeated machine states.
+//!
+//! The state of a machine is its pc, relative base and memory. A program which gets into the
+//! same state again without consuming input in between repeats itself forever, as execution is
+//! deterministic. The detector keeps a hash of the memory which is updated on every write, and
+//! looks for cycles in the sequence of state hashes with Brent's algorithm: a saved state is
+//! compared with every following one and replaced after 1, 2, 4, ... instructions. When the
+//! hashes match, the saved copy of the memory proves the repetition, so hash collisions never
+//! lead to a report. Memory pages are shared with the copy until written, keeping saves cheap.
+//!
+//! The search starts over whenever input is consumed or memory is changed from outside with
+//! [`Machine::set_state`].
+
+use crate::program::{Cells, Memory};
+use crate::{Machine, MachineError};
+
+#[derive(Debug, Clone)]
+pub(crate) struct LoopDetector {
+    /// Sum of the hashes of all cells, zero cells hash to 0.
+    memory_hash: u64,
+    saved: Option<Saved>,
+    /// Instructions from one save to the next.
+    power: u64,
+    /// Instructions since the last save.
+    steps: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Saved {
+    hash: u64,
+    pc: usize,
+    relative_base: i64,
+    state: Memory,
+    extended_state: Cells,
+    inputs_consumed: u64,
+}
+
+/// The finalizer of splitmix64.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn cell_hash(address: u128, value: i64) -> u64 {
+    if value == 0 {
+        0
+    } else {
+        mix(address as u64 ^ mix(value as u64))
+    }
+}
+
+/// Whether the non-zero cells of both maps are the same.
+fn same_cells(a: &Cells, b: &Cells) -> bool {
+    let contained = |a: &Cells, b: &Cells| {
+        a.iter()
+            .all(|(address, value)| *value == b.get(address).cloned().unwrap_or(0))
+    };
+    contained(a, b) && contained(b, a)
+}
+
+impl LoopDetector {
+    fn new(machine: &Machine) -> LoopDetector {
+        let mut memory_hash = 0u64;
+        for (address, value) in machine.state.iter().enumerate() {
+            memory_hash = memory_hash.wrapping_add(cell_hash(address as u128, value));
+        }
+        for (address, value) in &machine.extended_state {
+            memory_hash = memory_hash.wrapping_add(cell_hash(*address, *value));
+        }
+        LoopDetector {
+            memory_hash,
+            saved: None,
+            power: 1,
+            steps: 0,
+        }
+    }
+
+    /// Updates the memory hash for a write of `new` over `old`.
+    pub(crate) fn write(&mut self, address: u128, old: i64, new: i64) {
+        self.memory_hash = self
+            .memory_hash
+            .wrapping_sub(cell_hash(address, old))
+            .wrapping_add(cell_hash(address, new));
+    }
+}
+
+impl Machine {
+    /// Enables or disables the detection of infinite loops. Once enabled,
+    /// [`Machine::try_step`] fails with [`MachineError::InfiniteLoop`] when the program gets
+    /// into a state it was in before without consuming input since, which takes at most about
+    /// twice the instructions of the loop after entering it. Enabling hashes the whole memory,
+    /// afterwards each instruction costs a few extra operations.
+    pub fn detect_loops(&mut self, enabled: bool) {
+        self.loops = if enabled {
+            Some(Box::new(LoopDetector::new(self)))
+        } else {
+            None
+        };
+    }
+
+    /// Updates the memory hash before `value` is written to `location`. With `external` set the
+    /// write does not come from the program and the search starts over.
+    pub(crate) fn note_write(&mut self, location: u128, value: i64, external: bool) {
+        if self.loops.is_none() {
+            return;
+        }
+        let old = self.read(location);
+        if let Some(detector) = &mut self.loops {
+            detector.write(location, old, value);
+            if external {
+                detector.saved = None;
+            }
+        }
+    }
+
+    /// Checks the state after an executed instruction.
+    pub(crate) fn check_loop(&mut self) -> Result<(), MachineError> {
+        let detector = match &mut self.loops {
+            Some(detector) => detector,
+            None => return Ok(()),
+        };
+        let hash = detector.memory_hash
+            ^ mix(self.pc as u64)
+            ^ mix(self.relative_base as u64).rotate_left(32);
+        if let Some(saved) = &detector.saved {
+            if saved.inputs_consumed != self.inputs_consumed {
+                detector.saved = None;
+            } else if saved.hash == hash
+                && saved.pc == self.pc
+                && saved.relative_base == self.relative_base
+                && saved.state.same_content(&self.state)
+                && same_cells(&saved.extended_state, &self.extended_state)
+            {
+                return Err(MachineError::InfiniteLoop {
+                    pc: self.pc,
+                    period: detector.steps + 1,
+                });
+            }
+        }
+        detector.steps += 1;
+        if detector.saved.is_none() || detector.steps == detector.power {
+            if detector.saved.is_none() {
+                detector.power = 1;
+            } else {
+                detector.power *= 2;
+            }
+            detector.steps = 0;
+            detector.saved = Some(Saved {
+                hash,
+                pc: self.pc,
+                relative_base: self.relative_base,
+                state: self.state.clone(),
+                extended_state: self.extended_state.clone(),
+                inputs_consumed: self.inputs_consumed,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StepResult;
+
+    fn run(machine: &mut Machine) -> Result<StepResult, MachineError> {
+        loop {
+            match machine.try_step()? {
+                StepResult::Continue => {}
+                result => return Ok(result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_detects_loop() {
+        // counts down from 3 and then negates a cell forever, the state repeats every 4 instructions
+        let code = "101,-1,14,14,1005,14,0,1002,15,-1,15,1105,1,7,3,1";
+        let mut machine = Machine::new(Machine::parse_code(code));
+        machine.detect_loops(true);
+        assert_eq!(
+            run(&mut machine),
+            Err(MachineError::InfiniteLoop { pc: 7, period: 4 })
+        );
+        assert!(machine.instructions() < 30);
+    }
+
+    #[test]
+    fn test_progress_is_not_a_loop() {
+        // a counter never repeats, and neither does a relative base moving through memory
+        let mut machine = Machine::new(Machine::parse_code("1001,7,1,7,1105,1,0,0"));
+        machine.detect_loops(true);
+        for _ in 0..10_000 {
+            assert_eq!(machine.try_step(), Ok(StepResult::Continue));
+        }
+        let mut machine = Machine::new(Machine::parse_code("109,1,1105,1,0"));
+        machine.detect_loops(true);
+        for _ in 0..10_000 {
+            assert_eq!(machine.try_step(), Ok(StepResult::Continue));
+        }
+    }
+
+    #[test]
+    fn test_input_restarts() {
+        // reads into the same cell over and over, the state repeats but input is consumed
+        let mut machine = Machine::new(Machine::parse_code("3,5,1105,1,0,0"));
+        machine.detect_loops(true);
+        for _ in 0..100 {
+            machine.add_input(7);
+        }
+        assert_eq!(run(&mut machine), Ok(StepResult::NeedsInput));
+
+        // written from outside, the counter at 7 seems to repeat
+        let mut machine = Machine::new(Machine::parse_code("1001,7,1,7,1105,1,0,0"));
+        machine.detect_loops(true);
+        for _ in 0..100 {
+            machine.try_step().unwrap();
+            if machine.pc() == 0 {
+                machine.set_state(7, 0);
+            }
+        }
+    }
+}
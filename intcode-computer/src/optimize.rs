@@ -0,0 +1,513 @@
+//! Peephole optimization and partial evaluation of program images.
+//!
+//! The optimizer keeps every instruction at its address, so data and jump targets stay valid. It
+//! only rewrites instructions whose cells the program never writes or reads as data:
+//!
+//! - position mode reads of cells which are never written become immediate reads,
+//! - arithmetic and comparisons on two immediates are folded into `add <result>, 0`,
+//! - jumps to jumps and to never taken jumps are threaded to the final target,
+//! - jumps to the next instruction become never taken jumps with an immediate condition, which
+//!   the jumps to them are threaded past. Removing them would move the instructions behind.
+//!
+//! Specializing for fixed inputs works by poking the values into the image first, like day 2's
+//! noun and verb.
+//!
+//! Relative mode accesses and jumps to targets only known at run time defeat the analysis, such
+//! programs are returned unchanged. [`Options::assume_relative_outside_image`] lifts both
+//! restrictions for programs which keep their stack behind the image and return from functions
+//! through it, like `jf 0, [rb+0]`. [`verify`] runs the original and the optimized program side by
+//! side to check the result.
+
+use crate::analysis::{successors, Analysis, Jump};
+use crate::decode::{Instruction, Opcode, Param};
+use crate::{Machine, MachineError, StepResult};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Memory cells with known values, applied before optimizing.
+    pub pokes: Vec<(usize, i64)>,
+    /// Treat relative mode accesses as never touching the image, and jumps to relative mode
+    /// targets as returns to the addresses which calls store in relative mode.
+    pub assume_relative_outside_image: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Optimized {
+    pub code: Vec<i64>,
+    /// Addresses of the cells which differ from the poked input image.
+    pub rewritten: BTreeSet<usize>,
+}
+
+pub fn optimize(code: &[i64], options: &Options) -> Optimized {
+    let mut image = code.to_vec();
+    for (address, value) in &options.pokes {
+        if *address >= image.len() {
+            image.resize(address + 1, 0);
+        }
+        image[*address] = *value;
+    }
+    let (analysis, returns) = if options.assume_relative_outside_image {
+        analyze_returns(&image)
+    } else {
+        (Analysis::new(&image), BTreeSet::new())
+    };
+    let relative = analysis.uses_relative_mode();
+    let unknown_jump = analysis.dynamic_jumps.iter().any(|address| {
+        !options.assume_relative_outside_image
+            || !matches!(analysis.instructions[address].params[1], Param::Relative(_))
+    });
+    if (relative && !options.assume_relative_outside_image) || unknown_jump {
+        return Optimized {
+            code: image,
+            rewritten: BTreeSet::new(),
+        };
+    }
+
+    let mut writers: BTreeMap<i64, Vec<usize>> = BTreeMap::new();
+    let mut data = BTreeSet::new();
+    let mut owners: HashMap<usize, usize> = HashMap::new();
+    for instruction in analysis.instructions.values() {
+        if let Some(Param::Position(address)) = instruction.write() {
+            writers
+                .entry(address)
+                .or_default()
+                .push(instruction.address);
+        }
+        for param in instruction.reads() {
+            if let Param::Position(address) = param {
+                data.insert(address);
+            }
+        }
+        for cell in instruction.address..instruction.next() {
+            *owners.entry(cell).or_insert(0) += 1;
+        }
+    }
+    let written: BTreeSet<i64> = writers.keys().cloned().collect();
+
+    // writes which change the control flow make the analysis meaningless: a jump target read
+    // from a written cell, or a written op code or jump operand of an instruction which can
+    // still run after the write
+    for instruction in analysis.instructions.values() {
+        let control = match jump_condition(instruction.opcode) {
+            Some(_) => instruction.next(),
+            None => instruction.address + 1,
+        };
+        let target_written = match (
+            jump_condition(instruction.opcode),
+            instruction.params.get(1),
+        ) {
+            (Some(_), Some(Param::Position(address))) => written.contains(address),
+            _ => false,
+        };
+        let modified = (instruction.address..control)
+            .filter_map(|cell| writers.get(&(cell as i64)))
+            .flatten()
+            .any(|writer| reachable(&analysis, &image, &returns, *writer, instruction.address));
+        if target_written || modified {
+            return Optimized {
+                code: image,
+                rewritten: BTreeSet::new(),
+            };
+        }
+    }
+
+    let constant = |address: i64| -> Option<i64> {
+        if address < 0 || written.contains(&address) {
+            return None;
+        }
+        match image.get(address as usize) {
+            Some(value) => Some(*value),
+            None if !relative => Some(0),
+            None => None,
+        }
+    };
+    let rewritable = |instruction: &Instruction| {
+        (instruction.address..instruction.next()).all(|cell| {
+            let address = cell as i64;
+            !written.contains(&address) && !data.contains(&address) && owners[&cell] == 1
+        })
+    };
+
+    let mut rewrites: BTreeMap<usize, Instruction> = analysis
+        .instructions
+        .values()
+        .filter(|i| rewritable(i))
+        .map(|i| (i.address, simplify(i, &constant)))
+        .collect();
+    thread_jumps(&mut rewrites, &image);
+    for instruction in rewrites.values_mut() {
+        if skippable(instruction) {
+            instruction.opcode = Opcode::JumpIfTrue;
+            instruction.params = vec![Param::Immediate(0), instruction.params[1]];
+        }
+    }
+
+    let mut optimized = image.clone();
+    let mut rewritten = BTreeSet::new();
+    for (address, instruction) in &rewrites {
+        for (offset, cell) in instruction.encode().into_iter().enumerate() {
+            if optimized[address + offset] != cell {
+                optimized[address + offset] = cell;
+                rewritten.insert(address + offset);
+            }
+        }
+    }
+    Optimized {
+        code: optimized,
+        rewritten,
+    }
+}
+
+/// Analyzes the program with the return addresses as further entry points, and returns them.
+/// Calls store the address behind their jump with an `add` or `mul` of two immediates to a
+/// relative slot.
+fn analyze_returns(image: &[i64]) -> (Analysis, BTreeSet<usize>) {
+    let mut returns = BTreeSet::new();
+    loop {
+        let entries: Vec<usize> = std::iter::once(0).chain(returns.iter().cloned()).collect();
+        let analysis = Analysis::from_entries(image, &entries);
+        let behind_jumps: BTreeSet<usize> = analysis
+            .instructions
+            .values()
+            .filter(|instruction| jump_condition(instruction.opcode).is_some())
+            .map(Instruction::next)
+            .collect();
+        let found: BTreeSet<usize> = analysis
+            .instructions
+            .values()
+            .filter_map(|instruction| match instruction.params[..] {
+                [Param::Immediate(a), Param::Immediate(b), Param::Relative(_)] => {
+                    match instruction.opcode {
+                        Opcode::Add => a.checked_add(b),
+                        Opcode::Mul => a.checked_mul(b),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .filter_map(|address| usize::try_from(address).ok())
+            .filter(|address| behind_jumps.contains(address))
+            .collect();
+        if found.is_subset(&returns) {
+            return (analysis, returns);
+        }
+        returns.extend(found);
+    }
+}
+
+/// Whether the instruction at `to` can run after the one at `from`. Jumps to relative mode
+/// targets go to one of the `returns`.
+fn reachable(
+    analysis: &Analysis,
+    image: &[i64],
+    returns: &BTreeSet<usize>,
+    from: usize,
+    to: usize,
+) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![from];
+    while let Some(address) = pending.pop() {
+        let instruction = match analysis.instructions.get(&address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let successors = successors(image, instruction);
+        let mut next = Vec::new();
+        if successors.falls_through {
+            next.push(instruction.next());
+        }
+        match successors.jump {
+            Jump::Static(target) => next.push(target),
+            Jump::Dynamic => next.extend(returns),
+            Jump::None => {}
+        }
+        for address in next {
+            if address == to {
+                return true;
+            }
+            if visited.insert(address) {
+                pending.push(address);
+            }
+        }
+    }
+    false
+}
+
+/// Whether the op code is a conditional jump, and which condition value makes it jump.
+fn jump_condition(opcode: Opcode) -> Option<bool> {
+    match opcode {
+        Opcode::JumpIfTrue => Some(true),
+        Opcode::JumpIfFalse => Some(false),
+        _ => None,
+    }
+}
+
+fn simplify<F: Fn(i64) -> Option<i64>>(instruction: &Instruction, constant: &F) -> Instruction {
+    let mut simplified = instruction.clone();
+    let write = instruction.opcode.write_param();
+    for (i, param) in simplified.params.iter_mut().enumerate() {
+        if let Param::Position(address) = *param {
+            if Some(i) != write {
+                if let Some(value) = constant(address) {
+                    *param = Param::Immediate(value);
+                }
+            }
+        }
+    }
+    if let [Param::Immediate(a), Param::Immediate(b), out] = simplified.params[..] {
+        let result = match simplified.opcode {
+            Opcode::Add => a.checked_add(b),
+            Opcode::Mul => a.checked_mul(b),
+            Opcode::LessThan => Some((a < b) as i64),
+            Opcode::Equals => Some((a == b) as i64),
+            _ => None,
+        };
+        if let Some(result) = result {
+            simplified.opcode = Opcode::Add;
+            simplified.params = vec![Param::Immediate(result), Param::Immediate(0), out];
+        }
+    }
+    simplified
+}
+
+/// Whether the instruction is a jump to the next instruction which can be skipped, its condition
+/// does not fail to read.
+fn skippable(instruction: &Instruction) -> bool {
+    jump_condition(instruction.opcode).is_some()
+        && instruction.params[1] == Param::Immediate(instruction.next() as i64)
+        && match instruction.params[0] {
+            Param::Immediate(_) => true,
+            Param::Position(address) => address >= 0,
+            Param::Relative(_) => false,
+        }
+}
+
+/// Where execution continues after passing `address`, skipping over jumps which never jump,
+/// always jump or jump to the next instruction. Only rewritten instructions are followed, the
+/// others may change at run time.
+fn final_target(
+    rewrites: &BTreeMap<usize, Instruction>,
+    image: &[i64],
+    mut address: usize,
+) -> usize {
+    let mut visited = BTreeSet::new();
+    while visited.insert(address) {
+        let instruction = match rewrites.get(&address) {
+            Some(instruction) if jump_condition(instruction.opcode).is_some() => instruction,
+            _ => break,
+        };
+        if skippable(instruction) {
+            address = instruction.next();
+            continue;
+        }
+        let successors = successors(image, instruction);
+        address = match (successors.falls_through, successors.jump) {
+            (true, Jump::None) => instruction.next(),
+            (false, Jump::Static(target)) => target,
+            _ => break,
+        };
+    }
+    address
+}
+
+fn thread_jumps(rewrites: &mut BTreeMap<usize, Instruction>, image: &[i64]) {
+    let targets: Vec<(usize, usize)> = rewrites
+        .values()
+        .filter(|i| jump_condition(i.opcode).is_some())
+        .filter_map(|i| match i.params[1] {
+            Param::Immediate(target) if target >= 0 && target as usize != i.address => {
+                Some((i.address, final_target(rewrites, image, target as usize)))
+            }
+            _ => None,
+        })
+        .collect();
+    for (address, target) in targets {
+        let instruction = rewrites.get_mut(&address).expect("rewritten jump");
+        instruction.params[1] = Param::Immediate(target as i64);
+    }
+}
+
+/// How a run of [`verify`] ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ending {
+    Halted,
+    NeedsInput,
+    Error(MachineError),
+    StepLimit,
+}
+
+fn run(code: Vec<i64>, inputs: &[i64], step_limit: usize) -> (Machine, Ending) {
+    let mut machine = Machine::new(code);
+    for input in inputs {
+        machine.add_input(*input);
+    }
+    for _ in 0..step_limit {
+        match machine.try_step() {
+            Ok(StepResult::Continue) => {}
+            Ok(StepResult::Halt(_)) => return (machine, Ending::Halted),
+            Ok(StepResult::NeedsInput) => return (machine, Ending::NeedsInput),
+            Err(error) => return (machine, Ending::Error(error)),
+        }
+    }
+    (machine, Ending::StepLimit)
+}
+
+/// Runs the original and the optimized program with the same input and compares how they end,
+/// their output and their memory apart from the rewritten cells.
+pub fn verify(
+    original: &[i64],
+    optimized: &Optimized,
+    options: &Options,
+    inputs: &[i64],
+    step_limit: usize,
+) -> Result<(), String> {
+    let mut original = original.to_vec();
+    for (address, value) in &options.pokes {
+        if *address >= original.len() {
+            original.resize(address + 1, 0);
+        }
+        original[*address] = *value;
+    }
+    let (mut expected, expected_ending) = run(original, inputs, step_limit);
+    let (mut actual, actual_ending) = run(optimized.code.clone(), inputs, step_limit);
+
+    let comparable = |ending: &Ending| match ending {
+        // the pc of an error differs when jumps were threaded
+        Ending::Error(_) => Ending::Error(MachineError::Overflow { pc: 0 }),
+        ending => ending.clone(),
+    };
+    if comparable(&expected_ending) != comparable(&actual_ending) {
+        return Err(format!(
+            "ended with {:?} instead of {:?}",
+            actual_ending, expected_ending
+        ));
+    }
+    let (expected_output, actual_output) = (expected.drain_output(), actual.drain_output());
+    if expected_output != actual_output {
+        return Err(format!(
+            "output {:?} instead of {:?}",
+            actual_output, expected_output
+        ));
+    }
+    if let Some(change) = expected
+        .diff(&actual)
+        .into_iter()
+        .find(|c| !optimized.rewritten.contains(&(c.address as usize)))
+    {
+        return Err(format!("memory differs: {}", change));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::CASES;
+    use crate::RunOutcome;
+
+    fn instructions(code: Vec<i64>) -> u64 {
+        match Machine::new(code).run_until_block() {
+            RunOutcome::Halted(status) => status.instructions,
+            RunOutcome::NeedsInput => panic!("Needs input"),
+        }
+    }
+
+    #[test]
+    fn test_conformance_programs_verify() {
+        for case in CASES {
+            let code = Machine::parse_code(case.code);
+            let options = Options::default();
+            let optimized = optimize(&code, &options);
+            verify(&code, &optimized, &options, case.input, 10_000)
+                .unwrap_or_else(|e| panic!("{}: {}", case.name, e));
+        }
+    }
+
+    #[test]
+    fn test_specialize_and_fold() {
+        // day 2 style: memory[0] = noun * verb + 3 with the noun and verb poked in
+        let code = Machine::parse_code("1,0,0,0,2,1,2,13,1001,13,3,0,99,0");
+        let options = Options {
+            pokes: vec![(1, 12), (2, 13)],
+            ..Options::default()
+        };
+        let optimized = optimize(&code, &options);
+        assert_eq!(
+            &optimized.code[..12],
+            &[1, 12, 13, 0, 1101, 156, 0, 13, 1001, 13, 3, 0][..]
+        );
+        verify(&code, &optimized, &options, &[], 1000).unwrap();
+    }
+
+    #[test]
+    fn test_thread_jumps() {
+        // jumps from 0 to 3, from 3 over the never taken jump at 6 to 9
+        let code = Machine::parse_code("1105,1,3,1106,0,6,1105,0,0,104,7,99");
+        let optimized = optimize(&code, &Options::default());
+        assert_eq!(&optimized.code[..3], &[1105, 1, 9][..]);
+        verify(&code, &optimized, &Options::default(), &[], 1000).unwrap();
+        assert_eq!(instructions(code), 4);
+        assert_eq!(instructions(optimized.code), 2);
+    }
+
+    #[test]
+    fn test_jump_to_next_instruction() {
+        // jumps from 0 to 3, where a jump to the next instruction is skipped
+        let code = Machine::parse_code("1105,1,3,1106,0,6,104,7,99");
+        let optimized = optimize(&code, &Options::default());
+        assert_eq!(&optimized.code[..6], &[1105, 1, 6, 1105, 0, 6][..]);
+        verify(&code, &optimized, &Options::default(), &[], 1000).unwrap();
+        assert_eq!(instructions(code), 3);
+        assert_eq!(instructions(optimized.code), 2);
+
+        let optimized = optimize(
+            &Machine::parse_code("1105,1,3,104,7,99"),
+            &Options::default(),
+        );
+        assert_eq!(optimized.code, vec![1105, 0, 3, 104, 7, 99]);
+    }
+
+    #[test]
+    fn test_returns_through_relative_base() {
+        // main calls f at 19 twice, which adds the constant [27] to [26] and returns
+        let code = Machine::parse_code(
+            "109,30,21101,9,0,0,1105,1,19,21101,16,0,0,1105,1,19,4,26,99,\
+             1,26,27,26,2106,0,0,1,2",
+        );
+        assert!(optimize(&code, &Options::default()).rewritten.is_empty());
+        let options = Options {
+            assume_relative_outside_image: true,
+            ..Options::default()
+        };
+        let optimized = optimize(&code, &options);
+        assert_eq!(&optimized.code[19..23], &[1001, 26, 2, 26][..]);
+        verify(&code, &optimized, &options, &[], 1000).unwrap();
+    }
+
+    #[test]
+    fn test_self_modifying_code_is_kept() {
+        // the first instruction patches the add at 4 into a multiplication
+        let code = Machine::parse_code("1101,0,2,4,1101,3,4,10,99,99,0");
+        let optimized = optimize(&code, &Options::default());
+        assert!(!optimized.rewritten.iter().any(|a| (4..8).contains(a)));
+        verify(&code, &optimized, &Options::default(), &[], 1000).unwrap();
+    }
+
+    #[test]
+    fn test_verify_catches_wrong_assumption() {
+        // the first instruction writes 11 into cell 9 through relative mode, which is printed
+        let code = Machine::parse_code("21101,5,6,9,4,9,99,0,0,7");
+        let options = Options::default();
+        assert!(optimize(&code, &options).rewritten.is_empty());
+
+        let options = Options {
+            assume_relative_outside_image: true,
+            ..Options::default()
+        };
+        let optimized = optimize(&code, &options);
+        assert!(!optimized.rewritten.is_empty());
+        assert!(verify(&code, &optimized, &options, &[], 10_000).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Lazy iteration over the outputs of a [`Machine`].
+
+use crate::{Machine, StepResult};
+use std::iter::FromFn;
+
+/// Iterator over the outputs of a machine, see [`Machine::outputs`].
+#[derive(Debug)]
+pub struct Outputs<'a, I> {
+    machine: &'a mut Machine,
+    inputs: I,
+}
+
+impl Machine {
+    /// Runs the machine lazily, each call to `next` runs it until the next output. Inputs are
+    /// taken from `inputs` when the program needs them. The iterator ends when the program halts
+    /// or needs input after `inputs` ran out; the machine can then be resumed as usual. Panics on
+    /// any [`MachineError`](crate::MachineError) like [`Machine::step`].
+    pub fn outputs<I: IntoIterator<Item = i64>>(&mut self, inputs: I) -> Outputs<'_, I::IntoIter> {
+        Outputs {
+            machine: self,
+            inputs: inputs.into_iter(),
+        }
+    }
+
+    /// Like [`Machine::outputs`], calling `input` whenever the program needs input. Returning
+    /// `None` ends the iteration.
+    pub fn outputs_with<F: FnMut() -> Option<i64>>(&mut self, input: F) -> Outputs<'_, FromFn<F>> {
+        self.outputs(std::iter::from_fn(input))
+    }
+}
+
+impl<I: Iterator<Item = i64>> Iterator for Outputs<'_, I> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        loop {
+            if let Some(output) = self.machine.output.borrow_mut().pop_front() {
+                return Some(output);
+            }
+            match self.machine.step() {
+                StepResult::Continue => {}
+                StepResult::Halt(_) => return None,
+                StepResult::NeedsInput => self.machine.add_input(self.inputs.next()?),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn test_outputs() {
+        // outputs each input twice until it reads a 0
+        let code = Machine::parse_code("3,13,1006,13,12,4,13,4,13,1105,1,0,99,0");
+        let mut machine = Machine::new(code.clone());
+        let outputs: Vec<i64> = machine.outputs(vec![1, 2]).collect();
+        assert_eq!(outputs, vec![1, 1, 2, 2]);
+        // the machine waits for more input
+        assert_eq!(machine.run_with_input(3), RunOutcome::NeedsInput);
+        assert_eq!(machine.drain_output(), vec![3, 3]);
+
+        // runs only as far as needed, the second output of 4 is still to come
+        let mut machine = Machine::new(code);
+        assert_eq!(machine.outputs(vec![4, 5]).next(), Some(4));
+        assert_eq!(machine.run_until_block(), RunOutcome::NeedsInput);
+        assert_eq!(machine.drain_output(), vec![4]);
+    }
+
+    #[test]
+    fn test_outputs_with() {
+        let code = Machine::parse_code("3,13,1006,13,12,4,13,4,13,1105,1,0,99,0");
+        let mut machine = Machine::new(code);
+        let mut requested = 0;
+        let outputs: Vec<i64> = machine
+            .outputs_with(|| {
+                requested += 1;
+                Some(5 - requested)
+            })
+            .collect();
+        assert_eq!(outputs, vec![4, 4, 3, 3, 2, 2, 1, 1]);
+        assert_eq!(requested, 5);
+    }
+}
@@ -0,0 +1,345 @@
+//! Immutable program images shared between machines.
+//!
+//! A [`Program`] splits the code into pages behind an [`Arc`]. Machines loaded from it share the
+//! pages until they write to them, so loading and [`Machine::fork`](crate::Machine::fork) cost
+//! one reference count per page instead of a copy of the program.
+//!
+//! Cells beyond the program live in a hash map, which day 9 and later use for their stack. It
+//! hashes addresses with a multiplication, SipHash took longer than the rest of an instruction.
+
+use crate::image::{self, Image, ImageError};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Cells per page.
+const PAGE_SIZE: usize = 256;
+
+type Page = [i64; PAGE_SIZE];
+
+/// Written cells beyond the program by address.
+pub(crate) type Cells = HashMap<u128, i64, BuildHasherDefault<AddressHasher>>;
+
+/// Fibonacci hashing of addresses, the high bits which the map uses first are well mixed.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(u64::from(*byte));
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = (self.0.rotate_left(5) ^ value).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+
+    fn write_u128(&mut self, value: u128) {
+        self.write_u64(value as u64);
+        self.write_u64((value >> 64) as u64);
+    }
+}
+
+/// A program image, cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pages: Arc<[Arc<Page>]>,
+    len: usize,
+}
+
+#[derive(Debug)]
+pub enum ProgramError {
+    Io(io::Error),
+    /// A value which is not an integer, `index` counts the values from 0.
+    InvalidValue {
+        index: usize,
+        value: String,
+    },
+    Empty,
+    Image(ImageError),
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgramError::Io(error) => write!(f, "{}", error),
+            ProgramError::InvalidValue { index, value } => {
+                write!(f, "value {} is not an integer: {:?}", index, value)
+            }
+            ProgramError::Empty => write!(f, "empty program"),
+            ProgramError::Image(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ProgramError {}
+
+impl From<io::Error> for ProgramError {
+    fn from(error: io::Error) -> ProgramError {
+        ProgramError::Io(error)
+    }
+}
+
+impl Program {
+    pub fn new(code: Vec<i64>) -> Program {
+        let pages = code
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = [0; PAGE_SIZE];
+                page[..chunk.len()].copy_from_slice(chunk);
+                Arc::new(page)
+            })
+            .collect();
+        Program {
+            pages,
+            len: code.len(),
+        }
+    }
+
+    /// Parses comma separated values, checking all of them once instead of on every load.
+    pub fn parse(text: &str) -> Result<Program, ProgramError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ProgramError::Empty);
+        }
+        let code = text
+            .split(',')
+            .enumerate()
+            .map(|(index, value)| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| ProgramError::InvalidValue {
+                        index,
+                        value: value.trim().to_string(),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Program::new(code))
+    }
+
+    /// Reads a program in text form or as binary [`Image`].
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Program, ProgramError> {
+        let bytes = fs::read(path)?;
+        if image::is_image(&bytes) {
+            let image = Image::decode(&bytes).map_err(ProgramError::Image)?;
+            return Ok(Program::new(image.code));
+        }
+        let text = String::from_utf8(bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Program::parse(&text)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, address: usize) -> Option<i64> {
+        if address < self.len {
+            Some(self.pages[address / PAGE_SIZE][address % PAGE_SIZE])
+        } else {
+            None
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<i64> {
+        (0..self.len)
+            .map(|address| self.pages[address / PAGE_SIZE][address % PAGE_SIZE])
+            .collect()
+    }
+}
+
+impl From<Vec<i64>> for Program {
+    fn from(code: Vec<i64>) -> Program {
+        Program::new(code)
+    }
+}
+
+impl From<&Program> for Program {
+    fn from(program: &Program) -> Program {
+        program.clone()
+    }
+}
+
+/// The memory of a machine covering the program, pages are copied on their first write.
+#[derive(Debug, Clone)]
+pub(crate) struct Memory {
+    pages: Vec<Arc<Page>>,
+    len: usize,
+}
+
+impl Memory {
+    pub(crate) fn new(program: &Program) -> Memory {
+        Memory {
+            pages: program.pages.to_vec(),
+            len: program.len,
+        }
+    }
+
+    /// Restores the cells of `program`. Pages copied by writes are overwritten in place and kept
+    /// for the next run, pages which are still shared stay shared.
+    pub(crate) fn reset(&mut self, program: &Program) {
+        self.pages.truncate(program.pages.len());
+        for (page, original) in self.pages.iter_mut().zip(program.pages.iter()) {
+            if Arc::ptr_eq(page, original) {
+                continue;
+            }
+            match Arc::get_mut(page) {
+                Some(copy) => copy.copy_from_slice(&original[..]),
+                None => *page = original.clone(),
+            }
+        }
+        let kept = self.pages.len();
+        self.pages.extend(program.pages[kept..].iter().cloned());
+        self.len = program.len;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Reads a cell, `address` must be below [`Memory::len`].
+    pub(crate) fn get(&self, address: usize) -> i64 {
+        debug_assert!(address < self.len);
+        self.pages[address / PAGE_SIZE][address % PAGE_SIZE]
+    }
+
+    /// Writes a cell, `address` must be below [`Memory::len`].
+    pub(crate) fn set(&mut self, address: usize, value: i64) {
+        debug_assert!(address < self.len);
+        let page = &mut self.pages[address / PAGE_SIZE];
+        if let Some(page) = Arc::get_mut(page) {
+            page[address % PAGE_SIZE] = value;
+        } else if page[address % PAGE_SIZE] != value {
+            // skip the copy of a shared page if nothing changes
+            Arc::make_mut(page)[address % PAGE_SIZE] = value;
+        }
+    }
+
+    /// Whether both hold the same cells, comparing only pages which are not shared.
+    pub(crate) fn same_content(&self, other: &Memory) -> bool {
+        self.len == other.len
+            && self
+                .pages
+                .iter()
+                .zip(&other.pages)
+                .all(|(a, b)| Arc::ptr_eq(a, b) || a == b)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len).map(move |address| self.get(address))
+    }
+
+    /// Number of pages shared with `other`, for tests.
+    #[cfg(test)]
+    fn shared_pages(&self, other: &Memory) -> usize {
+        self.pages
+            .iter()
+            .zip(&other.pages)
+            .filter(|(a, b)| Arc::ptr_eq(a, b))
+            .count()
+    }
+
+    /// Address of the page holding `address`, for tests.
+    #[cfg(test)]
+    fn page(&self, address: usize) -> *const Page {
+        Arc::as_ptr(&self.pages[address / PAGE_SIZE])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut code = vec![0; 3 * PAGE_SIZE];
+        code[..5].copy_from_slice(&[1101, 2, 3, 700, 99]);
+        let program = Program::new(code.clone());
+        assert_eq!(program.len(), 3 * PAGE_SIZE);
+        assert_eq!(program.to_vec(), code);
+
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.state.shared_pages(&Memory::new(&program)), 3);
+        assert!(matches!(machine.run_until_block(), RunOutcome::Halted(_)));
+        assert_eq!(machine.get_state(700), 5);
+        // only the page holding 700 was copied
+        assert_eq!(machine.state.shared_pages(&Memory::new(&program)), 2);
+        assert_eq!(program.get(700), Some(0));
+    }
+
+    #[test]
+    fn test_fork() {
+        // adds each input to a total and outputs it
+        let mut machine = Machine::new(Machine::parse_code("3,11,1,11,12,12,4,12,1105,1,0,0,0"));
+        machine.add_input(5);
+        assert_eq!(machine.run_until_block(), RunOutcome::NeedsInput);
+        assert_eq!(machine.drain_output(), vec![5]);
+
+        let mut fork = machine.fork();
+        assert_eq!(fork.state.shared_pages(&machine.state), 1);
+        fork.add_input(10);
+        fork.run_until_block();
+        assert_eq!(fork.drain_output(), vec![15]);
+        // the original is unaffected by the inputs, outputs and writes of the fork
+        machine.add_input(1);
+        machine.run_until_block();
+        assert_eq!(machine.drain_output(), vec![6]);
+        assert_eq!(fork.get_state(12), 15);
+    }
+
+    #[test]
+    fn test_parse() {
+        let program = Program::parse("1, 2,-3\n").unwrap();
+        assert_eq!(program.to_vec(), vec![1, 2, -3]);
+        assert_eq!(
+            Program::parse("1,2,x,4").unwrap_err().to_string(),
+            "value 2 is not an integer: \"x\""
+        );
+        assert!(matches!(Program::parse(" \n"), Err(ProgramError::Empty)));
+    }
+
+    #[test]
+    fn test_reset() {
+        let program = Program::parse("3,9,1,9,10,10,4,10,99,0,0").unwrap();
+        let mut machine = Machine::new(&program);
+        machine.set_state(1000, 7);
+        machine.add_input(4);
+        machine.add_input(5);
+        machine.run_until_block();
+        assert_eq!(machine.get_state(10), 4);
+        let page = machine.state.page(10);
+
+        machine.reset();
+        assert_eq!(machine.snapshot(), Machine::new(&program).snapshot());
+        // the copied page is restored in place instead of shared again
+        assert_eq!(machine.state.shared_pages(&Memory::new(&program)), 0);
+        assert_eq!(machine.state.page(10), page);
+        assert_eq!(program.get(10), Some(0));
+        machine.add_input(6);
+        match machine.run_until_block() {
+            RunOutcome::Halted(status) => {
+                assert_eq!(status.instructions, 3);
+                assert!(!status.input_pending);
+            }
+            RunOutcome::NeedsInput => panic!("Needs input"),
+        }
+        assert_eq!(machine.drain_output(), vec![6]);
+        assert_eq!(machine.state.page(10), page);
+    }
+}
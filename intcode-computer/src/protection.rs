@@ -0,0 +1,261 @@
+//! Memory protection regions and detection of self-modifying code.
+//!
+//! Regions trap with [`MachineError::ProtectionViolation`] when the program writes to read-only
+//! memory or executes an instruction in no-execute memory. Watched regions only record the writes.
+//! Independently of any region, the machine can record every write to a cell it already executed
+//! as part of an instruction.
+
+use crate::{Machine, MachineError};
+use std::collections::HashSet;
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Protection {
+    ReadOnly,
+    NoExecute,
+    /// Writes are allowed but recorded, see [`Machine::watched_writes`].
+    Watch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub range: Range<usize>,
+    pub protection: Protection,
+}
+
+/// A write done by the instruction at `pc`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub pc: usize,
+    pub address: u128,
+    pub old: i64,
+    pub new: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Guard {
+    /// Whether there are regions or self-modification is tracked, the only test on the fast path.
+    active: bool,
+    regions: Vec<Region>,
+    watched_writes: Vec<MemoryWrite>,
+    /// Cells of executed instructions, `None` unless self-modification is tracked.
+    executed: Option<HashSet<u128>>,
+    self_modifications: Vec<MemoryWrite>,
+}
+
+impl Guard {
+    pub(crate) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Forgets what was recorded, keeping the regions and the tracking of self-modification.
+    pub(crate) fn reset(&mut self) {
+        self.watched_writes.clear();
+        if let Some(executed) = &mut self.executed {
+            executed.clear();
+        }
+        self.self_modifications.clear();
+    }
+}
+
+impl Region {
+    fn contains(&self, address: u128) -> bool {
+        self.range.start as u128 <= address && address < self.range.end as u128
+    }
+}
+
+impl Machine {
+    /// Number of cells of an instruction with the given op code, including the op code itself.
+    pub fn instruction_length(op: i64) -> usize {
+        match op {
+            1 | 2 | 7 | 8 => 4,
+            5 | 6 => 3,
+            3 | 4 | 9 => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn protect(&mut self, range: Range<usize>, protection: Protection) {
+        self.guard.regions.push(Region { range, protection });
+        self.guard.active = true;
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.guard.regions
+    }
+
+    /// Writes into [`Protection::Watch`] regions, oldest first.
+    pub fn watched_writes(&self) -> &[MemoryWrite] {
+        &self.guard.watched_writes
+    }
+
+    /// Starts recording writes to cells of already executed instructions.
+    pub fn track_self_modification(&mut self) {
+        if self.guard.executed.is_none() {
+            self.guard.executed = Some(HashSet::new());
+        }
+        self.guard.active = true;
+    }
+
+    /// Writes to cells of already executed instructions, oldest first.
+    pub fn self_modifications(&self) -> &[MemoryWrite] {
+        &self.guard.self_modifications
+    }
+
+    /// Only called while the guard [is active](Guard::is_active), like [`Machine::check_write`]
+    /// and [`Machine::record_write`].
+    pub(crate) fn check_execute(&mut self, op: i64) -> Result<(), MachineError> {
+        let pc = self.pc;
+        if let Some(region) = self
+            .guard
+            .regions
+            .iter()
+            .find(|r| r.protection == Protection::NoExecute && r.contains(pc as u128))
+        {
+            return Err(MachineError::ProtectionViolation {
+                pc,
+                address: pc as u128,
+                protection: region.protection,
+            });
+        }
+        if let Some(executed) = &mut self.guard.executed {
+            let start = pc as u128;
+            executed.extend(start..start + Self::instruction_length(op) as u128);
+        }
+        Ok(())
+    }
+
+    /// Checks a write before it is done.
+    pub(crate) fn check_write(&self, address: u128) -> Result<(), MachineError> {
+        if self
+            .guard
+            .regions
+            .iter()
+            .any(|r| r.protection == Protection::ReadOnly && r.contains(address))
+        {
+            return Err(MachineError::ProtectionViolation {
+                pc: self.pc,
+                address,
+                protection: Protection::ReadOnly,
+            });
+        }
+        Ok(())
+    }
+
+    /// Records a write once it succeeded, `old` is the value before.
+    pub(crate) fn record_write(&mut self, address: u128, old: i64, new: i64) {
+        let write = MemoryWrite {
+            pc: self.pc,
+            address,
+            old,
+            new,
+        };
+        if self
+            .guard
+            .regions
+            .iter()
+            .any(|r| r.protection == Protection::Watch && r.contains(address))
+        {
+            self.guard.watched_writes.push(write);
+        }
+        if let Some(executed) = &self.guard.executed {
+            if executed.contains(&address) {
+                self.guard.self_modifications.push(write);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RunOutcome;
+
+    #[test]
+    fn test_read_only() {
+        let mut machine = Machine::new(Machine::parse_code("1101,1,2,5,99,0"));
+        machine.protect(4..6, Protection::ReadOnly);
+        assert_eq!(
+            machine.try_step(),
+            Err(MachineError::ProtectionViolation {
+                pc: 0,
+                address: 5,
+                protection: Protection::ReadOnly
+            })
+        );
+        assert_eq!(machine.get_state(5), 0);
+    }
+
+    #[test]
+    fn test_no_execute() {
+        // jumps into the data behind the halt
+        let mut machine = Machine::new(Machine::parse_code("1105,1,4,99,99"));
+        machine.protect(4..5, Protection::NoExecute);
+        assert!(machine.try_step().is_ok());
+        assert_eq!(
+            machine.try_step(),
+            Err(MachineError::ProtectionViolation {
+                pc: 4,
+                address: 4,
+                protection: Protection::NoExecute
+            })
+        );
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut machine = Machine::new(Machine::parse_code("1101,1,2,9,1001,9,3,9,99,0"));
+        machine.protect(9..10, Protection::Watch);
+        assert!(matches!(machine.run_until_block(), RunOutcome::Halted(_)));
+        assert_eq!(
+            machine.watched_writes(),
+            &[
+                MemoryWrite {
+                    pc: 0,
+                    address: 9,
+                    old: 0,
+                    new: 3
+                },
+                MemoryWrite {
+                    pc: 4,
+                    address: 9,
+                    old: 3,
+                    new: 6
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_write_is_not_recorded() {
+        let mut machine = Machine::new(Machine::parse_code("1101,1,2,100,99"));
+        machine.protect(100..101, Protection::Watch);
+        machine.set_memory_limit(Some(0));
+        assert_eq!(
+            machine.try_step(),
+            Err(MachineError::MemoryLimitExceeded {
+                pc: 0,
+                address: 100
+            })
+        );
+        assert_eq!(machine.watched_writes(), &[]);
+    }
+
+    #[test]
+    fn test_self_modification() {
+        // day 2 style: the second instruction overwrites an operand of the first one, the third
+        // one patches the halt before it is executed
+        let mut machine = Machine::new(Machine::parse_code("1,0,0,13,1101,5,6,3,1101,0,99,12,0,0"));
+        machine.track_self_modification();
+        assert!(matches!(machine.run_until_block(), RunOutcome::Halted(_)));
+        assert_eq!(
+            machine.self_modifications(),
+            &[MemoryWrite {
+                pc: 4,
+                address: 3,
+                old: 13,
+                new: 11
+            }]
+        );
+    }
+}
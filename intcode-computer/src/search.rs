@@ -0,0 +1,176 @@
+//! Searches over parameter spaces, running a fresh [`Machine`] per candidate on all cores.
+//!
+//! Candidates are handed out in the order of the iterator. `setup` prepares the machine for a
+//! candidate by feeding inputs or poking memory, then the machine runs until it halts or needs
+//! more input and `score` judges the result from the machine and its outputs. Machines are not
+//! shared between threads, every thread loads its own from the [`Program`] and resets it for
+//! each candidate. A panic in a machine or closure is passed on once all threads stopped.
+
+use crate::{Machine, Program};
+use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Mutex;
+use std::thread;
+
+/// The winning candidate of a search with the outputs of its run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found<C, S> {
+    pub candidate: C,
+    pub score: S,
+    pub outputs: Vec<i64>,
+}
+
+/// Returns the first candidate, in the order of `candidates`, which `score` accepts with
+/// `Some`. Candidates after a match are not run, apart from those already in progress.
+pub fn first<C, I, S>(
+    program: &Program,
+    candidates: I,
+    setup: impl Fn(&mut Machine, &C) + Sync,
+    score: impl Fn(&Machine, &[i64]) -> Option<S> + Sync,
+) -> Option<Found<C, S>>
+where
+    I: IntoIterator<Item = C>,
+    I::IntoIter: Send,
+    C: Send,
+    S: Send,
+{
+    search(program, candidates, &setup, &score, None)
+}
+
+/// Returns the candidate with the highest score, the earliest one of equal scores. Candidates
+/// scored `None` are skipped.
+pub fn best<C, I, S>(
+    program: &Program,
+    candidates: I,
+    setup: impl Fn(&mut Machine, &C) + Sync,
+    score: impl Fn(&Machine, &[i64]) -> Option<S> + Sync,
+) -> Option<Found<C, S>>
+where
+    I: IntoIterator<Item = C>,
+    I::IntoIter: Send,
+    C: Send,
+    S: Ord + Send,
+{
+    search(program, candidates, &setup, &score, Some(&S::cmp))
+}
+
+type Compare<'a, S> = &'a (dyn Fn(&S, &S) -> Ordering + Sync);
+
+/// Runs all candidates, or with `compare` being `None` those up to the first match.
+fn search<C, I, S>(
+    program: &Program,
+    candidates: I,
+    setup: &(dyn Fn(&mut Machine, &C) + Sync),
+    score: &(dyn Fn(&Machine, &[i64]) -> Option<S> + Sync),
+    compare: Option<Compare<S>>,
+) -> Option<Found<C, S>>
+where
+    I: IntoIterator<Item = C>,
+    I::IntoIter: Send,
+    C: Send,
+    S: Send,
+{
+    let candidates = Mutex::new(candidates.into_iter().enumerate());
+    // index of the first match, no later candidates are started once it is known
+    let first_match = AtomicUsize::new(usize::MAX);
+    let winner: Mutex<Option<(usize, Found<C, S>)>> = Mutex::new(None);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                let mut machine = Machine::new(program);
+                loop {
+                    let next = candidates.lock().unwrap().next();
+                    let (index, candidate) = match next {
+                        Some(next) => next,
+                        None => return,
+                    };
+                    if compare.is_none() && index > first_match.load(atomic::Ordering::Relaxed) {
+                        return;
+                    }
+                    machine.reset();
+                    setup(&mut machine, &candidate);
+                    machine.run_until_block();
+                    let outputs = machine.drain_output();
+                    let score = match score(&machine, &outputs) {
+                        Some(score) => score,
+                        None => continue,
+                    };
+                    first_match.fetch_min(index, atomic::Ordering::Relaxed);
+
+                    let mut winner = winner.lock().unwrap();
+                    let better = match (&*winner, compare) {
+                        (None, _) => true,
+                        (Some((best, _)), None) => index < *best,
+                        (Some((best, found)), Some(compare)) => {
+                            match compare(&score, &found.score) {
+                                Ordering::Greater => true,
+                                Ordering::Equal => index < *best,
+                                Ordering::Less => false,
+                            }
+                        }
+                    };
+                    if better {
+                        let found = Found {
+                            candidate,
+                            score,
+                            outputs,
+                        };
+                        *winner = Some((index, found));
+                    }
+                }
+            });
+        }
+    });
+    winner.into_inner().unwrap().map(|(_, found)| found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first() {
+        // outputs the input times 7 plus the noun at 14
+        let code = "3,13,1002,13,7,13,1,13,14,13,4,13,99,0,0";
+        let program = Program::new(Machine::parse_code(code));
+        let found = first(
+            &program,
+            (0..10).flat_map(|noun| (0..1000).map(move |input| (noun, input))),
+            |machine, &(noun, input)| {
+                machine.set_state(14, noun);
+                machine.add_input(input);
+            },
+            |_, outputs| Some(outputs[0]).filter(|output| output % 100 == 42),
+        )
+        .unwrap();
+        // 7 * 6 + 0 is the first, later matches like 7 * 106 are not taken
+        assert_eq!(found.candidate, (0, 6));
+        assert_eq!(found.score, 42);
+        assert_eq!(found.outputs, vec![42]);
+
+        let none = first(&program, 0..10, |m, &i| m.add_input(i), |_, _| None::<()>);
+        assert_eq!(none, None);
+    }
+
+    #[test]
+    fn test_best() {
+        // day 2 style, scores the value at 0 after adding the noun and verb cells
+        let program = Program::new(Machine::parse_code("1,0,0,0,99,5,9,2,8"));
+        let found = best(
+            &program,
+            (5..9).flat_map(|noun| (5..9).map(move |verb| (noun, verb))),
+            |machine, &(noun, verb)| {
+                machine.set_state(1, noun);
+                machine.set_state(2, verb);
+            },
+            |machine, _| Some(machine.get_state(0)),
+        )
+        .unwrap();
+        // the 9 at 6 added to itself
+        assert_eq!(found.candidate, (6, 6));
+        assert_eq!(found.score, 18);
+        assert!(found.outputs.is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+//! Machine sessions driven by a line protocol, served over TCP by the `intcode-server` binary.
+//!
+//! Every request is one line of words separated by spaces and gets one response line, `ok`
+//! followed by the results or `error` followed by a message:
+//!
+//! ```text
+//! load <program> [<limit>]    ok <session>    program as comma separated values
+//! input <session> <value>...  ok
+//! run <session>               ok halted | ok blocked | ok limit
+//! output <session>            ok <value>...   takes the outputs produced so far
+//! snapshot <session>          ok <session>    a copy of the session which runs on its own
+//! kill <session>              ok
+//! ```
+//!
+//! `run` runs until the program halts, blocks on input or used up the instruction limit of its
+//! session, which counts all instructions since the load. Without `<limit>` the session gets the
+//! limit of [`Sessions::new`], which also caps the given limits. Sessions may write as many cells
+//! beyond their program as [`Sessions::set_memory_limit`] allows. Programs which fail keep their
+//! session, further runs fail the same way.
+
+use crate::{Machine, Program, StepResult};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Load {
+        program: Program,
+        limit: Option<u64>,
+    },
+    Input {
+        session: u64,
+        values: Vec<i64>,
+    },
+    Run(u64),
+    Output(u64),
+    Snapshot(u64),
+    Kill(u64),
+}
+
+fn parse<T: FromStr>(word: Option<&str>, what: &str) -> Result<T, String> {
+    let word = word.ok_or_else(|| format!("missing {}", what))?;
+    word.parse()
+        .map_err(|_| format!("invalid {} {:?}", what, word))
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("empty request")?;
+        let command = match name {
+            "load" => {
+                let program = words.next().ok_or("missing program")?;
+                Command::Load {
+                    program: Program::parse(program).map_err(|error| error.to_string())?,
+                    limit: match words.next() {
+                        Some(limit) => Some(parse(Some(limit), "limit")?),
+                        None => None,
+                    },
+                }
+            }
+            "input" => Command::Input {
+                session: parse(words.next(), "session")?,
+                values: words
+                    .by_ref()
+                    .map(|value| parse(Some(value), "input"))
+                    .collect::<Result<_, _>>()?,
+            },
+            "run" => Command::Run(parse(words.next(), "session")?),
+            "output" => Command::Output(parse(words.next(), "session")?),
+            "snapshot" => Command::Snapshot(parse(words.next(), "session")?),
+            "kill" => Command::Kill(parse(words.next(), "session")?),
+            _ => return Err(format!("unknown request {}", name)),
+        };
+        match words.next() {
+            Some(word) => Err(format!("unexpected {:?}", word)),
+            None => Ok(command),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Session {
+    machine: Machine,
+    limit: u64,
+}
+
+/// The sessions of a server, numbered from 1.
+#[derive(Debug)]
+pub struct Sessions {
+    sessions: BTreeMap<u64, Session>,
+    last: u64,
+    limit: u64,
+    memory_limit: Option<usize>,
+}
+
+impl Sessions {
+    /// Sessions may execute at most `limit` instructions, also when loaded with a higher limit.
+    pub fn new(limit: u64) -> Sessions {
+        Sessions {
+            sessions: BTreeMap::new(),
+            last: 0,
+            limit,
+            memory_limit: None,
+        }
+    }
+
+    /// The memory limit of the machines loaded from now on, see [`Machine::set_memory_limit`].
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    fn add(&mut self, session: Session) -> u64 {
+        self.last += 1;
+        self.sessions.insert(self.last, session);
+        self.last
+    }
+
+    fn session(&mut self, id: u64) -> Result<&mut Session, String> {
+        self.sessions
+            .get_mut(&id)
+            .ok_or_else(|| format!("unknown session {}", id))
+    }
+
+    /// Executes `command` and returns the results of the response.
+    pub fn execute(&mut self, command: Command) -> Result<String, String> {
+        match command {
+            Command::Load { program, limit } => {
+                let mut machine = Machine::new(program);
+                machine.set_memory_limit(self.memory_limit);
+                let session = Session {
+                    machine,
+                    limit: limit.map_or(self.limit, |limit| limit.min(self.limit)),
+                };
+                Ok(self.add(session).to_string())
+            }
+            Command::Input { session, values } => {
+                let machine = &mut self.session(session)?.machine;
+                for value in values {
+                    machine.add_input(value);
+                }
+                Ok(String::new())
+            }
+            Command::Run(session) => {
+                let session = self.session(session)?;
+                let machine = &mut session.machine;
+                while machine.instructions() < session.limit {
+                    match machine.try_step() {
+                        Ok(StepResult::Continue) => {}
+                        Ok(StepResult::Halt(_)) => return Ok("halted".to_string()),
+                        Ok(StepResult::NeedsInput) => return Ok("blocked".to_string()),
+                        Err(error) => return Err(error.to_string()),
+                    }
+                }
+                Ok("limit".to_string())
+            }
+            Command::Output(session) => {
+                let outputs = self.session(session)?.machine.drain_output();
+                let outputs: Vec<String> = outputs.iter().map(|value| value.to_string()).collect();
+                Ok(outputs.join(" "))
+            }
+            Command::Snapshot(session) => {
+                let session = self.session(session)?;
+                let copy = Session {
+                    machine: session.machine.fork(),
+                    limit: session.limit,
+                };
+                Ok(self.add(copy).to_string())
+            }
+            Command::Kill(session) => {
+                self.sessions
+                    .remove(&session)
+                    .ok_or_else(|| format!("unknown session {}", session))?;
+                Ok(String::new())
+            }
+        }
+    }
+
+    /// Parses and executes a request line and returns the response line without line break.
+    pub fn respond(&mut self, line: &str) -> String {
+        match Command::parse(line).and_then(|command| self.execute(command)) {
+            Ok(results) if results.is_empty() => "ok".to_string(),
+            Ok(results) => format!("ok {}", results),
+            Err(message) => format!("error {}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions() {
+        let mut sessions = Sessions::new(1000);
+        // doubles its inputs
+        assert_eq!(
+            sessions.respond("load 3,11,1002,11,2,11,4,11,1105,1,0,0"),
+            "ok 1"
+        );
+        assert_eq!(sessions.respond("input 1 5 6"), "ok");
+        assert_eq!(sessions.respond("run 1"), "ok blocked");
+        assert_eq!(sessions.respond("output 1"), "ok 10 12");
+
+        assert_eq!(sessions.respond("snapshot 1"), "ok 2");
+        assert_eq!(sessions.respond("input 2 -4"), "ok");
+        assert_eq!(sessions.respond("run 2"), "ok blocked");
+        assert_eq!(sessions.respond("output 2"), "ok -8");
+        assert_eq!(sessions.respond("output 1"), "ok");
+        assert_eq!(sessions.respond("kill 1"), "ok");
+        assert_eq!(sessions.respond("run 1"), "error unknown session 1");
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_limits_and_errors() {
+        let mut sessions = Sessions::new(1000);
+        assert_eq!(sessions.respond("load 1105,1,0 5"), "ok 1");
+        assert_eq!(sessions.respond("run 1"), "ok limit");
+        assert_eq!(sessions.respond("run 1"), "ok limit");
+        assert_eq!(sessions.respond("load 1105,1,0"), "ok 2");
+        assert_eq!(sessions.respond("run 2"), "ok limit");
+        assert_eq!(sessions.respond("load 99"), "ok 3");
+        assert_eq!(sessions.respond("run 3"), "ok halted");
+
+        assert_eq!(sessions.respond("load 1,-1,0,0,99"), "ok 4");
+        assert_eq!(sessions.respond("run 4"), "error negativ address -1 at 0");
+        assert_eq!(
+            sessions.respond("load 1,x"),
+            "error value 1 is not an integer: \"x\""
+        );
+        // the server limit caps the limit of the session
+        assert_eq!(sessions.respond("load 1105,1,0 5000"), "ok 5");
+        assert_eq!(sessions.respond("run 5"), "ok limit");
+        assert_eq!(sessions.sessions[&5].machine.instructions(), 1000);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut sessions = Sessions::new(1000);
+        sessions.set_memory_limit(Some(10));
+        // writes 7 to ever new cells behind the program
+        assert_eq!(
+            sessions.respond("load 109,1,21101,7,0,1000,1105,1,0"),
+            "ok 1"
+        );
+        assert_eq!(
+            sessions.respond("run 1"),
+            "error memory limit exceeded writing 1011 at 2"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(Command::parse("run 3"), Ok(Command::Run(3))));
+        assert!(matches!(
+            Command::parse(" input 1 5  -7"),
+            Ok(Command::Input { session: 1, values }) if values == vec![5, -7]
+        ));
+        assert!(matches!(
+            Command::parse("load 1,2,3 7"),
+            Ok(Command::Load { program, limit: Some(7) }) if program.len() == 3
+        ));
+        let error = |line| Command::parse(line).unwrap_err();
+        assert_eq!(error("run"), "missing session");
+        assert_eq!(error("kill x"), "invalid session \"x\"");
+        assert_eq!(error("run 1 2"), "unexpected \"2\"");
+        assert_eq!(error("jump 1"), "unknown request jump");
+    }
+}
@@ -0,0 +1,700 @@
+//! Symbolic execution of Intcode programs.
+//!
+//! Selected memory cells and inputs are replaced by symbols with a range of possible values. The
+//! [`Executor`] runs the program on expression trees, forks at conditional jumps on symbolic
+//! conditions and finally searches concrete values for the symbols which reach a [`Target`].
+//!
+//! The search solves the target directly when it is affine in the symbols, as for day 2, and falls
+//! back to trying all combinations of small symbol ranges otherwise.
+
+use crate::Machine;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::ops::{Index, Range};
+use std::rc::Rc;
+
+/// Combinations tried when the target is not affine in the symbols.
+const SEARCH_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Const(i64),
+    Symbol(Symbol),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    Lt(Rc<Expr>, Rc<Expr>),
+    Eq(Rc<Expr>, Rc<Expr>),
+    /// Memory read through a symbolic address, `memory` is the content at the time of the read.
+    Load {
+        address: Rc<Expr>,
+        memory: Rc<Memory>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memory {
+    cells: Vec<Rc<Expr>>,
+    extended: HashMap<u128, Rc<Expr>>,
+}
+
+/// A condition of a path: `expr` is non-zero if `nonzero`, zero otherwise.
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    pub expr: Rc<Expr>,
+    pub nonzero: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathEnd {
+    Halt,
+    NeedsInput,
+    /// The path reached the pc passed to [`Executor::explore`].
+    ReachedPc,
+    StepLimit,
+    /// An error of the program or a construct the executor cannot follow symbolically, like a
+    /// jump to a symbolic address.
+    Unsupported(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub end: PathEnd,
+    pub pc: usize,
+    pub constraints: Vec<Constraint>,
+    pub outputs: Vec<Rc<Expr>>,
+    pub memory: Memory,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// The output with the given index has the value.
+    Output { index: usize, value: i64 },
+    /// The memory cell has the value once the program halts.
+    Memory { address: usize, value: i64 },
+    /// Execution reaches the pc.
+    Pc(usize),
+}
+
+/// Concrete symbol values, indexed by [`Symbol`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    values: Vec<i64>,
+}
+
+#[derive(Debug, Clone)]
+enum Input {
+    Concrete(i64),
+    Symbolic(Symbol),
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    pc: usize,
+    relative_base: i64,
+    memory: Memory,
+    next_input: usize,
+    outputs: Vec<Rc<Expr>>,
+    constraints: Vec<Constraint>,
+    steps: usize,
+}
+
+enum Step {
+    Continue,
+    End(PathEnd),
+    Fork {
+        condition: Rc<Expr>,
+        jump_if_nonzero: bool,
+        target: usize,
+    },
+}
+
+pub struct Executor {
+    code: Vec<i64>,
+    ranges: Vec<Range<i64>>,
+    symbolic_cells: Vec<(usize, Symbol)>,
+    inputs: Vec<Input>,
+    max_paths: usize,
+    max_steps: usize,
+}
+
+impl Expr {
+    fn constant(&self) -> Option<i64> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The sum, `None` if both are constants whose sum overflows.
+    fn add(a: Rc<Expr>, b: Rc<Expr>) -> Option<Rc<Expr>> {
+        Some(match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Rc::new(Expr::Const(a.checked_add(b)?)),
+            (Some(0), _) => b,
+            (_, Some(0)) => a,
+            _ => Rc::new(Expr::Add(a, b)),
+        })
+    }
+
+    /// The product, `None` if both are constants whose product overflows.
+    fn mul(a: Rc<Expr>, b: Rc<Expr>) -> Option<Rc<Expr>> {
+        Some(match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Rc::new(Expr::Const(a.checked_mul(b)?)),
+            (Some(0), _) | (_, Some(0)) => Rc::new(Expr::Const(0)),
+            (Some(1), _) => b,
+            (_, Some(1)) => a,
+            _ => Rc::new(Expr::Mul(a, b)),
+        })
+    }
+
+    fn lt(a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Rc::new(Expr::Const((a < b) as i64)),
+            _ => Rc::new(Expr::Lt(a, b)),
+        }
+    }
+
+    fn eq(a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Rc::new(Expr::Const((a == b) as i64)),
+            _ => Rc::new(Expr::Eq(a, b)),
+        }
+    }
+
+    /// Evaluates the expression for the given symbol values, `None` if the computation
+    /// overflows like it does on a [`Machine`].
+    pub fn eval(&self, values: &[i64]) -> Option<i64> {
+        Some(match self {
+            Expr::Const(value) => *value,
+            Expr::Symbol(symbol) => values[symbol.0],
+            Expr::Add(a, b) => a.eval(values)?.checked_add(b.eval(values)?)?,
+            Expr::Mul(a, b) => a.eval(values)?.checked_mul(b.eval(values)?)?,
+            Expr::Lt(a, b) => (a.eval(values)? < b.eval(values)?) as i64,
+            Expr::Eq(a, b) => (a.eval(values)? == b.eval(values)?) as i64,
+            Expr::Load { address, memory } => {
+                let address = address.eval(values)?;
+                if address < 0 {
+                    0
+                } else {
+                    memory.read(address as u128).eval(values)?
+                }
+            }
+        })
+    }
+
+    /// Returns the coefficients per symbol and the constant if the expression is affine and they
+    /// do not overflow.
+    fn affine(&self) -> Option<(BTreeMap<Symbol, i64>, i64)> {
+        match self {
+            Expr::Const(value) => Some((BTreeMap::new(), *value)),
+            Expr::Symbol(symbol) => Some((vec![(*symbol, 1)].into_iter().collect(), 0)),
+            Expr::Add(a, b) => {
+                let (mut coefficients, a) = a.affine()?;
+                let (other, b) = b.affine()?;
+                for (symbol, factor) in other {
+                    let coefficient = coefficients.entry(symbol).or_insert(0);
+                    *coefficient = coefficient.checked_add(factor)?;
+                }
+                Some((coefficients, a.checked_add(b)?))
+            }
+            Expr::Mul(a, b) => {
+                let (factor, (coefficients, constant)) = match (a.constant(), b.constant()) {
+                    (Some(factor), _) => (factor, b.affine()?),
+                    (_, Some(factor)) => (factor, a.affine()?),
+                    _ => return None,
+                };
+                let coefficients = coefficients
+                    .into_iter()
+                    .map(|(symbol, c)| Some((symbol, c.checked_mul(factor)?)))
+                    .collect::<Option<_>>()?;
+                Some((coefficients, constant.checked_mul(factor)?))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Symbol(symbol) => write!(f, "x{}", symbol.0),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "{} * {}", a, b),
+            Expr::Lt(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Eq(a, b) => write!(f, "({} == {})", a, b),
+            Expr::Load { address, .. } => write!(f, "[{}]", address),
+        }
+    }
+}
+
+impl Memory {
+    fn read(&self, address: u128) -> Rc<Expr> {
+        if address < self.cells.len() as u128 {
+            self.cells[address as usize].clone()
+        } else {
+            self.extended
+                .get(&address)
+                .cloned()
+                .unwrap_or_else(|| Rc::new(Expr::Const(0)))
+        }
+    }
+
+    fn write(&mut self, address: u128, value: Rc<Expr>) {
+        if address < self.cells.len() as u128 {
+            self.cells[address as usize] = value;
+        } else {
+            self.extended.insert(address, value);
+        }
+    }
+
+    pub fn get(&self, address: usize) -> Rc<Expr> {
+        self.read(address as u128)
+    }
+}
+
+impl Constraint {
+    /// Whether the condition holds, a path whose condition overflows ends there.
+    fn holds(&self, values: &[i64]) -> bool {
+        self.expr
+            .eval(values)
+            .is_some_and(|value| (value != 0) == self.nonzero)
+    }
+}
+
+impl Index<Symbol> for Solution {
+    type Output = i64;
+
+    fn index(&self, symbol: Symbol) -> &i64 {
+        &self.values[symbol.0]
+    }
+}
+
+impl State {
+    fn overflow(&self) -> PathEnd {
+        PathEnd::Unsupported(format!("integer overflow at {}", self.pc))
+    }
+
+    fn concrete(&self, expr: &Expr, what: &str) -> Result<i64, PathEnd> {
+        expr.constant()
+            .ok_or_else(|| PathEnd::Unsupported(format!("symbolic {} at {}", what, self.pc)))
+    }
+
+    fn raw(&self, offset: usize) -> Rc<Expr> {
+        self.memory.read(self.pc as u128 + offset as u128)
+    }
+
+    fn address(&self, mode: u8, value: i64) -> Result<u128, PathEnd> {
+        let address = match mode {
+            0 => value as i128,
+            2 => value as i128 + self.relative_base as i128,
+            mode => {
+                return Err(PathEnd::Unsupported(format!(
+                    "invalid mode {} at {}",
+                    mode, self.pc
+                )))
+            }
+        };
+        if address < 0 {
+            return Err(PathEnd::Unsupported(format!(
+                "negative address at {}",
+                self.pc
+            )));
+        }
+        Ok(address as u128)
+    }
+
+    fn param(&self, mode: u8, offset: usize) -> Result<Rc<Expr>, PathEnd> {
+        let raw = self.raw(offset);
+        if mode == 1 {
+            return Ok(raw);
+        }
+        match raw.constant() {
+            Some(value) => Ok(self.memory.read(self.address(mode, value)?)),
+            None if mode == 0 => Ok(Rc::new(Expr::Load {
+                address: raw,
+                memory: Rc::new(self.memory.clone()),
+            })),
+            None => Ok(Rc::new(Expr::Load {
+                address: Expr::add(raw, Rc::new(Expr::Const(self.relative_base)))
+                    .ok_or_else(|| self.overflow())?,
+                memory: Rc::new(self.memory.clone()),
+            })),
+        }
+    }
+
+    fn write(&mut self, mode: u8, offset: usize, value: Rc<Expr>) -> Result<(), PathEnd> {
+        let raw = self.raw(offset);
+        let address = self.address(mode, self.concrete(&raw, "write address")?)?;
+        self.memory.write(address, value);
+        Ok(())
+    }
+
+    fn step(&mut self, inputs: &[Input]) -> Result<Step, PathEnd> {
+        let instruction = self.concrete(&self.raw(0), "instruction")?;
+        let mode = Machine::get_mode_digits(instruction);
+        match instruction % 100 {
+            op @ 1 | op @ 2 | op @ 7 | op @ 8 => {
+                let in1 = self.param(mode[0], 1)?;
+                let in2 = self.param(mode[1], 2)?;
+                let value = match op {
+                    1 => Expr::add(in1, in2).ok_or_else(|| self.overflow())?,
+                    2 => Expr::mul(in1, in2).ok_or_else(|| self.overflow())?,
+                    7 => Expr::lt(in1, in2),
+                    _ => Expr::eq(in1, in2),
+                };
+                self.write(mode[2], 3, value)?;
+                self.pc += 4;
+            }
+            3 => {
+                let value = match inputs.get(self.next_input) {
+                    None => return Ok(Step::End(PathEnd::NeedsInput)),
+                    Some(Input::Concrete(value)) => Rc::new(Expr::Const(*value)),
+                    Some(Input::Symbolic(symbol)) => Rc::new(Expr::Symbol(*symbol)),
+                };
+                self.write(mode[0], 1, value)?;
+                self.next_input += 1;
+                self.pc += 2;
+            }
+            4 => {
+                let value = self.param(mode[0], 1)?;
+                self.outputs.push(value);
+                self.pc += 2;
+            }
+            op @ 5 | op @ 6 => {
+                let condition = self.param(mode[0], 1)?;
+                let target = self.param(mode[1], 2)?;
+                let target = self.concrete(&target, "jump target")?;
+                if target < 0 {
+                    return Err(PathEnd::Unsupported(format!(
+                        "negative jump target at {}",
+                        self.pc
+                    )));
+                }
+                let jump_if_nonzero = op == 5;
+                match condition.constant() {
+                    Some(value) if (value != 0) == jump_if_nonzero => self.pc = target as usize,
+                    Some(_) => self.pc += 3,
+                    None => {
+                        return Ok(Step::Fork {
+                            condition,
+                            jump_if_nonzero,
+                            target: target as usize,
+                        })
+                    }
+                }
+            }
+            9 => {
+                let value = self.param(mode[0], 1)?;
+                let offset = self.concrete(&value, "relative base offset")?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(offset)
+                    .ok_or_else(|| self.overflow())?;
+                self.pc += 2;
+            }
+            99 => return Ok(Step::End(PathEnd::Halt)),
+            opcode => {
+                return Err(PathEnd::Unsupported(format!(
+                    "unknown op code {} at {}",
+                    opcode, self.pc
+                )))
+            }
+        }
+        Ok(Step::Continue)
+    }
+}
+
+impl Executor {
+    pub fn new(code: Vec<i64>) -> Executor {
+        Executor {
+            code,
+            ranges: vec![],
+            symbolic_cells: vec![],
+            inputs: vec![],
+            max_paths: 1000,
+            max_steps: 1_000_000,
+        }
+    }
+
+    /// Limits the number of explored paths and the steps per path.
+    pub fn set_limits(&mut self, max_paths: usize, max_steps: usize) {
+        self.max_paths = max_paths;
+        self.max_steps = max_steps;
+    }
+
+    fn new_symbol(&mut self, range: Range<i64>) -> Symbol {
+        self.ranges.push(range);
+        Symbol(self.ranges.len() - 1)
+    }
+
+    /// Replaces the initial content of a memory cell, like day 2's noun at address 1.
+    pub fn symbolize_memory(&mut self, address: usize, range: Range<i64>) -> Symbol {
+        let symbol = self.new_symbol(range);
+        self.symbolic_cells.push((address, symbol));
+        symbol
+    }
+
+    /// Appends a symbolic value to the input.
+    pub fn symbolic_input(&mut self, range: Range<i64>) -> Symbol {
+        let symbol = self.new_symbol(range);
+        self.inputs.push(Input::Symbolic(symbol));
+        symbol
+    }
+
+    pub fn add_input(&mut self, value: i64) {
+        self.inputs.push(Input::Concrete(value));
+    }
+
+    fn initial_state(&self) -> State {
+        let mut memory = Memory {
+            cells: self.code.iter().map(|v| Rc::new(Expr::Const(*v))).collect(),
+            extended: HashMap::new(),
+        };
+        for (address, symbol) in &self.symbolic_cells {
+            memory.write(*address as u128, Rc::new(Expr::Symbol(*symbol)));
+        }
+        State {
+            pc: 0,
+            relative_base: 0,
+            memory,
+            next_input: 0,
+            outputs: vec![],
+            constraints: vec![],
+            steps: 0,
+        }
+    }
+
+    /// Follows every path of the program, stopping paths early which reach `stop_at`.
+    pub fn explore(&self, stop_at: Option<usize>) -> Vec<Path> {
+        let mut paths = vec![];
+        let mut pending = VecDeque::new();
+        pending.push_back(self.initial_state());
+
+        while let Some(mut state) = pending.pop_back() {
+            if paths.len() + pending.len() >= self.max_paths {
+                break;
+            }
+            let end = loop {
+                if Some(state.pc) == stop_at {
+                    break PathEnd::ReachedPc;
+                }
+                if state.steps >= self.max_steps {
+                    break PathEnd::StepLimit;
+                }
+                state.steps += 1;
+                match state.step(&self.inputs) {
+                    Ok(Step::Continue) => {}
+                    Ok(Step::End(end)) | Err(end) => break end,
+                    Ok(Step::Fork {
+                        condition,
+                        jump_if_nonzero,
+                        target,
+                    }) => {
+                        let mut jumped = state.clone();
+                        jumped.pc = target;
+                        jumped.constraints.push(Constraint {
+                            expr: condition.clone(),
+                            nonzero: jump_if_nonzero,
+                        });
+                        pending.push_back(jumped);
+                        state.pc += 3;
+                        state.constraints.push(Constraint {
+                            expr: condition,
+                            nonzero: !jump_if_nonzero,
+                        });
+                    }
+                }
+            };
+            paths.push(Path {
+                end,
+                pc: state.pc,
+                constraints: state.constraints,
+                outputs: state.outputs,
+                memory: state.memory,
+            });
+        }
+        paths
+    }
+
+    /// Searches symbol values which make the program reach the target.
+    pub fn solve(&self, target: Target) -> Option<Solution> {
+        let stop_at = match target {
+            Target::Pc(pc) => Some(pc),
+            _ => None,
+        };
+        for path in self.explore(stop_at) {
+            let goal = match (target, &path.end) {
+                (Target::Output { index, value }, _) => match path.outputs.get(index) {
+                    Some(expr) => Some((expr.clone(), value)),
+                    None => continue,
+                },
+                (Target::Memory { address, value }, PathEnd::Halt) => {
+                    Some((path.memory.get(address), value))
+                }
+                (Target::Pc(_), PathEnd::ReachedPc) => None,
+                _ => continue,
+            };
+            if let Some(values) = self.find_values(goal, &path.constraints) {
+                return Some(Solution { values });
+            }
+        }
+        None
+    }
+
+    fn find_values(
+        &self,
+        goal: Option<(Rc<Expr>, i64)>,
+        constraints: &[Constraint],
+    ) -> Option<Vec<i64>> {
+        let holds = |values: &[i64]| constraints.iter().all(|c| c.holds(values));
+        let mut values: Vec<i64> = self.ranges.iter().map(|r| r.start).collect();
+
+        let (expr, value) = match goal {
+            Some(goal) => goal,
+            None => return self.search(&mut values, None, &|values| holds(values)),
+        };
+        let affine = expr.affine().and_then(|(coefficients, _)| {
+            coefficients
+                .into_iter()
+                .rev()
+                .find(|(_, factor)| *factor != 0)
+        });
+        match affine {
+            // solve `factor * symbol == value - other terms` for the last symbol
+            Some((symbol, factor)) => {
+                let range = self.ranges[symbol.0].clone();
+                let check = |values: &mut Vec<i64>| {
+                    values[symbol.0] = 0;
+                    let solved = expr
+                        .eval(values)
+                        .and_then(|other| value.checked_sub(other))
+                        .filter(|remaining| remaining.checked_rem(factor) == Some(0))
+                        .and_then(|remaining| remaining.checked_div(factor));
+                    match solved {
+                        Some(solved) if range.contains(&solved) => values[symbol.0] = solved,
+                        _ => return false,
+                    }
+                    // the terms may overflow for the solved value
+                    expr.eval(values) == Some(value) && holds(values)
+                };
+                self.search(&mut values, Some(symbol), &check)
+            }
+            None => self.search(&mut values, None, &|values| {
+                expr.eval(values) == Some(value) && holds(values)
+            }),
+        }
+    }
+
+    /// Tries every combination of symbol values until `accept` holds. `accept` sets the `solved`
+    /// symbol itself.
+    fn search(
+        &self,
+        values: &mut Vec<i64>,
+        solved: Option<Symbol>,
+        accept: &dyn Fn(&mut Vec<i64>) -> bool,
+    ) -> Option<Vec<i64>> {
+        if self.ranges.iter().any(|r| r.start >= r.end) {
+            return None;
+        }
+        let free: Vec<usize> = (0..self.ranges.len())
+            .filter(|i| solved != Some(Symbol(*i)))
+            .collect();
+        for _ in 0..SEARCH_LIMIT {
+            if accept(values) {
+                return Some(values.clone());
+            }
+            // advance to the next combination like an odometer
+            let mut done = true;
+            for i in free.iter() {
+                values[*i] += 1;
+                if values[*i] < self.ranges[*i].end {
+                    done = false;
+                    break;
+                }
+                values[*i] = self.ranges[*i].start;
+            }
+            if done {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_affine_memory() {
+        // memory[0] = 100 * noun + verb + 7
+        let code = Machine::parse_code("1,0,0,0,1002,1,100,17,1,17,2,17,1001,17,7,0,99,0");
+        let mut executor = Executor::new(code.clone());
+        let noun = executor.symbolize_memory(1, 0..100);
+        let verb = executor.symbolize_memory(2, 0..100);
+        let solution = executor
+            .solve(Target::Memory {
+                address: 0,
+                value: 100 * 42 + 17 + 7,
+            })
+            .unwrap();
+        assert_eq!((solution[noun], solution[verb]), (42, 17));
+
+        let mut machine = Machine::new(code);
+        let _ = machine.run(solution[noun], solution[verb]);
+        assert_eq!(machine.get_state(0), 100 * 42 + 17 + 7);
+    }
+
+    #[test]
+    fn test_solve_input_through_branches() {
+        // outputs 999 below 8, 1000 for 8 and 1001 above 8
+        let code = Machine::parse_code(
+            "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,\
+             1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,\
+             999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99",
+        );
+        let mut executor = Executor::new(code);
+        let input = executor.symbolic_input(-100..100);
+        for (value, expected) in &[(1000, 8), (1001, 9), (999, -100)] {
+            let target = Target::Output {
+                index: 0,
+                value: *value,
+            };
+            assert_eq!(executor.solve(target).unwrap()[input], *expected);
+        }
+        assert_eq!(executor.solve(Target::Output { index: 0, value: 5 }), None);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let end = |code: &str| {
+            let executor = Executor::new(Machine::parse_code(code));
+            executor.explore(None).remove(0).end
+        };
+        let overflow = |pc| PathEnd::Unsupported(format!("integer overflow at {}", pc));
+        assert_eq!(end("1101,9223372036854775807,1,0,99"), overflow(0));
+        assert_eq!(end("109,9223372036854775807,109,1,99"), overflow(2));
+
+        // outputs the negated input, no input gives i64::MIN
+        let code = Machine::parse_code("3,9,1002,9,-1,9,4,9,99,0");
+        let mut executor = Executor::new(code);
+        executor.symbolic_input(-10..10);
+        let target = Target::Output {
+            index: 0,
+            value: i64::MIN,
+        };
+        assert_eq!(executor.solve(target), None);
+    }
+
+    #[test]
+    fn test_solve_pc() {
+        // jumps to 10 only for inputs equal to 3
+        let code = Machine::parse_code("3,11,1008,11,3,11,1005,11,10,99,99,0");
+        let mut executor = Executor::new(code);
+        let input = executor.symbolic_input(0..10);
+        assert_eq!(executor.solve(Target::Pc(10)).unwrap()[input], 3);
+    }
+}
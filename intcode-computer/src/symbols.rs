@@ -0,0 +1,280 @@
+//! Symbol maps naming addresses of a program, for reading traces, errors and disassembly.
+//!
+//! The text format has one `name = address` per line, `#` starts a comment:
+//!
+//! ```text
+//! # day 13
+//! main_loop = 120
+//! score = 386
+//! ```
+//!
+//! Names are made of ASCII letters, digits, `_` and `.` and don't start with a digit. Each name
+//! and each address appears at most once. Data addresses are shown by their exact name, code
+//! addresses relative to the closest name before them, like `main_loop+4`.
+
+use crate::image::Symbol;
+use crate::MachineError;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    names: BTreeMap<usize, String>,
+    addresses: HashMap<String, usize>,
+}
+
+#[derive(Debug)]
+pub enum SymbolError {
+    Io(io::Error),
+    /// A line which is not `name = address`, `line` counts from 1.
+    Syntax {
+        line: usize,
+    },
+    InvalidName {
+        line: usize,
+        name: String,
+    },
+    Duplicate {
+        line: usize,
+        name: String,
+    },
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolError::Io(error) => write!(f, "{}", error),
+            SymbolError::Syntax { line } => write!(f, "expected name = address in line {}", line),
+            SymbolError::InvalidName { line, name } => {
+                write!(f, "invalid symbol name {:?} in line {}", name, line)
+            }
+            SymbolError::Duplicate { line, name } => {
+                write!(f, "{} or its address defined twice in line {}", name, line)
+            }
+        }
+    }
+}
+
+impl Error for SymbolError {}
+
+impl From<io::Error> for SymbolError {
+    fn from(error: io::Error) -> SymbolError {
+        SymbolError::Io(error)
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl SymbolMap {
+    pub fn new() -> SymbolMap {
+        SymbolMap::default()
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolMap, SymbolError> {
+        let mut map = SymbolMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let address = parts
+                .next()
+                .and_then(|address| address.trim().parse().ok())
+                .ok_or(SymbolError::Syntax { line: line_number })?;
+            if !is_valid_name(name) {
+                return Err(SymbolError::InvalidName {
+                    line: line_number,
+                    name: name.to_string(),
+                });
+            }
+            if map.addresses.contains_key(name) || map.names.contains_key(&address) {
+                return Err(SymbolError::Duplicate {
+                    line: line_number,
+                    name: name.to_string(),
+                });
+            }
+            map.insert(name, address);
+        }
+        Ok(map)
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<SymbolMap, SymbolError> {
+        SymbolMap::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Writes the map in text form, ordered by address.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Names `address`, replacing earlier entries with the same name or address. Panics on a
+    /// name which is not valid in the text format.
+    pub fn insert(&mut self, name: &str, address: usize) {
+        assert!(is_valid_name(name), "Invalid symbol name {:?}", name);
+        if let Some(old) = self.addresses.remove(name) {
+            self.names.remove(&old);
+        }
+        if let Some(old) = self.names.insert(address, name.to_string()) {
+            self.addresses.remove(&old);
+        }
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<usize> {
+        let address = self.addresses.remove(name)?;
+        self.names.remove(&address);
+        Some(address)
+    }
+
+    pub fn name(&self, address: usize) -> Option<&str> {
+        self.names.get(&address).map(|name| name.as_str())
+    }
+
+    pub fn address(&self, name: &str) -> Option<usize> {
+        self.addresses.get(name).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Entries ordered by address.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.names
+            .iter()
+            .map(|(address, name)| (*address, name.as_str()))
+    }
+
+    /// The name of a data address, or the address itself.
+    pub fn describe(&self, address: i128) -> String {
+        match usize::try_from(address).ok().and_then(|a| self.name(a)) {
+            Some(name) => name.to_string(),
+            None => address.to_string(),
+        }
+    }
+
+    /// A code address relative to the closest name at or before it.
+    pub fn describe_code(&self, address: usize) -> String {
+        match self.names.range(..=address).next_back() {
+            Some((base, name)) if *base == address => name.clone(),
+            Some((base, name)) => format!("{}+{}", name, address - base),
+            None => address.to_string(),
+        }
+    }
+
+    /// Formats `error` like its `Display` implementation, with names for its addresses.
+    pub fn describe_error(&self, error: &MachineError) -> String {
+        struct Described<'a>(&'a SymbolMap, &'a MachineError);
+
+        impl fmt::Display for Described<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.1
+                    .write_with(f, &|pc| self.0.describe_code(pc), &|address| {
+                        self.0.describe(address)
+                    })
+            }
+        }
+
+        Described(self, error).to_string()
+    }
+
+    /// The symbols of an [`Image`](crate::image::Image), skipping names which are not valid in
+    /// the text format.
+    pub fn from_symbols(symbols: &[Symbol]) -> SymbolMap {
+        let mut map = SymbolMap::new();
+        for symbol in symbols.iter().filter(|symbol| is_valid_name(&symbol.name)) {
+            map.insert(&symbol.name, symbol.address);
+        }
+        map
+    }
+
+    /// The entries as symbols of an [`Image`](crate::image::Image).
+    pub fn to_symbols(&self) -> Vec<Symbol> {
+        self.iter()
+            .map(|(address, name)| Symbol {
+                name: name.to_string(),
+                address,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for SymbolMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, name) in self.iter() {
+            writeln!(f, "{} = {}", name, address)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::disassemble_with;
+    use crate::Machine;
+
+    #[test]
+    fn test_parse() {
+        let map =
+            SymbolMap::parse("# day 13\n\nmain_loop = 120\nscore=386  # drawn at -1, 0\n").unwrap();
+        assert_eq!(map.address("score"), Some(386));
+        assert_eq!(map.name(120), Some("main_loop"));
+        assert_eq!(map.to_string(), "main_loop = 120\nscore = 386\n");
+        assert_eq!(SymbolMap::parse(&map.to_string()).unwrap(), map);
+        assert_eq!(SymbolMap::from_symbols(&map.to_symbols()), map);
+
+        assert!(matches!(
+            SymbolMap::parse("a = 1\nb 2"),
+            Err(SymbolError::Syntax { line: 2 })
+        ));
+        assert!(matches!(
+            SymbolMap::parse("1a = 1"),
+            Err(SymbolError::InvalidName { line: 1, .. })
+        ));
+        assert!(matches!(
+            SymbolMap::parse("a = 1\nb = 1"),
+            Err(SymbolError::Duplicate { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut map = SymbolMap::new();
+        map.insert("main", 0);
+        map.insert("counter", 9);
+        assert_eq!(map.describe(9), "counter");
+        assert_eq!(map.describe(10), "10");
+        assert_eq!(map.describe_code(4), "main+4");
+
+        let code = Machine::parse_code("1001,9,-1,9,1005,9,0,99,0,3");
+        assert_eq!(
+            disassemble_with(&code, &map),
+            "main:\n     0: add [counter], -1, [counter]\n     4: jt [counter], main\n     \
+             7: hlt\n     8: data 0\ncounter:\n     9: data 3\n"
+        );
+
+        let mut machine = Machine::new(Machine::parse_code("1101,1,1,-4,99"));
+        let error = machine.try_step().unwrap_err();
+        map.insert("adder", 0);
+        assert_eq!(map.describe_error(&error), "negativ address -4 at adder");
+    }
+}
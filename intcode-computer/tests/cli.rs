@@ -0,0 +1,108 @@
+//! Runs programs with the `intcode` binary, alone and chained through a pipe.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Output, Stdio};
+
+/// Reads two values and outputs their sum.
+const ADD: &str = "3,11,3,12,1,11,12,13,4,13,99,0,0,0";
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("intcode-cli-{}-{}", process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn intcode(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_intcode"));
+    command.args(args).stdin(Stdio::null());
+    command
+}
+
+fn text(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).unwrap()
+}
+
+fn run(args: &[&str]) -> Output {
+    intcode(args).output().unwrap()
+}
+
+#[test]
+fn test_input_and_poke() {
+    // outputs its input plus the value at 10
+    let program = temp_file("poke.txt", "3,9,1,9,10,9,4,9,99,0,0");
+    let program = program.to_str().unwrap();
+    let output = run(&["run", program, "--input", "2", "--poke", "10=40"]);
+    assert!(output.status.success());
+    assert_eq!(text(&output.stdout), "42\n");
+
+    // further inputs come from stdin
+    let add = temp_file("add.txt", ADD);
+    let output = intcode(&["run", add.to_str().unwrap(), "--input", "5"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(b"-7\n")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(text(&output.stdout), "-2\n");
+}
+
+#[test]
+fn test_csv() {
+    let program = temp_file("csv.txt", "104,1,104,-2,104,3,99");
+    let output = run(&["run", program.to_str().unwrap(), "--format", "csv"]);
+    assert!(output.status.success());
+    assert_eq!(text(&output.stdout), "1,-2,3\n");
+
+    let program = temp_file("silent.txt", "99");
+    let output = run(&["run", program.to_str().unwrap(), "--format", "csv"]);
+    assert!(output.status.success());
+    assert_eq!(text(&output.stdout), "");
+}
+
+#[test]
+fn test_pipe() {
+    let first = temp_file("first.txt", "104,20,104,22,99");
+    let second = temp_file("second.txt", ADD);
+    let mut producer = intcode(&["run", first.to_str().unwrap(), "--format", "csv"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let output = intcode(&["run", second.to_str().unwrap()])
+        .stdin(producer.stdout.take().unwrap())
+        .output()
+        .unwrap();
+    assert!(producer.wait().unwrap().success());
+    assert!(output.status.success());
+    assert_eq!(text(&output.stdout), "42\n");
+}
+
+#[test]
+fn test_errors() {
+    let program = temp_file("negative.txt", "104,7,1,-1,0,0,99");
+    let output = run(&["run", program.to_str().unwrap(), "--format", "csv"]);
+    assert_eq!(output.status.code(), Some(1));
+    // outputs before the error are passed on
+    assert_eq!(text(&output.stdout), "7\n");
+    assert!(text(&output.stderr).contains("intcode: negativ address -1 at 2"));
+    // calls are only tracked for a backtrace when asked for
+    assert!(!text(&output.stderr).contains("#0"));
+    let output = run(&["run", program.to_str().unwrap(), "--backtrace"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(text(&output.stderr).starts_with("#0 2 in 0\n"));
+
+    let add = temp_file("exhausted.txt", ADD);
+    let output = run(&["run", add.to_str().unwrap(), "--input", "1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(text(&output.stderr).contains("program needs input but stdin is exhausted"));
+
+    let output = run(&["run", "missing.txt", "--bogus"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(text(&output.stderr).starts_with("intcode: unknown argument --bogus"));
+}
@@ -0,0 +1,260 @@
+//! Drives `intcode-dap` like an editor would, over stdio and over TCP.
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+
+/// Main at 0 calls f at 10, which calls g at 24. g reads a value to 50, outputs it and returns.
+const PROGRAM: &str = "109,100,21101,9,0,0,1105,1,10,99,\
+                       109,2,21101,19,0,0,1105,1,24,109,-2,2106,0,0,\
+                       109,3,3,50,4,50,109,-3,2106,0,0";
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("intcode-dap-{}-{}", process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+/// A client which checks messages by their text, the server writes JSON without spaces.
+struct Client<R: BufRead, W: Write> {
+    reader: R,
+    writer: W,
+    seq: u64,
+    /// Events received while waiting for a response.
+    events: Vec<String>,
+}
+
+impl<R: BufRead, W: Write> Client<R, W> {
+    fn new(reader: R, writer: W) -> Self {
+        Client {
+            reader,
+            writer,
+            seq: 0,
+            events: vec![],
+        }
+    }
+
+    fn receive(&mut self) -> String {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            assert!(
+                self.reader.read_line(&mut line).unwrap() > 0,
+                "server closed"
+            );
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            length = line["Content-Length: ".len()..].parse().unwrap();
+        }
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    /// Sends a request and returns its response, keeping the events before it.
+    fn request(&mut self, command: &str, arguments: &str) -> String {
+        self.seq += 1;
+        let text = format!(
+            r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#,
+            self.seq, command, arguments
+        );
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            text.len(),
+            text
+        )
+        .unwrap();
+        self.writer.flush().unwrap();
+        let response = format!(r#""request_seq":{},"#, self.seq);
+        loop {
+            let message = self.receive();
+            if message.contains(&response) {
+                return message;
+            }
+            self.events.push(message);
+        }
+    }
+
+    /// Returns the next event named `event`, dropping the events before it.
+    fn event(&mut self, event: &str) -> String {
+        let name = format!(r#""event":"{}""#, event);
+        loop {
+            let message = if self.events.is_empty() {
+                self.receive()
+            } else {
+                self.events.remove(0)
+            };
+            if message.contains(&name) {
+                return message;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_debug_session() {
+    let program = temp_file("program.txt", PROGRAM);
+    let symbols = temp_file("program.map", "main = 0\nf = 10\ng = 24\n");
+    let mut server = Command::new(env!("CARGO_BIN_EXE_intcode-dap"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut client = Client::new(
+        BufReader::new(server.stdout.take().unwrap()),
+        server.stdin.take().unwrap(),
+    );
+
+    let response = client.request("initialize", r#"{"adapterID":"intcode"}"#);
+    assert!(response.contains(r#""supportsInstructionBreakpoints":true"#));
+    client.event("initialized");
+    let response = client.request(
+        "launch",
+        &format!(
+            r#"{{"program":{:?},"symbols":{:?},"input":[]}}"#,
+            program.to_str().unwrap(),
+            symbols.to_str().unwrap()
+        ),
+    );
+    assert!(response.contains(r#""success":true"#));
+    let response = client.request(
+        "setInstructionBreakpoints",
+        r#"{"breakpoints":[{"instructionReference":"g"},{"instructionReference":"x"}]}"#,
+    );
+    assert!(
+        response.contains(r#"[{"verified":true,"instructionReference":"24"},{"verified":false,"#)
+    );
+    client.request("configurationDone", "{}");
+    let stopped = client.event("stopped");
+    assert!(stopped.contains(r#""reason":"instruction breakpoint""#));
+
+    let response = client.request("stackTrace", r#"{"threadId":1}"#);
+    for (name, address) in &[("g", "24"), ("f", "16"), ("main", "6")] {
+        assert!(response.contains(&format!(
+            r#""name":"{}","line":0,"column":0,"instructionPointerReference":"{}""#,
+            name, address
+        )));
+    }
+    let response = client.request("scopes", r#"{"frameId":0}"#);
+    assert!(response.contains(r#""name":"Registers""#));
+    let response = client.request("variables", r#"{"variablesReference":1}"#);
+    assert!(response.contains(r#""name":"pc","value":"24""#));
+    assert!(response.contains(r#""name":"relative_base","value":"102""#));
+    let response = client.request(
+        "variables",
+        r#"{"variablesReference":2,"start":9,"count":2}"#,
+    );
+    assert!(response.contains(r#"[{"name":"9","value":"99","variablesReference":0},{"name":"f","#));
+    let response = client.request(
+        "disassemble",
+        r#"{"memoryReference":"24","instructionOffset":-1,"instructionCount":2}"#,
+    );
+    assert!(response.contains(
+        r#"[{"address":"21","instruction":"jf 0, [rb+0]"},{"address":"24","instruction":"arb 3","symbol":"g"}]"#
+    ));
+
+    client.request("next", r#"{"threadId":1}"#);
+    assert!(client.event("stopped").contains(r#""reason":"step""#));
+    let response = client.request("evaluate", r#"{"expression":"pc"}"#);
+    assert!(response.contains(r#""result":"26""#));
+
+    client.request("continue", r#"{"threadId":1}"#);
+    assert!(client
+        .event("stopped")
+        .contains(r#""description":"Waiting for input""#));
+    let response = client.request("evaluate", r#"{"expression":"input 42"}"#);
+    assert!(response.contains(r#""result":"1 inputs queued""#));
+    client.request("stepOut", r#"{"threadId":1}"#);
+    assert!(client.event("output").contains(r#""output":"42\n""#));
+    assert!(client.event("stopped").contains(r#""reason":"step""#));
+    let response = client.request("evaluate", r#"{"expression":"pc"}"#);
+    assert!(response.contains(r#""result":"19""#));
+    let response = client.request("evaluate", r#"{"expression":"[50]"}"#);
+    assert!(response.contains(r#""result":"42""#));
+
+    client.request("continue", r#"{"threadId":1}"#);
+    assert!(client.event("exited").contains(r#""exitCode":0"#));
+    client.event("terminated");
+    let response = client.request("continue", r#"{"threadId":1}"#);
+    assert!(response.contains(r#""success":false"#));
+    client.request("disconnect", "{}");
+    assert!(server.wait().unwrap().success());
+    fs::remove_file(program).unwrap();
+    fs::remove_file(symbols).unwrap();
+}
+
+#[test]
+fn test_pause() {
+    // counts up forever
+    let program = temp_file("counter.txt", "1001,7,1,7,1105,1,0,0");
+    let mut server = Command::new(env!("CARGO_BIN_EXE_intcode-dap"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut client = Client::new(
+        BufReader::new(server.stdout.take().unwrap()),
+        server.stdin.take().unwrap(),
+    );
+
+    client.request("initialize", "{}");
+    client.request(
+        "launch",
+        &format!(r#"{{"program":{:?}}}"#, program.to_str().unwrap()),
+    );
+    client.request("configurationDone", "{}");
+    let response = client.request("next", r#"{"threadId":1}"#);
+    assert!(response.contains(r#""message":"the program is running""#));
+    let response = client.request("pause", r#"{"threadId":1}"#);
+    assert!(response.contains(r#""success":true"#));
+    assert!(client.event("stopped").contains(r#""reason":"pause""#));
+    let response = client.request("pause", r#"{"threadId":1}"#);
+    assert!(response.contains(r#""message":"the program is not running""#));
+
+    client.request("continue", r#"{"threadId":1}"#);
+    client.request("disconnect", "{}");
+    assert!(server.wait().unwrap().success());
+    fs::remove_file(program).unwrap();
+}
+
+#[test]
+fn test_tcp() {
+    let program = temp_file("echo.txt", "3,0,4,0,99");
+    let mut server = Command::new(env!("CARGO_BIN_EXE_intcode-dap"))
+        .args(["--port", "0"])
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(server.stderr.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let address = line.trim().strip_prefix("listening on ").unwrap();
+    let stream = TcpStream::connect(address).unwrap();
+    let mut client = Client::new(BufReader::new(stream.try_clone().unwrap()), stream);
+
+    let response = client.request("evaluate", r#"{"expression":"pc"}"#);
+    assert!(response.contains(r#""success":false,"message":"no program launched""#));
+    client.request("initialize", "{}");
+    client.request(
+        "launch",
+        &format!(
+            r#"{{"program":{:?},"input":[7],"stopOnEntry":true}}"#,
+            program.to_str().unwrap()
+        ),
+    );
+    client.request("configurationDone", "{}");
+    assert!(client.event("stopped").contains(r#""reason":"entry""#));
+    client.request("continue", r#"{"threadId":1}"#);
+    assert!(client.event("output").contains(r#""output":"7\n""#));
+    client.event("terminated");
+    client.request("disconnect", "{}");
+    assert!(server.wait().unwrap().success());
+    fs::remove_file(program).unwrap();
+}
@@ -0,0 +1,73 @@
+//! Drives `intcode-server` from several connections and shuts it down.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(address: &str) -> Client {
+        let stream = TcpStream::connect(address).unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    fn request(&mut self, line: &str) -> String {
+        writeln!(self.writer, "{}", line).unwrap();
+        let mut response = String::new();
+        self.reader.read_line(&mut response).unwrap();
+        response.trim_end().to_string()
+    }
+}
+
+#[test]
+fn test_sessions_and_shutdown() {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_intcode-server"))
+        .args(["--limit", "1000", "--memory", "10"])
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(server.stderr.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let address = line.trim().strip_prefix("listening on ").unwrap();
+    let mut first = Client::connect(address);
+    let mut second = Client::connect(address);
+
+    // doubles its inputs
+    assert_eq!(
+        first.request("load 3,11,1002,11,2,11,4,11,1105,1,0,0"),
+        "ok 1"
+    );
+    assert_eq!(first.request("input 1 21"), "ok");
+    assert_eq!(second.request("run 1"), "ok blocked");
+    assert_eq!(first.request("snapshot 1"), "ok 2");
+    assert_eq!(second.request("output 2"), "ok 42");
+    assert_eq!(first.request("output 1"), "ok 42");
+    assert_eq!(second.request("kill 2"), "ok");
+    assert_eq!(second.request("output 2"), "error unknown session 2");
+
+    assert_eq!(second.request("load 1105,1,0"), "ok 3");
+    assert_eq!(second.request("run 3"), "ok limit");
+    assert_eq!(second.request("load 1105,1,0 100000"), "ok 4");
+    assert_eq!(second.request("run 4"), "ok limit");
+    assert_eq!(second.request("reset 4"), "error unknown request reset");
+    // writes 7 to ever new cells behind the program
+    assert_eq!(second.request("load 109,1,21101,7,0,1000,1105,1,0"), "ok 5");
+    assert_eq!(
+        second.request("run 5"),
+        "error memory limit exceeded writing 1011 at 2"
+    );
+
+    assert_eq!(first.request("shutdown"), "ok");
+    assert!(server.wait().unwrap().success());
+    // the server closed the other connection
+    assert_eq!(second.reader.read_line(&mut line).unwrap(), 0);
+}